@@ -0,0 +1,481 @@
+//! The subset of ASN.1 BER that SNMP messages use: integers, octet
+//! strings, OIDs, the SMI application types, the PDUs and the v1/v2c and
+//! v3 envelopes.
+
+use std::ops::Range;
+
+use anyhow::{bail, ensure, Result};
+
+pub type Oid = Vec<u32>;
+
+pub const TAG_INTEGER: u8 = 0x02;
+pub const TAG_OCTET_STRING: u8 = 0x04;
+pub const TAG_NULL: u8 = 0x05;
+pub const TAG_OID: u8 = 0x06;
+pub const TAG_SEQUENCE: u8 = 0x30;
+pub const TAG_COUNTER32: u8 = 0x41;
+pub const TAG_GAUGE32: u8 = 0x42;
+pub const TAG_TIMETICKS: u8 = 0x43;
+pub const TAG_NO_SUCH_OBJECT: u8 = 0x80;
+pub const TAG_NO_SUCH_INSTANCE: u8 = 0x81;
+pub const TAG_END_OF_MIB_VIEW: u8 = 0x82;
+
+pub const PDU_GET: u8 = 0xA0;
+pub const PDU_GET_NEXT: u8 = 0xA1;
+pub const PDU_RESPONSE: u8 = 0xA2;
+pub const PDU_SET: u8 = 0xA3;
+pub const PDU_GET_BULK: u8 = 0xA5;
+pub const PDU_TRAP_V2: u8 = 0xA7;
+pub const PDU_REPORT: u8 = 0xA8;
+
+// SNMPv3 msgFlags.
+pub const FLAG_AUTH: u8 = 0x01;
+pub const FLAG_PRIV: u8 = 0x02;
+pub const FLAG_REPORTABLE: u8 = 0x04;
+
+/// `msgSecurityModel` for the User-based Security Model.
+pub const SECURITY_MODEL_USM: i64 = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    OctetString(Vec<u8>),
+    Null,
+    Oid(Oid),
+    Counter32(u32),
+    Gauge32(u32),
+    TimeTicks(u32),
+    NoSuchObject,
+    NoSuchInstance,
+    EndOfMibView,
+}
+
+impl Value {
+    pub fn string(text: &str) -> Self {
+        Self::OctetString(text.as_bytes().to_vec())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pdu {
+    pub kind: u8,
+    pub request_id: i32,
+    /// `non-repeaters` in a GetBulk.
+    pub error_status: i64,
+    /// `max-repetitions` in a GetBulk.
+    pub error_index: i64,
+    pub varbinds: Vec<(Oid, Value)>,
+}
+
+/// `version` is 0 for v1 and 1 for v2c.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub version: i64,
+    pub community: Vec<u8>,
+    pub pdu: Pdu,
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut message = Vec::new();
+        put_integer(&mut message, TAG_INTEGER, self.version);
+        put_tlv(&mut message, TAG_OCTET_STRING, &self.community);
+        put_pdu(&mut message, &self.pdu);
+        let mut out = Vec::new();
+        put_tlv(&mut out, TAG_SEQUENCE, &message);
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut outer = Reader::new(bytes);
+        let mut message = Reader::new(outer.expect(TAG_SEQUENCE)?);
+        let version = message.integer()?;
+        let community = message.expect(TAG_OCTET_STRING)?.to_vec();
+        let pdu = message.pdu()?;
+        Ok(Self {
+            version,
+            community,
+            pdu,
+        })
+    }
+}
+
+/// The `msgVersion` of any message, to pick the decoder.
+pub fn version(bytes: &[u8]) -> Result<i64> {
+    let mut outer = Reader::new(bytes);
+    Reader::new(outer.expect(TAG_SEQUENCE)?).integer()
+}
+
+/// A PDU with the context it applies to (RFC 3412).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScopedPdu {
+    pub context_engine_id: Vec<u8>,
+    pub context_name: Vec<u8>,
+    pub pdu: Pdu,
+}
+
+impl ScopedPdu {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut scoped = Vec::new();
+        put_tlv(&mut scoped, TAG_OCTET_STRING, &self.context_engine_id);
+        put_tlv(&mut scoped, TAG_OCTET_STRING, &self.context_name);
+        put_pdu(&mut scoped, &self.pdu);
+        let mut out = Vec::new();
+        put_tlv(&mut out, TAG_SEQUENCE, &scoped);
+        out
+    }
+
+    /// Bytes after the sequence are ignored, as a decrypted PDU may carry
+    /// padding.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut outer = Reader::new(bytes);
+        let mut scoped = Reader::new(outer.expect(TAG_SEQUENCE)?);
+        Ok(Self {
+            context_engine_id: scoped.expect(TAG_OCTET_STRING)?.to_vec(),
+            context_name: scoped.expect(TAG_OCTET_STRING)?.to_vec(),
+            pdu: scoped.pdu()?,
+        })
+    }
+}
+
+/// `UsmSecurityParameters` (RFC 3414).
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct UsmParams {
+    pub engine_id: Vec<u8>,
+    pub boots: i64,
+    pub time: i64,
+    pub user: Vec<u8>,
+    pub auth: Vec<u8>,
+    pub privacy: Vec<u8>,
+}
+
+/// The scoped PDU, in the clear or encrypted when `FLAG_PRIV` is set.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScopedData {
+    Plain(ScopedPdu),
+    Encrypted(Vec<u8>),
+}
+
+/// An SNMPv3 message with USM security parameters; the version is 3.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageV3 {
+    pub msg_id: i64,
+    pub max_size: i64,
+    pub flags: u8,
+    pub usm: UsmParams,
+    pub data: ScopedData,
+}
+
+impl MessageV3 {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut global = Vec::new();
+        put_integer(&mut global, TAG_INTEGER, self.msg_id);
+        put_integer(&mut global, TAG_INTEGER, self.max_size);
+        put_tlv(&mut global, TAG_OCTET_STRING, &[self.flags]);
+        put_integer(&mut global, TAG_INTEGER, SECURITY_MODEL_USM);
+
+        let usm = &self.usm;
+        let mut params = Vec::new();
+        put_tlv(&mut params, TAG_OCTET_STRING, &usm.engine_id);
+        put_integer(&mut params, TAG_INTEGER, usm.boots);
+        put_integer(&mut params, TAG_INTEGER, usm.time);
+        put_tlv(&mut params, TAG_OCTET_STRING, &usm.user);
+        put_tlv(&mut params, TAG_OCTET_STRING, &usm.auth);
+        put_tlv(&mut params, TAG_OCTET_STRING, &usm.privacy);
+        let mut security = Vec::new();
+        put_tlv(&mut security, TAG_SEQUENCE, &params);
+
+        let mut message = Vec::new();
+        put_integer(&mut message, TAG_INTEGER, 3);
+        put_tlv(&mut message, TAG_SEQUENCE, &global);
+        put_tlv(&mut message, TAG_OCTET_STRING, &security);
+        match &self.data {
+            ScopedData::Plain(scoped) => message.extend(scoped.encode()),
+            ScopedData::Encrypted(bytes) => put_tlv(&mut message, TAG_OCTET_STRING, bytes),
+        }
+        let mut out = Vec::new();
+        put_tlv(&mut out, TAG_SEQUENCE, &message);
+        out
+    }
+
+    /// Fails on other versions and security models.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let (mut message, mut global, mut params) = v3_readers(bytes)?;
+        let msg_id = global.integer()?;
+        let max_size = global.integer()?;
+        let flags = match global.expect(TAG_OCTET_STRING)? {
+            [flags] => *flags,
+            other => bail!("msgFlags of {} bytes", other.len()),
+        };
+        let usm = UsmParams {
+            engine_id: params.expect(TAG_OCTET_STRING)?.to_vec(),
+            boots: params.integer()?,
+            time: params.integer()?,
+            user: params.expect(TAG_OCTET_STRING)?.to_vec(),
+            auth: params.expect(TAG_OCTET_STRING)?.to_vec(),
+            privacy: params.expect(TAG_OCTET_STRING)?.to_vec(),
+        };
+        let data = if flags & FLAG_PRIV != 0 {
+            ScopedData::Encrypted(message.expect(TAG_OCTET_STRING)?.to_vec())
+        } else {
+            ScopedData::Plain(ScopedPdu::decode(message.expect_raw(TAG_SEQUENCE)?)?)
+        };
+        Ok(Self {
+            msg_id,
+            max_size,
+            flags,
+            usm,
+            data,
+        })
+    }
+
+    /// Where `msgAuthenticationParameters` sits in an encoded message: the
+    /// digest covers the whole message with these bytes zeroed.
+    pub fn auth_range(bytes: &[u8]) -> Result<Range<usize>> {
+        let (_, _, mut params) = v3_readers(bytes)?;
+        params.expect(TAG_OCTET_STRING)?;
+        params.integer()?;
+        params.integer()?;
+        params.expect(TAG_OCTET_STRING)?;
+        let auth = params.expect(TAG_OCTET_STRING)?;
+        // Every slice the readers hand out borrows from `bytes`.
+        let start = auth.as_ptr() as usize - bytes.as_ptr() as usize;
+        Ok(start..start + auth.len())
+    }
+}
+
+/// Readers for the rest of a v3 message, its header data and its USM
+/// parameters, each positioned at their first field.
+fn v3_readers(bytes: &[u8]) -> Result<(Reader<'_>, Reader<'_>, Reader<'_>)> {
+    let mut outer = Reader::new(bytes);
+    let mut message = Reader::new(outer.expect(TAG_SEQUENCE)?);
+    let version = message.integer()?;
+    ensure!(version == 3, "not an SNMPv3 message: version {version}");
+    let global = Reader::new(message.expect(TAG_SEQUENCE)?);
+    // The model sits after the flags; peek at it before decoding the rest.
+    let mut header = Reader::new(global.buf);
+    for _ in 0..3 {
+        header.tlv()?;
+    }
+    let model = header.integer()?;
+    ensure!(
+        model == SECURITY_MODEL_USM,
+        "unsupported security model {model}"
+    );
+    let mut security = Reader::new(message.expect(TAG_OCTET_STRING)?);
+    let params = Reader::new(security.expect(TAG_SEQUENCE)?);
+    Ok((message, global, params))
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    fn tlv(&mut self) -> Result<(u8, &'a [u8])> {
+        ensure!(self.buf.len() >= 2, "truncated header");
+        let tag = self.buf[0];
+        let (len, header) = match self.buf[1] {
+            short @ 0..=0x7F => (short as usize, 2),
+            long => {
+                let count = (long & 0x7F) as usize;
+                ensure!(
+                    (1..=4).contains(&count),
+                    "unsupported length of {count} bytes"
+                );
+                ensure!(self.buf.len() >= 2 + count, "truncated length");
+                let len = self.buf[2..2 + count]
+                    .iter()
+                    .fold(0usize, |len, b| len << 8 | *b as usize);
+                (len, 2 + count)
+            }
+        };
+        ensure!(self.buf.len() - header >= len, "truncated value");
+        let content = &self.buf[header..header + len];
+        self.buf = &self.buf[header + len..];
+        Ok((tag, content))
+    }
+
+    fn expect(&mut self, want: u8) -> Result<&'a [u8]> {
+        let (tag, content) = self.tlv()?;
+        ensure!(tag == want, "expected tag {want:#04x}, got {tag:#04x}");
+        Ok(content)
+    }
+
+    /// Like [`Reader::expect`], but with the tag and length still on.
+    fn expect_raw(&mut self, want: u8) -> Result<&'a [u8]> {
+        let start = self.buf;
+        self.expect(want)?;
+        Ok(&start[..start.len() - self.buf.len()])
+    }
+
+    fn integer(&mut self) -> Result<i64> {
+        decode_integer(self.expect(TAG_INTEGER)?)
+    }
+
+    fn pdu(&mut self) -> Result<Pdu> {
+        let (kind, body) = self.tlv()?;
+        let mut body = Reader::new(body);
+        let request_id = i32::try_from(body.integer()?)?;
+        let error_status = body.integer()?;
+        let error_index = body.integer()?;
+        let mut list = Reader::new(body.expect(TAG_SEQUENCE)?);
+        let mut varbinds = Vec::new();
+        while !list.is_empty() {
+            let mut varbind = Reader::new(list.expect(TAG_SEQUENCE)?);
+            let oid = decode_oid(varbind.expect(TAG_OID)?)?;
+            varbinds.push((oid, varbind.value()?));
+        }
+        Ok(Pdu {
+            kind,
+            request_id,
+            error_status,
+            error_index,
+            varbinds,
+        })
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        let (tag, content) = self.tlv()?;
+        let unsigned =
+            |content: &[u8]| -> Result<u32> { Ok(u32::try_from(decode_integer(content)?)?) };
+        Ok(match tag {
+            TAG_INTEGER => Value::Integer(decode_integer(content)?),
+            TAG_OCTET_STRING => Value::OctetString(content.to_vec()),
+            TAG_NULL => Value::Null,
+            TAG_OID => Value::Oid(decode_oid(content)?),
+            TAG_COUNTER32 => Value::Counter32(unsigned(content)?),
+            TAG_GAUGE32 => Value::Gauge32(unsigned(content)?),
+            TAG_TIMETICKS => Value::TimeTicks(unsigned(content)?),
+            TAG_NO_SUCH_OBJECT => Value::NoSuchObject,
+            TAG_NO_SUCH_INSTANCE => Value::NoSuchInstance,
+            TAG_END_OF_MIB_VIEW => Value::EndOfMibView,
+            other => bail!("unsupported value tag {other:#04x}"),
+        })
+    }
+}
+
+fn decode_integer(content: &[u8]) -> Result<i64> {
+    ensure!(
+        !content.is_empty() && content.len() <= 8,
+        "integer of {} bytes",
+        content.len()
+    );
+    // Sign-extend from the first byte.
+    let first = content[0] as i8 as i64;
+    Ok(content[1..]
+        .iter()
+        .fold(first, |value, b| value << 8 | *b as i64))
+}
+
+fn decode_oid(content: &[u8]) -> Result<Oid> {
+    ensure!(!content.is_empty(), "empty oid");
+    let mut arcs = Vec::new();
+    let mut arc: u32 = 0;
+    for (i, b) in content.iter().enumerate() {
+        ensure!(arc < 1 << 25, "oid arc too large");
+        arc = arc << 7 | (b & 0x7F) as u32;
+        if b & 0x80 == 0 {
+            if arcs.is_empty() {
+                let first = (arc / 40).min(2);
+                arcs.extend([first, arc - first * 40]);
+            } else {
+                arcs.push(arc);
+            }
+            arc = 0;
+        } else {
+            ensure!(i + 1 < content.len(), "truncated oid");
+        }
+    }
+    Ok(arcs)
+}
+
+fn put_pdu(out: &mut Vec<u8>, pdu: &Pdu) {
+    let mut varbinds = Vec::new();
+    for (oid, value) in &pdu.varbinds {
+        let mut varbind = Vec::new();
+        put_oid(&mut varbind, oid);
+        put_value(&mut varbind, value);
+        put_tlv(&mut varbinds, TAG_SEQUENCE, &varbind);
+    }
+    let mut body = Vec::new();
+    put_integer(&mut body, TAG_INTEGER, pdu.request_id.into());
+    put_integer(&mut body, TAG_INTEGER, pdu.error_status);
+    put_integer(&mut body, TAG_INTEGER, pdu.error_index);
+    put_tlv(&mut body, TAG_SEQUENCE, &varbinds);
+    put_tlv(out, pdu.kind, &body);
+}
+
+fn put_length(out: &mut Vec<u8>, len: usize) {
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|b| **b == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+}
+
+fn put_tlv(out: &mut Vec<u8>, tag: u8, content: &[u8]) {
+    out.push(tag);
+    put_length(out, content.len());
+    out.extend_from_slice(content);
+}
+
+/// Two's complement in as few bytes as keep the sign.
+fn put_integer(out: &mut Vec<u8>, tag: u8, value: i64) {
+    let bytes = value.to_be_bytes();
+    let mut start = 0;
+    while start < 7 {
+        let (b, next) = (bytes[start], bytes[start + 1]);
+        if (b == 0 && next & 0x80 == 0) || (b == 0xFF && next & 0x80 != 0) {
+            start += 1;
+        } else {
+            break;
+        }
+    }
+    put_tlv(out, tag, &bytes[start..]);
+}
+
+fn put_oid(out: &mut Vec<u8>, oid: &[u32]) {
+    let mut content = Vec::new();
+    let (first, rest) = match oid {
+        [a, b, rest @ ..] => (a * 40 + b, rest),
+        [a] => (a * 40, &[][..]),
+        [] => (0, &[][..]),
+    };
+    for arc in std::iter::once(first).chain(rest.iter().copied()) {
+        let mut groups = vec![(arc & 0x7F) as u8];
+        let mut arc = arc >> 7;
+        while arc > 0 {
+            groups.push(0x80 | (arc & 0x7F) as u8);
+            arc >>= 7;
+        }
+        content.extend(groups.iter().rev());
+    }
+    put_tlv(out, TAG_OID, &content);
+}
+
+fn put_value(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Integer(v) => put_integer(out, TAG_INTEGER, *v),
+        Value::OctetString(bytes) => put_tlv(out, TAG_OCTET_STRING, bytes),
+        Value::Null => put_tlv(out, TAG_NULL, &[]),
+        Value::Oid(oid) => put_oid(out, oid),
+        Value::Counter32(v) => put_integer(out, TAG_COUNTER32, (*v).into()),
+        Value::Gauge32(v) => put_integer(out, TAG_GAUGE32, (*v).into()),
+        Value::TimeTicks(v) => put_integer(out, TAG_TIMETICKS, (*v).into()),
+        Value::NoSuchObject => put_tlv(out, TAG_NO_SUCH_OBJECT, &[]),
+        Value::NoSuchInstance => put_tlv(out, TAG_NO_SUCH_INSTANCE, &[]),
+        Value::EndOfMibView => put_tlv(out, TAG_END_OF_MIB_VIEW, &[]),
+    }
+}
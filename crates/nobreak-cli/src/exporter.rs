@@ -5,24 +5,20 @@ use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::Result;
 use chrono::{DateTime, Days, NaiveDate, Utc};
-use nobreak_core::{Monitor, Snapshot, UpsDriver};
-use tokio::time::sleep;
+use nobreak_core::{PowerQualityDay, Snapshot, SnapshotFeed};
+use tokio_stream::StreamExt;
 
-pub async fn run_exporter<D: UpsDriver>(
-    monitor: &mut Monitor<D>,
-    output_dir: &str,
-    retention_days: u64,
-) -> Result<()> {
+pub async fn run_exporter(feed: SnapshotFeed, output_dir: &str, retention_days: u64) -> Result<()> {
     let out_dir = PathBuf::from(output_dir);
     fs::create_dir_all(&out_dir)?;
 
     let mut state = ExportState::new(out_dir, retention_days)?;
+    let mut snapshots = feed.stream();
 
     loop {
         tokio::select! {
             _ = tokio::signal::ctrl_c() => break,
-            _ = sleep(monitor.effective_interval()) => {
-                let snapshot = monitor.tick().await;
+            Some(snapshot) = snapshots.next() => {
                 state.write_snapshot(&snapshot)?;
                 state.maybe_prune()?;
             }
@@ -83,6 +79,10 @@ impl ExportState {
             "connected": snapshot.device.connected,
             "freshness": snapshot.freshness,
             "status": snapshot.status,
+            "quality": snapshot.quality,
+            "events": snapshot.events,
+            "alerts": snapshot.alerts,
+            "daily_report": snapshot.daily_report,
             "metrics": {
                 "vInput": snapshot.vars.get("vInput").cloned(),
                 "vOutput": snapshot.vars.get("vOutput").cloned(),
@@ -90,15 +90,36 @@ impl ExportState {
                 "pOutput": snapshot.vars.get("pOutput").cloned(),
                 "vBattery": snapshot.vars.get("vBattery").cloned(),
                 "cBattery": snapshot.vars.get("cBattery").cloned(),
-                "temperature": snapshot.vars.get("temperature").cloned()
+                "temperature": snapshot.vars.get("temperature").cloned(),
+                "runtimeRemainingSec": snapshot.vars.get("runtimeRemainingSec").cloned(),
+                "runtimeRemainingSecLow": snapshot.vars.get("runtimeRemainingSecLow").cloned(),
+                "runtimeRemainingSecHigh": snapshot.vars.get("runtimeRemainingSecHigh").cloned(),
+                "batteryHealthPct": snapshot.vars.get("batteryHealthPct").cloned(),
+                "batteryHealthTrendPctPerMonth": snapshot.vars.get("batteryHealthTrendPctPerMonth").cloned(),
+                "pOutputWatts": snapshot.vars.get("pOutputWatts").cloned(),
+                "energyTotalWh": snapshot.vars.get("energyTotalWh").cloned(),
+                "energyDayWh": snapshot.vars.get("energyDayWh").cloned(),
+                "energyDayCost": snapshot.vars.get("energyDayCost").cloned()
             },
+            "raw": snapshot
+                .vars
+                .iter()
+                .filter(|(key, _)| key.ends_with("Raw"))
+                .collect::<std::collections::BTreeMap<_, _>>(),
+            "vars_meta": snapshot.vars_meta,
+            "validity": snapshot.validity,
             "meta": {
                 "metricsConfidence": snapshot.vars.get("metricsConfidence").cloned(),
+                "runtimeSource": snapshot.vars.get("runtimeSource").cloned(),
                 "rawFrameHex": snapshot.vars.get("rawFrameHex").cloned(),
                 "rawFrameLen": snapshot.vars.get("rawFrameLen").cloned()
             }
         });
 
+        if let Some(report) = &snapshot.daily_report {
+            write_daily_report(&self.out_dir, snapshot, report)?;
+        }
+
         serde_json::to_writer(&mut self.writer, &exported)?;
         self.writer.write_all(b"\n")?;
         self.writer.flush()?;
@@ -121,7 +142,29 @@ impl ExportState {
     }
 }
 
-pub(crate) fn prune_old_log_files(out_dir: &Path, retention_days: u64, now: SystemTime) -> Result<()> {
+/// Writes `nobreak-report-<date>.json` for the day that just closed.
+pub(crate) fn write_daily_report(
+    out_dir: &Path,
+    snapshot: &Snapshot,
+    report: &PowerQualityDay,
+) -> Result<PathBuf> {
+    let path = out_dir.join(format!("nobreak-report-{}.json", report.date));
+    let body = serde_json::json!({
+        "date": report.date,
+        "device_id": snapshot.device.id,
+        "model": snapshot.device.model,
+        "generated_at": snapshot.ts,
+        "power_quality": report,
+    });
+    fs::write(&path, serde_json::to_vec_pretty(&body)?)?;
+    Ok(path)
+}
+
+pub(crate) fn prune_old_log_files(
+    out_dir: &Path,
+    retention_days: u64,
+    now: SystemTime,
+) -> Result<()> {
     let today = DateTime::<Utc>::from(now).date_naive();
     let cutoff = today
         .checked_sub_days(Days::new(retention_days))
@@ -160,5 +203,3 @@ pub(crate) fn prune_old_log_files(out_dir: &Path, retention_days: u64, now: Syst
 
     Ok(())
 }
-
- 
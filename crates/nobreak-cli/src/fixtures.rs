@@ -0,0 +1,197 @@
+//! Snapshots and helpers shared by the sink tests.
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use nobreak_core::{
+    DeviceInfo, DriverError, EventPhase, Monitor, MonitorConfig, MonitorService, PowerEvent,
+    PowerEventKind, ReadResult, Snapshot, UpsDriver,
+};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_stream::{Stream, StreamExt};
+
+use crate::power::{Live, PowerState};
+
+/// A healthy read on mains with the minimum var set and one quality window.
+pub fn snapshot() -> Snapshot {
+    serde_json::from_value(serde_json::json!({
+        "ts": "2026-03-01T12:00:00Z",
+        "mono_ms": 60000,
+        "device": {
+            "id": "cdc:/dev/ttyACM0",
+            "model": "RagTech 3200VA",
+            "transport": { "type": "cdc", "path": "/dev/ttyACM0", "vid": "04D8", "pid": "000A" },
+            "connected": true
+        },
+        "freshness": { "rtt_ms": 180, "age_ms": 180, "stale": false, "last_ok_ts": "2026-03-01T12:00:00Z" },
+        "status": { "code": "ONLINE_RAW", "failures": [] },
+        "vars": {
+            "vInput": 127.4,
+            "vOutput": 120.0,
+            "fOutput": 60.0,
+            "pOutput": 35.0,
+            "vBattery": 27.2,
+            "cBattery": 100.0,
+            "temperature": 38.0,
+            "runtimeRemainingSec": 1500.0,
+            "energyTotalWh": 5120.5,
+            "vInputClass": "adequate",
+            "rawFrameHex": "AA04"
+        },
+        "quality": {
+            "poll_ms": 180,
+            "stale_seconds": 0.0,
+            "reads_ok": 58,
+            "reads_err": 2,
+            "reconnects": 1,
+            "effective_interval_ms": 1000,
+            "ticks_skipped": 0,
+            "ticks_late": 0,
+            "windows": [{
+                "window_sec": 60,
+                "reads": 60,
+                "success_rate": 0.9667,
+                "rtt_p50_ms": 170,
+                "rtt_p95_ms": 240,
+                "rtt_p99_ms": 260,
+                "max_gap_ms": 2000
+            }]
+        }
+    }))
+    .expect("valid snapshot fixture")
+}
+
+/// One edge of a power condition, stamped with the fixture's time.
+pub fn event(kind: PowerEventKind, phase: EventPhase) -> PowerEvent {
+    let at = snapshot().ts;
+    PowerEvent {
+        kind,
+        phase,
+        started_at: at,
+        ended_at: (phase == EventPhase::End).then_some(at),
+        duration_ms: (phase == EventPhase::End).then_some(0),
+        value: None,
+    }
+}
+
+/// `snapshot` as the servers see it, after `power` has followed it.
+pub fn live(snapshot: Snapshot, power: &mut PowerState) -> Live {
+    power.observe(&snapshot);
+    Live {
+        snapshot,
+        power: power.clone(),
+    }
+}
+
+/// A listener on a free loopback port, and its address.
+pub async fn loopback() -> (TcpListener, SocketAddr) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("addr");
+    (listener, addr)
+}
+
+/// `snapshot` once, then a feed that stays open.
+pub fn feed(snapshot: Snapshot) -> impl Stream<Item = Snapshot> + Unpin {
+    tokio_stream::iter([snapshot]).chain(tokio_stream::pending())
+}
+
+/// Asks a server that was just started until it has taken in its first
+/// snapshot: `ask` gives `None` while the reply is still "no data yet".
+/// Fails the test after 5 s.
+pub async fn until_ready<T, F>(mut ask: impl FnMut() -> F) -> T
+where
+    F: Future<Output = Option<T>>,
+{
+    let retry = async {
+        loop {
+            if let Some(answer) = ask().await {
+                return answer;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(5), retry)
+        .await
+        .expect("server answers")
+}
+
+/// Driver that reads once per permit, so a test decides when each tick
+/// happens. Reads wait for their permit instead of timing out.
+pub struct GatedDriver {
+    permits: mpsc::UnboundedReceiver<()>,
+    connected: Option<DeviceInfo>,
+}
+
+impl GatedDriver {
+    fn device() -> DeviceInfo {
+        DeviceInfo {
+            id: "cdc:/dev/ttyACM0".to_string(),
+            model: "RagTech 3200VA".to_string(),
+            transport: "cdc".to_string(),
+            path: "/dev/ttyACM0".to_string(),
+            vid: "04d8".to_string(),
+            pid: "000a".to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl UpsDriver for GatedDriver {
+    async fn discover(&mut self) -> Result<Vec<DeviceInfo>, DriverError> {
+        Ok(vec![Self::device()])
+    }
+
+    async fn connect(&mut self, _preferred_id: Option<&str>) -> Result<DeviceInfo, DriverError> {
+        self.connected = Some(Self::device());
+        Ok(Self::device())
+    }
+
+    async fn read(&mut self) -> Result<ReadResult, DriverError> {
+        self.permits.recv().await.ok_or(DriverError::Disconnected)?;
+        Ok(ReadResult {
+            status_code: "ONLINE_RAW".to_string(),
+            failures: Vec::new(),
+            vars: [("vInput".to_string(), serde_json::json!(127.4))].into(),
+        })
+    }
+
+    async fn disconnect(&mut self) -> Result<(), DriverError> {
+        self.connected = None;
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected.is_some()
+    }
+
+    fn current_device(&self) -> Option<DeviceInfo> {
+        self.connected.clone()
+    }
+}
+
+/// A running monitor on a [`GatedDriver`], and the sender of its permits.
+pub fn gated_service() -> (MonitorService, mpsc::UnboundedSender<()>) {
+    let (permit, permits) = mpsc::unbounded_channel();
+    let hour = Duration::from_secs(3600);
+    let config = MonitorConfig {
+        sample_interval: Duration::from_millis(10),
+        sample_interval_min: Duration::from_millis(10),
+        auto_tune: false,
+        power_aware_sampling: false,
+        poll_timeout: hour,
+        stale_after: hour,
+        disconnected_after: hour,
+        ..MonitorConfig::default()
+    };
+    let driver = GatedDriver {
+        permits,
+        connected: None,
+    };
+    (
+        MonitorService::spawn(Monitor::new(driver, config, None)),
+        permit,
+    )
+}
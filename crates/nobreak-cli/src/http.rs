@@ -0,0 +1,148 @@
+//! Embedded HTTP server. Handlers only read from the [`SnapshotFeed`]; none
+//! of them can reach the device.
+
+use std::convert::Infallible;
+
+use anyhow::Result;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{FromRef, State};
+use axum::http::{header, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use nobreak_core::SnapshotFeed;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::StreamExt;
+use tracing::debug;
+
+use crate::stream::{StreamHub, StreamMessage};
+use crate::metrics;
+
+#[derive(Clone)]
+struct AppState {
+    feed: SnapshotFeed,
+    hub: StreamHub,
+}
+
+impl FromRef<AppState> for SnapshotFeed {
+    fn from_ref(state: &AppState) -> Self {
+        state.feed.clone()
+    }
+}
+
+impl FromRef<AppState> for StreamHub {
+    fn from_ref(state: &AppState) -> Self {
+        state.hub.clone()
+    }
+}
+
+pub async fn run_http(feed: SnapshotFeed, listener: TcpListener) -> Result<()> {
+    let (hub, pump) = StreamHub::new(feed.clone());
+    let app = Router::new()
+        .route("/", get(dashboard))
+        .route("/metrics", get(prometheus))
+        .route("/api/v1/snapshot", get(snapshot))
+        .route("/api/v1/stream", get(stream))
+        .with_state(AppState { feed, hub });
+    tokio::select! {
+        result = axum::serve(listener, app) => result?,
+        _ = pump => {}
+    }
+    Ok(())
+}
+
+/// Single self-contained page, compiled into the binary.
+pub(crate) const DASHBOARD: &str = include_str!("../web/index.html");
+
+async fn dashboard() -> Response {
+    ([(header::CACHE_CONTROL, "no-cache")], Html(DASHBOARD)).into_response()
+}
+
+async fn prometheus(State(feed): State<SnapshotFeed>) -> Response {
+    match feed.latest() {
+        Some(snapshot) => (
+            [(header::CONTENT_TYPE, metrics::CONTENT_TYPE)],
+            metrics::render(&snapshot),
+        )
+            .into_response(),
+        None => no_snapshot_yet(),
+    }
+}
+
+async fn snapshot(State(feed): State<SnapshotFeed>) -> Response {
+    match feed.latest() {
+        Some(snapshot) => Json(snapshot).into_response(),
+        None => no_snapshot_yet(),
+    }
+}
+
+/// WebSocket when the request asks for an upgrade, SSE otherwise.
+async fn stream(
+    State(hub): State<StreamHub>,
+    upgrade: Result<WebSocketUpgrade, axum::extract::ws::rejection::WebSocketUpgradeRejection>,
+) -> Response {
+    match upgrade {
+        Ok(upgrade) => upgrade.on_upgrade(move |socket| websocket(socket, hub)),
+        Err(_) => server_sent_events(hub).into_response(),
+    }
+}
+
+fn server_sent_events(
+    hub: StreamHub,
+) -> Sse<impl tokio_stream::Stream<Item = Result<Event, Infallible>>> {
+    let subscription = hub.subscribe();
+    let covered = subscription.covered();
+    let initial = tokio_stream::iter(subscription.initial);
+    // A lagged client skips ahead; the gap in `id` shows what it missed.
+    let rest = BroadcastStream::new(subscription.rest)
+        .filter_map(move |item| item.ok().filter(|message| message.seq > covered));
+    let events = initial.chain(rest).map(|message| Ok(sse_event(&message)));
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+fn sse_event(message: &StreamMessage) -> Event {
+    Event::default()
+        .id(message.seq.to_string())
+        .event(message.kind)
+        .data(&message.json)
+}
+
+async fn websocket(mut socket: WebSocket, hub: StreamHub) {
+    let mut subscription = hub.subscribe();
+    let covered = subscription.covered();
+    if let Some(initial) = subscription.initial.take() {
+        if socket
+            .send(Message::text(initial.json.clone()))
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+    loop {
+        tokio::select! {
+            message = subscription.rest.recv() => match message {
+                Ok(message) if message.seq > covered => {
+                    if socket.send(Message::text(message.json.clone())).await.is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                // The next message's `seq` shows the client what it missed.
+                Err(RecvError::Lagged(missed)) => debug!(missed, "websocket client lagged"),
+                Err(RecvError::Closed) => return,
+            },
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+fn no_snapshot_yet() -> Response {
+    (StatusCode::SERVICE_UNAVAILABLE, "no snapshot yet\n").into_response()
+}
@@ -0,0 +1,17 @@
+use crate::http::DASHBOARD;
+
+#[test]
+fn dashboard_is_self_contained_and_reads_the_stream() {
+    // Arrange
+    let page = DASHBOARD;
+
+    // Act
+    let external = ["http://", "https://", "//cdn", "<link", "src="]
+        .into_iter()
+        .filter(|needle| page.contains(needle))
+        .collect::<Vec<_>>();
+
+    // Assert
+    assert!(external.is_empty(), "external resources: {external:?}");
+    assert!(page.contains(r#"new EventSource("/api/v1/stream")"#));
+}
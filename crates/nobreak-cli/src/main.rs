@@ -1,17 +1,63 @@
-use std::time::Duration;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
 
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
+use clap::builder::BoolishValueParser;
 use clap::{Parser, Subcommand, ValueEnum};
-use nobreak_core::{Monitor, MonitorConfig, VendorShimDriver};
-use tokio::time::{interval_at, Instant};
+use nobreak_core::{
+    Monitor, MonitorService, PqStatsState, ProdistState, SnapshotFeed, VendorShimDriver,
+};
+use serde::Deserialize;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::task::{self, JoinSet};
+use tokio_stream::StreamExt;
 use tracing::{info, warn};
 use tracing_subscriber::EnvFilter;
 
+use crate::settings::Settings;
+
 mod viewer;
+mod ber;
 mod exporter;
+mod http;
+mod metrics;
+mod modbus;
+mod mqtt;
+mod net;
+mod nis;
+mod nut;
+mod power;
+mod settings;
+mod snmp;
+mod stream;
+mod usm;
 #[cfg(test)]
 mod exporter_tests;
+#[cfg(test)]
+mod fixtures;
+#[cfg(test)]
+mod http_tests;
+#[cfg(test)]
+mod metrics_tests;
+#[cfg(test)]
+mod modbus_tests;
+#[cfg(test)]
+mod mqtt_tests;
+#[cfg(test)]
+mod nis_tests;
+#[cfg(test)]
+mod nut_tests;
+#[cfg(test)]
+mod settings_tests;
+#[cfg(test)]
+mod snmp_tests;
+#[cfg(test)]
+mod stream_tests;
 
+/// Every option below can also come from its `NOBREAK_*` env var or from
+/// `nobreakd.toml`; flags win over env, env over the file, the file over
+/// the built-in defaults shown in brackets.
 #[derive(Debug, Parser)]
 #[command(name = "nobreakd")]
 #[command(about = "RagTech Nobreak realtime monitor (read-only)")]
@@ -19,26 +65,145 @@ struct Cli {
     #[command(subcommand)]
     command: Command,
 
-    #[arg(long, default_value = "./vendor")]
-    vendor_dir: String,
+    /// Config file [/etc/nobreak/nobreakd.toml when present].
+    #[arg(long, env = "NOBREAK_CONFIG")]
+    config: Option<String>,
+
+    /// [./vendor]
+    #[arg(long, env = "NOBREAK_VENDOR_DIR")]
+    vendor_dir: Option<String>,
+
+    /// [1000]
+    #[arg(long, env = "NOBREAK_INTERVAL_MS")]
+    interval_ms: Option<u64>,
+
+    /// Lower bound for the adaptive interval [1000].
+    #[arg(long, env = "NOBREAK_INTERVAL_MIN_MS")]
+    interval_min_ms: Option<u64>,
+
+    /// Upper bound for the adaptive interval [3000].
+    #[arg(long, env = "NOBREAK_INTERVAL_MAX_MS")]
+    interval_max_ms: Option<u64>,
+
+    /// [2500]
+    #[arg(long, env = "NOBREAK_STALE_AFTER_MS")]
+    stale_after_ms: Option<u64>,
+
+    /// [5000]
+    #[arg(long, env = "NOBREAK_DISCONNECTED_AFTER_MS")]
+    disconnected_after_ms: Option<u64>,
+
+    /// [700]
+    #[arg(long, env = "NOBREAK_POLL_TIMEOUT_MS")]
+    poll_timeout_ms: Option<u64>,
+
+    /// [3]
+    #[arg(long, env = "NOBREAK_ERROR_THRESHOLD")]
+    error_threshold: Option<u32>,
+
+    /// Rolling quality windows in seconds, comma separated [60,300,3600].
+    #[arg(long, env = "NOBREAK_QUALITY_WINDOWS_SEC", value_delimiter = ',')]
+    quality_windows_sec: Option<Vec<u64>>,
+
+    /// Keep the interval fixed instead of adapting it to read RTT and errors [false].
+    #[arg(
+        long,
+        env = "NOBREAK_NO_AUTO_TUNE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        value_parser = BoolishValueParser::new()
+    )]
+    no_auto_tune: Option<bool>,
+
+    /// Keep the RTT-driven interval even on battery or during power events [false].
+    #[arg(
+        long,
+        env = "NOBREAK_NO_POWER_AWARE_SAMPLING",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        value_parser = BoolishValueParser::new()
+    )]
+    no_power_aware_sampling: Option<bool>,
+
+    /// Seconds on mains without power events before relaxing to the maximum interval [3600].
+    #[arg(long, env = "NOBREAK_STABLE_AFTER_SEC")]
+    stable_after_sec: Option<u64>,
+
+    /// Carry the last good vars into failed-read snapshots, marked as held [false].
+    #[arg(
+        long,
+        env = "NOBREAK_HOLD_LAST_GOOD",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        value_parser = BoolishValueParser::new()
+    )]
+    hold_last_good: Option<bool>,
+
+    /// TOML file with `[limits.<var>]` plausibility ranges, merged over the defaults.
+    #[arg(long, env = "NOBREAK_PLAUSIBILITY_LIMITS")]
+    plausibility_limits: Option<String>,
 
-    #[arg(long, default_value_t = 1000)]
-    interval_ms: u64,
+    /// TOML file with per-var `[vars.<var>]` smoothing filters (median, ema, hampel).
+    #[arg(long, env = "NOBREAK_FILTERS")]
+    filters: Option<String>,
 
-    #[arg(long, default_value_t = 2500)]
-    stale_after_ms: u64,
+    /// TOML file with `[[alerts]]` rules evaluated against every snapshot.
+    #[arg(long, env = "NOBREAK_ALERT_RULES")]
+    alert_rules: Option<String>,
 
-    #[arg(long, default_value_t = 5000)]
-    disconnected_after_ms: u64,
+    /// Directory for state that must survive restarts (battery health, energy, power quality).
+    #[arg(long, env = "NOBREAK_STATE_DIR")]
+    state_dir: Option<String>,
 
-    #[arg(long, default_value_t = 700)]
-    poll_timeout_ms: u64,
+    /// Offset from UTC, in minutes, for energy and power-quality buckets and tariff hours [0].
+    #[arg(long, env = "NOBREAK_UTC_OFFSET_MINUTES", allow_hyphen_values = true)]
+    utc_offset_minutes: Option<i32>,
 
-    #[arg(long, default_value_t = 3)]
-    error_threshold: u32,
+    /// TOML file describing the energy tariff and its hourly bands.
+    #[arg(long, env = "NOBREAK_TARIFF")]
+    tariff: Option<String>,
 
-    #[arg(long)]
+    /// Nominal mains voltage (127 or 220) for PRODIST classification; auto-detected if unset.
+    #[arg(long, env = "NOBREAK_NOMINAL_INPUT_V")]
+    nominal_input_v: Option<f64>,
+
+    #[arg(long, env = "NOBREAK_DEVICE_ID")]
     device_id: Option<String>,
+
+    /// Serve the HTTP API (see docs/http.md) on this address while streaming; enables `[sinks.http]` [127.0.0.1:9750].
+    #[arg(long, env = "NOBREAK_HTTP_LISTEN")]
+    http_listen: Option<String>,
+
+    /// Publish to this MQTT broker while streaming; enables `[sinks.mqtt]` (see docs/mqtt.md).
+    #[arg(long, env = "NOBREAK_MQTT_HOST")]
+    mqtt_host: Option<String>,
+
+    /// MQTT password; prefer the env var so it stays out of `ps`.
+    #[arg(long, env = "NOBREAK_MQTT_PASSWORD", hide_env_values = true)]
+    mqtt_password: Option<String>,
+
+    /// Answer NUT clients on this address while streaming; enables `[sinks.nut]` (see docs/nut.md) [127.0.0.1:3493].
+    #[arg(long, env = "NOBREAK_NUT_LISTEN")]
+    nut_listen: Option<String>,
+
+    /// Answer apcupsd NIS clients on this address while streaming; enables `[sinks.nis]` (see docs/nis.md) [127.0.0.1:3551].
+    #[arg(long, env = "NOBREAK_NIS_LISTEN")]
+    nis_listen: Option<String>,
+
+    /// Answer SNMP v1/v2c/v3 for the UPS-MIB on this address while streaming; enables `[sinks.snmp]` (see docs/snmp.md) [127.0.0.1:1161].
+    #[arg(long, env = "NOBREAK_SNMP_LISTEN")]
+    snmp_listen: Option<String>,
+
+    /// SNMP read community; prefer the env var so it stays out of `ps`.
+    #[arg(long, env = "NOBREAK_SNMP_COMMUNITY", hide_env_values = true)]
+    snmp_community: Option<String>,
+
+    /// Serve the register map over Modbus TCP on this address while streaming; enables `[sinks.modbus]` (see docs/modbus.md) [127.0.0.1:5020].
+    #[arg(long, env = "NOBREAK_MODBUS_LISTEN")]
+    modbus_listen: Option<String>,
 }
 
 #[derive(Debug, Subcommand)]
@@ -50,26 +215,58 @@ enum Command {
         format: OutputFormat,
     },
     Run {
-        #[arg(long, value_enum, default_value = "human")]
-        format: OutputFormat,
+        /// [sinks.stdout.format, else human]
+        #[arg(long, value_enum)]
+        format: Option<OutputFormat>,
     },
     Watch {
-        #[arg(long, value_enum, default_value = "human")]
-        format: OutputFormat,
+        /// [sinks.stdout.format, else human]
+        #[arg(long, value_enum)]
+        format: Option<OutputFormat>,
     },
     View {
         #[arg(long, default_value_t = 180.0)]
         window_sec: f64,
     },
     Export {
-        #[arg(long, default_value = "./data/metrics")]
-        output_dir: String,
-        #[arg(long, default_value_t = 90)]
-        retention_days: u64,
+        /// [sinks.export.output_dir, else ./data/metrics]
+        #[arg(long)]
+        output_dir: Option<String>,
+        /// [sinks.export.retention_days, else 90]
+        #[arg(long)]
+        retention_days: Option<u64>,
+    },
+    /// Print a report from the persisted state in `--state-dir`.
+    Query {
+        #[arg(value_enum)]
+        report: QueryReport,
+        /// Restrict day-based reports to one local date (YYYY-MM-DD).
+        #[arg(long)]
+        date: Option<chrono::NaiveDate>,
     },
+    /// Inspect the configuration.
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigAction {
+    /// Parse and validate the config file with env and flags applied.
+    Check,
 }
 
 #[derive(Debug, Clone, Copy, ValueEnum)]
+enum QueryReport {
+    /// PRODIST Module 8 voltage conformity (DRP/DRC) and frequency deviations.
+    Prodist,
+    /// Sag/swell counts by depth, ITIC regions, `vInput` histograms and hourly excursions.
+    PowerQuality,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
 enum OutputFormat {
     Human,
     Json,
@@ -85,21 +282,13 @@ async fn main() -> Result<()> {
         .init();
 
     let cli = Cli::parse();
+    let settings = settings::load(&cli)?;
+    let config = settings.monitor.clone();
+    let device_id = settings.device_id.clone();
 
-    let config = MonitorConfig {
-        sample_interval: Duration::from_millis(cli.interval_ms),
-        sample_interval_min: Duration::from_secs(1),
-        sample_interval_max: Duration::from_secs(3),
-        stale_after: Duration::from_millis(cli.stale_after_ms),
-        disconnected_after: Duration::from_millis(cli.disconnected_after_ms),
-        poll_timeout: Duration::from_millis(cli.poll_timeout_ms),
-        error_threshold: cli.error_threshold,
-        auto_tune: true,
-    };
-
-    let mut driver = VendorShimDriver::new(cli.vendor_dir.clone());
+    let mut driver = VendorShimDriver::new(settings.vendor_dir.clone());
 
-    match cli.command {
+    match &cli.command {
         Command::Scan => {
             let devices = nobreak_core::UpsDriver::discover(&mut driver).await?;
             println!("{}", serde_json::to_string_pretty(&devices)?);
@@ -115,36 +304,289 @@ async fn main() -> Result<()> {
             println!("{}", serde_json::to_string_pretty(&out)?);
         }
         Command::Once { format } => {
-            let mut monitor = Monitor::new(driver, config, cli.device_id);
+            let mut monitor = Monitor::new(driver, config, device_id);
             let snapshot = monitor.tick().await;
-            print_snapshot(&snapshot, format)?;
+            print_snapshot(&snapshot, *format)?;
         }
         Command::Run { format } | Command::Watch { format } => {
-            let mut monitor = Monitor::new(driver, config, cli.device_id);
-            stream_loop(&mut monitor, format).await?;
+            let format = format.unwrap_or(settings.stdout_format());
+            let service = MonitorService::spawn(Monitor::new(driver, config, device_id));
+            let sink = stream_loop(service.feed(), format);
+            let result = serve(&cli, &settings, &service, true, sink).await;
+            service.shutdown().await;
+            result?;
         }
         Command::View { window_sec } => {
-            let mut monitor = Monitor::new(driver, config, cli.device_id);
-            viewer::run_viewer(&mut monitor, window_sec).await?;
+            let service = MonitorService::spawn(Monitor::new(driver, config, device_id));
+            let sink = viewer::run_viewer(service.feed(), *window_sec);
+            let result = serve(&cli, &settings, &service, true, sink).await;
+            service.shutdown().await;
+            result?;
         }
         Command::Export {
             output_dir,
             retention_days,
         } => {
-            let mut monitor = Monitor::new(driver, config, cli.device_id);
-            exporter::run_exporter(&mut monitor, &output_dir, retention_days).await?;
+            let output_dir = output_dir.clone().unwrap_or_else(|| settings.export_dir());
+            let retention_days = retention_days.unwrap_or(settings.retention_days());
+            let service = MonitorService::spawn(Monitor::new(driver, config, device_id));
+            let export = exporter::run_exporter(service.feed(), &output_dir, retention_days);
+            let result = serve(&cli, &settings, &service, false, export).await;
+            service.shutdown().await;
+            result?;
+        }
+        Command::Query { report, date } => {
+            let missing = || anyhow!("query needs --state-dir");
+            let out = match report {
+                QueryReport::Prodist => {
+                    let path = config.prodist.state_path.ok_or_else(missing)?;
+                    let state: ProdistState = nobreak_core::persist::load_json(&path)
+                        .with_context(|| format!("reading {}", path.display()))?;
+                    serde_json::to_value(nobreak_core::prodist::report_from(&state))?
+                }
+                QueryReport::PowerQuality => {
+                    let path = config.pq_stats.state_path.ok_or_else(missing)?;
+                    let state: PqStatsState = nobreak_core::persist::load_json(&path)
+                        .with_context(|| format!("reading {}", path.display()))?;
+                    let days = state
+                        .days
+                        .into_iter()
+                        .filter(|day| date.is_none_or(|date| day.date == date))
+                        .collect::<Vec<_>>();
+                    serde_json::to_value(days)?
+                }
+            };
+            println!("{}", serde_json::to_string_pretty(&out)?);
+        }
+        Command::Config {
+            action: ConfigAction::Check,
+        } => {
+            print_config_summary(&settings);
         }
     }
 
     Ok(())
 }
 
-async fn stream_loop<D: nobreak_core::UpsDriver>(
-    monitor: &mut Monitor<D>,
-    format: OutputFormat,
+/// Runs the foreground sink next to the background sinks enabled in the
+/// config, and re-applies the config on SIGHUP without touching the device.
+/// A background sink that stops ends the command with its error.
+async fn serve(
+    cli: &Cli,
+    settings: &Settings,
+    service: &MonitorService,
+    with_export: bool,
+    sink: impl Future<Output = Result<()>>,
 ) -> Result<()> {
-    let start = Instant::now() + Duration::from_millis(50);
-    let mut ticker = interval_at(start, monitor.effective_interval());
+    let mut background = Sinks::default();
+    if with_export && settings.sinks.export.enabled {
+        let feed = service.feed();
+        let (dir, days) = (settings.export_dir(), settings.retention_days());
+        background.spawn("export", async move {
+            exporter::run_exporter(feed, &dir, days).await
+        });
+    }
+    if settings.sinks.http.enabled {
+        let listener = net::bind_tcp("http", settings.http_listen()?).await?;
+        background.spawn("http", http::run_http(service.feed(), listener));
+    }
+    if settings.sinks.mqtt.enabled {
+        let mqtt = settings.sinks.mqtt.clone();
+        background.spawn("mqtt", mqtt::run_mqtt(mqtt, service.feed().stream()));
+    }
+    if settings.sinks.nut.enabled {
+        let listener = net::bind_tcp("nut", settings.nut_listen()?).await?;
+        let nut = settings.sinks.nut.clone();
+        background.spawn("nut", nut::run_nut(nut, service.feed().stream(), listener));
+    }
+    if settings.sinks.nis.enabled {
+        let listener = net::bind_tcp("nis", settings.nis_listen()?).await?;
+        let nis = nis::Nis {
+            config: settings.sinks.nis.clone(),
+            hostname: nis::hostname(),
+            started: chrono::Utc::now(),
+            battery_low_pct: settings.monitor.events.battery_low_below_pct,
+            rated_w: settings.monitor.profile.rated_w,
+            offset: nis::local_offset(),
+        };
+        let log = nis::EventLog::open(settings.sinks.nis.events_path.clone())?;
+        let stream = service.feed().stream();
+        background.spawn("nis", nis::run_nis(nis, log, stream, listener));
+    }
+    if settings.sinks.snmp.enabled {
+        let socket = snmp::bind(settings.snmp_listen()?).await?;
+        let sys_name = nis::hostname();
+        let engine = usm::Engine::start(&settings.sinks.snmp, &sys_name)?;
+        let agent = snmp::Agent::new(
+            settings.sinks.snmp.clone(),
+            snmp::Identity {
+                sys_name,
+                rated_va: settings.monitor.profile.rated_va,
+                rated_w: settings.monitor.profile.rated_w,
+            },
+            engine,
+        );
+        let traps = settings.snmp_traps()?;
+        let stream = service.feed().stream();
+        background.spawn("snmp", snmp::run_snmp(agent, traps, stream, socket));
+    }
+    if settings.sinks.modbus.enabled {
+        let listener = net::bind_tcp("modbus", settings.modbus_listen()?).await?;
+        let config = settings.sinks.modbus.clone();
+        let stream = service.feed().stream();
+        background.spawn("modbus", modbus::run_modbus(config, stream, listener));
+    }
+
+    let mut current = settings.clone();
+    let mut hangup = signal(SignalKind::hangup())?;
+    tokio::pin!(sink);
+    // Dropping the set on the way out aborts the sinks still running.
+    loop {
+        tokio::select! {
+            result = &mut sink => break result,
+            result = background.first_ended() => break result,
+            _ = hangup.recv() => reload(cli, &mut current, service),
+        }
+    }
+}
+
+/// Background sinks, named so the one that ends can be reported.
+#[derive(Default)]
+struct Sinks {
+    tasks: JoinSet<Result<()>>,
+    names: HashMap<task::Id, &'static str>,
+}
+
+impl Sinks {
+    fn spawn(
+        &mut self,
+        name: &'static str,
+        task: impl Future<Output = Result<()>> + Send + 'static,
+    ) {
+        let handle = self.tasks.spawn(task);
+        self.names.insert(handle.id(), name);
+    }
+
+    /// Resolves when the first sink ends, always with an error; never
+    /// resolves when no sink runs.
+    async fn first_ended(&mut self) -> Result<()> {
+        let Some(joined) = self.tasks.join_next_with_id().await else {
+            return std::future::pending().await;
+        };
+        match joined {
+            Ok((id, Ok(()))) => Err(anyhow!("{} sink stopped", self.names[&id])),
+            Ok((id, Err(err))) => Err(err.context(format!("{} sink failed", self.names[&id]))),
+            Err(err) => Err(anyhow!("{} sink panicked: {err}", self.names[&err.id()])),
+        }
+    }
+}
+
+fn reload(cli: &Cli, current: &mut Settings, service: &MonitorService) {
+    let next = match settings::load(cli) {
+        Ok(next) => next,
+        Err(err) => {
+            warn!(error = %format!("{err:#}"), "config reload rejected, keeping the running config");
+            return;
+        }
+    };
+    let (next, kept) = current.reloaded(next);
+    if !kept.is_empty() {
+        warn!(sections = %kept.join(", "), "these changes need a restart; applying the rest");
+    }
+    service.reconfigure(next.monitor.clone());
+    info!(source = ?next.source, "config reloaded");
+    *current = next;
+}
+
+fn print_config_summary(settings: &Settings) {
+    let m = &settings.monitor;
+    match &settings.source {
+        Some(path) => println!("config ok: {}", path.display()),
+        None => println!("config ok: no config file, using env, flags and defaults"),
+    }
+    println!(
+        "device:      vendor_dir={} id={}",
+        settings.vendor_dir,
+        settings.device_id.as_deref().unwrap_or("auto")
+    );
+    println!(
+        "interval:    {} ms ({}..{} ms) auto_tune={} power_aware={} stable_after={} s",
+        m.sample_interval.as_millis(),
+        m.sample_interval_min.as_millis(),
+        m.sample_interval_max.as_millis(),
+        m.auto_tune,
+        m.power_aware_sampling,
+        m.stable_after.as_secs(),
+    );
+    println!(
+        "freshness:   stale_after={} ms disconnected_after={} ms poll_timeout={} ms error_threshold={}",
+        m.stale_after.as_millis(),
+        m.disconnected_after.as_millis(),
+        m.poll_timeout.as_millis(),
+        m.error_threshold,
+    );
+    println!(
+        "state_dir:   {}",
+        settings
+            .state_dir
+            .as_deref()
+            .and_then(Path::to_str)
+            .unwrap_or("none")
+    );
+    println!("alerts:      {} rules", m.alerts.len());
+    println!("filters:     {} vars", m.filters.vars.len());
+    println!(
+        "tariff:      {}",
+        m.energy
+            .tariff
+            .as_ref()
+            .map_or("none", |t| t.currency.as_str())
+    );
+    let mqtt = &settings.sinks.mqtt;
+    println!(
+        "sinks:       stdout={:?} export={} http={} mqtt={} nut={} nis={} snmp={} modbus={}",
+        settings.stdout_format(),
+        if settings.sinks.export.enabled {
+            settings.export_dir()
+        } else {
+            "off".to_string()
+        },
+        match settings.http_listen() {
+            Ok(listen) if settings.sinks.http.enabled => listen.to_string(),
+            _ => "off".to_string(),
+        },
+        if mqtt.enabled {
+            format!(
+                "{}:{}/{}/{}",
+                mqtt.host, mqtt.port, mqtt.topic_prefix, mqtt.node_id
+            )
+        } else {
+            "off".to_string()
+        },
+        match settings.nut_listen() {
+            Ok(listen) if settings.sinks.nut.enabled =>
+                format!("{}@{listen}", settings.sinks.nut.ups_name),
+            _ => "off".to_string(),
+        },
+        match settings.nis_listen() {
+            Ok(listen) if settings.sinks.nis.enabled => listen.to_string(),
+            _ => "off".to_string(),
+        },
+        match settings.snmp_listen() {
+            Ok(listen) if settings.sinks.snmp.enabled =>
+                format!("{listen} traps={}", settings.sinks.snmp.traps.len()),
+            _ => "off".to_string(),
+        },
+        match settings.modbus_listen() {
+            Ok(listen) if settings.sinks.modbus.enabled =>
+                format!("{listen} unit={}", settings.sinks.modbus.unit_id),
+            _ => "off".to_string(),
+        },
+    );
+}
+
+async fn stream_loop(feed: SnapshotFeed, format: OutputFormat) -> Result<()> {
+    let mut snapshots = feed.stream();
 
     loop {
         tokio::select! {
@@ -152,12 +594,9 @@ async fn stream_loop<D: nobreak_core::UpsDriver>(
                 warn!("received ctrl-c, stopping");
                 break;
             }
-            _ = ticker.tick() => {
-                let snapshot = monitor.tick().await;
+            Some(snapshot) = snapshots.next() => {
                 print_snapshot(&snapshot, format)?;
-                let next = monitor.effective_interval();
-                ticker = interval_at(Instant::now() + next, next);
-                info!(effective_interval_ms=%next.as_millis(), connected=%snapshot.device.connected, stale=%snapshot.freshness.stale, "tick");
+                info!(effective_interval_ms=%snapshot.quality.effective_interval_ms, interval_reason=?snapshot.quality.interval_reason, connected=%snapshot.device.connected, stale=%snapshot.freshness.stale, ticks_skipped=%snapshot.quality.ticks_skipped, "tick");
             }
         }
     }
@@ -193,10 +632,65 @@ fn print_snapshot(snapshot: &nobreak_core::Snapshot, format: OutputFormat) -> Re
                 snapshot.freshness.rtt_ms,
             );
 
+            for window in &snapshot.quality.windows {
+                println!(
+                    "Quality:    {:>5}s reads={} ok={} rtt_p50/p95/p99={}/{}/{} ms max_gap_ms={}",
+                    window.window_sec,
+                    window.reads,
+                    window
+                        .success_rate
+                        .map(|r| format!("{:.1}%", r * 100.0))
+                        .unwrap_or_else(|| "n/a".to_string()),
+                    fmt_opt(window.rtt_p50_ms),
+                    fmt_opt(window.rtt_p95_ms),
+                    fmt_opt(window.rtt_p99_ms),
+                    window.max_gap_ms,
+                );
+            }
+
+            for event in &snapshot.events {
+                println!(
+                    "Event:      {:?} {:?} started={} duration_ms={} value={}",
+                    event.kind,
+                    event.phase,
+                    event.started_at.to_rfc3339(),
+                    event
+                        .duration_ms
+                        .map(|d| d.to_string())
+                        .unwrap_or_else(|| "n/a".to_string()),
+                    event
+                        .value
+                        .map(|v| format!("{v:.1}"))
+                        .unwrap_or_else(|| "n/a".to_string()),
+                );
+            }
+
+            for alert in &snapshot.alerts {
+                println!(
+                    "Alert:      [{:?}] {} {:?} since={} value={}",
+                    alert.severity,
+                    alert.rule,
+                    alert.state,
+                    alert.since.to_rfc3339(),
+                    alert
+                        .value
+                        .map(|v| format!("{v:.1}"))
+                        .unwrap_or_else(|| "n/a".to_string()),
+                );
+            }
+
             if !snapshot.status.failures.is_empty() {
                 println!("Failures:   {}", snapshot.status.failures.join(", "));
             }
 
+            if let Some(oldest) = snapshot.vars_meta.values().map(|m| m.age_ms).max() {
+                println!(
+                    "Held:       {} vars from last good read (oldest age_ms={})",
+                    snapshot.vars_meta.len(),
+                    oldest
+                );
+            }
+
             if let Some(raw_hex) = snapshot.vars.get("rawFrameHex").and_then(|v| v.as_str()) {
                 println!("Raw Frame:  {}", raw_hex);
             }
@@ -262,12 +756,95 @@ fn print_snapshot(snapshot: &nobreak_core::Snapshot, format: OutputFormat) -> Re
                     print_est_metric(metrics, "temperature_est", "Temperature (C)");
                 }
             }
+
+            if let Some(total) = snapshot.vars.get("energyTotalWh").and_then(|v| v.as_f64()) {
+                let wh = |key: &str| {
+                    snapshot
+                        .vars
+                        .get(key)
+                        .and_then(|v| v.as_f64())
+                        .unwrap_or_default()
+                };
+                println!(
+                    "Energy:     hour={:.1} Wh day={:.1} Wh month={:.2} kWh total={:.2} kWh",
+                    wh("energyHourWh"),
+                    wh("energyDayWh"),
+                    wh("energyMonthWh") / 1000.0,
+                    total / 1000.0,
+                );
+            }
+
+            if let Some(runtime) = snapshot
+                .vars
+                .get("runtimeRemainingSec")
+                .and_then(|v| v.as_f64())
+            {
+                let bound = |key: &str| {
+                    snapshot
+                        .vars
+                        .get(key)
+                        .and_then(|v| v.as_f64())
+                        .map(|v| format!("{:.0}", v / 60.0))
+                        .unwrap_or_else(|| "n/a".to_string())
+                };
+                println!(
+                    "Runtime:    ~{:.0} min [{}..{}] ({})",
+                    runtime / 60.0,
+                    bound("runtimeRemainingSecLow"),
+                    bound("runtimeRemainingSecHigh"),
+                    snapshot
+                        .vars
+                        .get("runtimeSource")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("n/a"),
+                );
+            }
+            if let Some(class) = snapshot.vars.get("vInputClass").and_then(|v| v.as_str()) {
+                let num = |key: &str| {
+                    snapshot
+                        .vars
+                        .get(key)
+                        .and_then(|v| v.as_f64())
+                        .unwrap_or(0.0)
+                };
+                println!(
+                    "Mains:      {} (nominal {:.0} V) DRP={:.2}% DRC={:.2}% over {:.0} intervals",
+                    class,
+                    num("vInputNominal"),
+                    num("prodistDrpPct"),
+                    num("prodistDrcPct"),
+                    num("prodistIntervals"),
+                );
+            }
+            if let Some(sags) = snapshot.vars.get("pqSagsDay").and_then(|v| v.as_u64()) {
+                let num = |key: &str| {
+                    snapshot
+                        .vars
+                        .get(key)
+                        .and_then(|v| v.as_f64())
+                        .unwrap_or(0.0)
+                };
+                println!(
+                    "PQ today:   sags={} swells={:.0} interruptions={:.0} hour vInput {:.1}..{:.1} V",
+                    sags,
+                    num("pqSwellsDay"),
+                    num("pqInterruptionsDay"),
+                    num("vInputHourMin"),
+                    num("vInputHourMax"),
+                );
+            }
         }
     }
 
     Ok(())
 }
 
+fn fmt_opt(value: Option<u128>) -> String {
+    value
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| "n/a".to_string())
+}
+
 fn print_est_metric(metrics: &serde_json::Value, key: &str, label: &str) {
     if let Some(value) = metrics.get(key).and_then(|v| v.as_f64()) {
         println!("  {label:<16} ~ {:.2}", value);
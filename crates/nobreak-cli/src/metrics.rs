@@ -0,0 +1,275 @@
+//! Prometheus text exposition (format 0.0.4) of the latest snapshot.
+//!
+//! Monitor fields keep the names from Appendix B of the ultraspec. Every
+//! numeric or boolean var becomes its own gauge, `nobreak_<snake_case var>`,
+//! except the cumulative ones listed in [`COUNTER_VARS`]. Held vars are left
+//! out, so a failed read makes their series go stale instead of repeating
+//! an old value.
+
+use std::fmt::Write;
+
+use nobreak_core::Snapshot;
+
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Vars that only grow, exported as counters under these names.
+const COUNTER_VARS: &[(&str, &str)] = &[
+    ("energyTotalWh", "nobreak_energy_wh_total"),
+    ("energyTotalCost", "nobreak_energy_cost_total"),
+    (
+        "batteryDischargeEpisodes",
+        "nobreak_battery_discharge_episodes_total",
+    ),
+];
+
+pub fn render(snapshot: &Snapshot) -> String {
+    let mut out = Exposition {
+        out: String::new(),
+        labels: format!(
+            "device=\"{}\",model=\"{}\"",
+            escape(&snapshot.device.id),
+            escape(&snapshot.device.model)
+        ),
+    };
+    let flag = |b: bool| if b { 1.0 } else { 0.0 };
+    let q = &snapshot.quality;
+
+    let monitor = [
+        (
+            "nobreak_connected",
+            "gauge",
+            "1 when the device answered recently.",
+            flag(snapshot.device.connected),
+        ),
+        (
+            "nobreak_stale",
+            "gauge",
+            "1 when the newest good sample is older than stale_after.",
+            flag(snapshot.freshness.stale),
+        ),
+        (
+            "nobreak_age_ms",
+            "gauge",
+            "Age of the newest good sample.",
+            snapshot.freshness.age_ms as f64,
+        ),
+        (
+            "nobreak_rtt_ms",
+            "gauge",
+            "Round trip of the last read.",
+            snapshot.freshness.rtt_ms as f64,
+        ),
+        (
+            "nobreak_reconnects_total",
+            "counter",
+            "Driver re-initialisations since start.",
+            q.reconnects as f64,
+        ),
+        (
+            "nobreak_ticks_skipped_total",
+            "counter",
+            "Sampling slots lost to an overrunning read.",
+            q.ticks_skipped as f64,
+        ),
+        (
+            "nobreak_ticks_late_total",
+            "counter",
+            "Ticks fired more than a tenth of a period late.",
+            q.ticks_late as f64,
+        ),
+        (
+            "nobreak_interval_changes_total",
+            "counter",
+            "Sampling interval changes since start.",
+            q.interval_changes as f64,
+        ),
+        (
+            "nobreak_effective_interval_ms",
+            "gauge",
+            "Current sampling interval.",
+            q.effective_interval_ms as f64,
+        ),
+        (
+            "nobreak_poll_ms",
+            "gauge",
+            "Duration of the last poll.",
+            q.poll_ms as f64,
+        ),
+        (
+            "nobreak_snapshot_timestamp_seconds",
+            "gauge",
+            "Wall-clock time of the snapshot.",
+            snapshot.ts.timestamp_millis() as f64 / 1000.0,
+        ),
+    ];
+    for (name, kind, help, value) in monitor {
+        out.single(name, kind, help, value);
+    }
+    out.family(
+        "nobreak_reads_total",
+        "counter",
+        "Reads since start, by result.",
+    );
+    out.sample(
+        "nobreak_reads_total",
+        &[("result", "ok")],
+        q.reads_ok as f64,
+    );
+    out.sample(
+        "nobreak_reads_total",
+        &[("result", "err")],
+        q.reads_err as f64,
+    );
+
+    if !q.windows.is_empty() {
+        out.family(
+            "nobreak_window_reads",
+            "gauge",
+            "Reads within the rolling window.",
+        );
+        for w in &q.windows {
+            out.sample(
+                "nobreak_window_reads",
+                &[("window_sec", &w.window_sec.to_string())],
+                w.reads as f64,
+            );
+        }
+        out.family(
+            "nobreak_window_success_ratio",
+            "gauge",
+            "Share of good reads within the rolling window.",
+        );
+        for w in &q.windows {
+            if let Some(rate) = w.success_rate {
+                out.sample(
+                    "nobreak_window_success_ratio",
+                    &[("window_sec", &w.window_sec.to_string())],
+                    rate,
+                );
+            }
+        }
+        out.family(
+            "nobreak_window_rtt_ms",
+            "gauge",
+            "RTT percentiles of good reads within the rolling window.",
+        );
+        for w in &q.windows {
+            let window = w.window_sec.to_string();
+            // Not `quantile`: that label is reserved for summary types.
+            for (percentile, value) in [
+                ("50", w.rtt_p50_ms),
+                ("95", w.rtt_p95_ms),
+                ("99", w.rtt_p99_ms),
+            ] {
+                if let Some(value) = value {
+                    out.sample(
+                        "nobreak_window_rtt_ms",
+                        &[("window_sec", &window), ("percentile", percentile)],
+                        value as f64,
+                    );
+                }
+            }
+        }
+        out.family(
+            "nobreak_window_max_gap_ms",
+            "gauge",
+            "Longest gap between good samples within the rolling window.",
+        );
+        for w in &q.windows {
+            out.sample(
+                "nobreak_window_max_gap_ms",
+                &[("window_sec", &w.window_sec.to_string())],
+                w.max_gap_ms as f64,
+            );
+        }
+    }
+
+    for (name, value) in &snapshot.vars {
+        if snapshot.vars_meta.contains_key(name) {
+            continue;
+        }
+        let value = match value {
+            serde_json::Value::Bool(b) => flag(*b),
+            serde_json::Value::Number(n) => match n.as_f64() {
+                Some(v) => v,
+                None => continue,
+            },
+            _ => continue,
+        };
+        match COUNTER_VARS.iter().find(|(var, _)| var == name) {
+            Some((_, metric)) => out.single(metric, "counter", &format!("Var {name}."), value),
+            None => out.single(
+                &var_metric_name(name),
+                "gauge",
+                &format!("Var {name}."),
+                value,
+            ),
+        }
+    }
+
+    out.out
+}
+
+/// `vInput` → `nobreak_v_input`; anything outside `[a-zA-Z0-9]` becomes `_`.
+pub fn var_metric_name(var: &str) -> String {
+    let mut name = String::from("nobreak_");
+    let mut prev_lower = false;
+    for c in var.chars() {
+        if c.is_ascii_uppercase() {
+            if prev_lower {
+                name.push('_');
+            }
+            name.push(c.to_ascii_lowercase());
+            prev_lower = false;
+        } else if c.is_ascii_alphanumeric() {
+            name.push(c);
+            prev_lower = true;
+        } else {
+            name.push('_');
+            prev_lower = false;
+        }
+    }
+    name
+}
+
+struct Exposition {
+    out: String,
+    labels: String,
+}
+
+impl Exposition {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.out, "# HELP {name} {help}");
+        let _ = writeln!(self.out, "# TYPE {name} {kind}");
+    }
+
+    fn sample(&mut self, name: &str, extra: &[(&str, &str)], value: f64) {
+        let mut labels = self.labels.clone();
+        for (key, val) in extra {
+            let _ = write!(labels, ",{key}=\"{}\"", escape(val));
+        }
+        let _ = writeln!(self.out, "{name}{{{labels}}} {}", number(value));
+    }
+
+    fn single(&mut self, name: &str, kind: &str, help: &str, value: f64) {
+        self.family(name, kind, help);
+        self.sample(name, &[], value);
+    }
+}
+
+fn number(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,72 @@
+use crate::fixtures::snapshot;
+use crate::metrics::{render, var_metric_name};
+
+const LABELS: &str = r#"device="cdc:/dev/ttyACM0",model="RagTech 3200VA""#;
+
+#[test]
+fn appendix_b_metrics_are_typed_and_labelled() {
+    // Arrange
+    let snapshot = snapshot();
+
+    // Act
+    let text = render(&snapshot);
+
+    // Assert
+    for line in [
+        "# TYPE nobreak_connected gauge".to_string(),
+        format!("nobreak_connected{{{LABELS}}} 1"),
+        format!("nobreak_stale{{{LABELS}}} 0"),
+        format!("nobreak_age_ms{{{LABELS}}} 180"),
+        format!("nobreak_rtt_ms{{{LABELS}}} 180"),
+        "# TYPE nobreak_reads_total counter".to_string(),
+        format!("nobreak_reads_total{{{LABELS},result=\"ok\"}} 58"),
+        format!("nobreak_reads_total{{{LABELS},result=\"err\"}} 2"),
+        "# TYPE nobreak_reconnects_total counter".to_string(),
+        format!("nobreak_reconnects_total{{{LABELS}}} 1"),
+        format!("nobreak_window_rtt_ms{{{LABELS},window_sec=\"60\",percentile=\"95\"}} 240"),
+    ] {
+        assert!(
+            text.lines().any(|l| l == line),
+            "missing {line:?} in\n{text}"
+        );
+    }
+    assert!(
+        !text.contains("quantile="),
+        "quantile is reserved for summaries"
+    );
+}
+
+#[test]
+fn vars_become_gauges_except_cumulative_ones() {
+    // Arrange
+    let mut snapshot = snapshot();
+    snapshot.vars_meta.insert(
+        "temperature".to_string(),
+        nobreak_core::VarMeta {
+            age_ms: 3000,
+            held: true,
+        },
+    );
+
+    // Act
+    let text = render(&snapshot);
+
+    // Assert
+    assert!(text.contains("# TYPE nobreak_v_input gauge\n"));
+    assert!(text.contains(&format!("nobreak_v_input{{{LABELS}}} 127.4\n")));
+    assert!(text.contains("# TYPE nobreak_energy_wh_total counter\n"));
+    assert!(!text.contains("nobreak_energy_total_wh"));
+    assert!(
+        !text.contains("nobreak_temperature"),
+        "held vars are not exported"
+    );
+    assert!(
+        !text.contains("nobreak_v_input_class"),
+        "strings are not exported"
+    );
+    assert_eq!(
+        var_metric_name("runtimeRemainingSec"),
+        "nobreak_runtime_remaining_sec"
+    );
+    assert_eq!(var_metric_name("vInputRaw"), "nobreak_v_input_raw");
+}
@@ -0,0 +1,277 @@
+//! Read-only Modbus TCP server, for building management systems and PLCs.
+//!
+//! Input registers (function 04) and holding registers (function 03) are
+//! the same map, documented in `docs/modbus.md`: a status block, quality
+//! flags and the snapshot vars as scaled integers. Every write function is
+//! refused with `ILLEGAL FUNCTION`.
+
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use nobreak_core::{Snapshot, Validity};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_stream::Stream;
+use tracing::debug;
+
+use crate::net;
+use crate::power::{Live, LiveReceiver};
+use crate::settings::ModbusSink;
+
+pub const READ_HOLDING_REGISTERS: u8 = 0x03;
+pub const READ_INPUT_REGISTERS: u8 = 0x04;
+
+// Exception codes.
+pub const ILLEGAL_FUNCTION: u8 = 0x01;
+pub const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+pub const ILLEGAL_DATA_VALUE: u8 = 0x03;
+pub const GATEWAY_TARGET_FAILED: u8 = 0x0B;
+
+/// The spec's limit for one read.
+const MAX_READ: usize = 125;
+/// The MBAP length counts the unit id and the PDU, at most 253 bytes.
+const MAX_FRAME: usize = 254;
+/// Unit id for "the server itself" on Modbus TCP, always answered.
+const UNIT_TCP: u8 = 0xFF;
+
+/// Unsigned registers read this when there is no value.
+pub const NA_U16: u16 = 0xFFFF;
+/// Signed registers read this when there is no value.
+pub const NA_I16: u16 = 0x8000;
+
+// Status word (register 0) bits.
+pub const STATUS_VALID: u16 = 1 << 0;
+pub const STATUS_CONNECTED: u16 = 1 << 1;
+pub const STATUS_STALE: u16 = 1 << 2;
+pub const STATUS_ON_BATTERY: u16 = 1 << 3;
+pub const STATUS_BATTERY_LOW: u16 = 1 << 4;
+pub const STATUS_OVERLOAD: u16 = 1 << 5;
+pub const STATUS_BROWNOUT: u16 = 1 << 6;
+pub const STATUS_OVERVOLTAGE: u16 = 1 << 7;
+pub const STATUS_HELD: u16 = 1 << 8;
+pub const STATUS_IMPLAUSIBLE: u16 = 1 << 9;
+
+/// Quality flag words, one bit per row of [`VARS`].
+pub const VARS_PRESENT: usize = 20;
+pub const VARS_HELD: usize = 21;
+pub const VARS_IMPLAUSIBLE: usize = 22;
+
+#[derive(Debug, Clone, Copy)]
+enum Width {
+    /// Two's complement, saturating at ±32767.
+    I16,
+    /// Two registers, high word first.
+    U32,
+}
+
+/// Snapshot vars by register address and scale.
+const VARS: &[(usize, &str, f64, Width)] = &[
+    (100, "vInput", 10.0, Width::I16),
+    (101, "vOutput", 10.0, Width::I16),
+    (102, "fOutput", 100.0, Width::I16),
+    (103, "pOutput", 10.0, Width::I16),
+    (104, "pOutputWatts", 1.0, Width::I16),
+    (105, "vBattery", 100.0, Width::I16),
+    (106, "cBattery", 10.0, Width::I16),
+    (107, "temperature", 10.0, Width::I16),
+    (108, "runtimeRemainingSec", 1.0, Width::I16),
+    (109, "vInputNominal", 1.0, Width::I16),
+    (110, "energyTotalWh", 1.0, Width::U32),
+];
+
+/// Addresses past the last var are out of the map.
+pub const REGISTERS: usize = 112;
+
+/// The whole map for a state. Before the first snapshot everything reads
+/// as zero or "no value"; readings are left out while the device is away
+/// or stale, as the other servers do.
+pub fn registers(live: Option<&Live>) -> Vec<u16> {
+    let mut regs = vec![0; REGISTERS];
+    for (addr, _, _, width) in VARS {
+        match width {
+            Width::I16 => regs[*addr] = NA_I16,
+            Width::U32 => put_u32(&mut regs, *addr, u32::MAX),
+        }
+    }
+    regs[11] = NA_U16;
+    let Some(live) = live else {
+        return regs;
+    };
+    let snapshot = &live.snapshot;
+    let power = &live.power;
+    let reachable = live.reachable();
+
+    let mut held = 0;
+    let mut implausible = 0;
+    let mut present = 0;
+    for (row, (addr, var, scale, width)) in VARS.iter().enumerate() {
+        if snapshot.vars_meta.contains_key(*var) {
+            held |= 1 << row;
+        }
+        if snapshot
+            .validity
+            .get(*var)
+            .is_some_and(|validity| *validity != Validity::Ok)
+        {
+            implausible |= 1 << row;
+        }
+        let Some(value) = live.fresh(var).filter(|_| reachable) else {
+            continue;
+        };
+        present |= 1 << row;
+        let scaled = (value * scale).round();
+        match width {
+            Width::I16 => regs[*addr] = scaled.clamp(-32767.0, 32767.0) as i16 as u16,
+            Width::U32 => put_u32(
+                &mut regs,
+                *addr,
+                scaled.clamp(0.0, u32::MAX as f64 - 1.0) as u32,
+            ),
+        }
+    }
+    regs[VARS_PRESENT] = present;
+    regs[VARS_HELD] = held;
+    regs[VARS_IMPLAUSIBLE] = implausible;
+
+    let mut status = 0;
+    for (active, bit) in [
+        (reachable, STATUS_VALID),
+        (snapshot.device.connected, STATUS_CONNECTED),
+        (snapshot.freshness.stale, STATUS_STALE),
+        (power.on_battery(), STATUS_ON_BATTERY),
+        (power.battery_low, STATUS_BATTERY_LOW),
+        (power.overload, STATUS_OVERLOAD),
+        (power.brownout, STATUS_BROWNOUT),
+        (power.overvoltage, STATUS_OVERVOLTAGE),
+        (held != 0, STATUS_HELD),
+        (implausible != 0, STATUS_IMPLAUSIBLE),
+    ] {
+        if active {
+            status |= bit;
+        }
+    }
+    regs[0] = status;
+
+    let quality = &snapshot.quality;
+    // A heartbeat: it moves on every read attempt, good or bad.
+    regs[1] = (quality.reads_ok + quality.reads_err) as u16;
+    put_u32(
+        &mut regs,
+        2,
+        snapshot.ts.timestamp().clamp(0, u32::MAX as i64) as u32,
+    );
+    put_u32(&mut regs, 4, saturate_u32(snapshot.freshness.age_ms));
+    let on_battery_s = power
+        .on_battery_since
+        .map_or(0, |since| (snapshot.ts - since).num_seconds().max(0));
+    put_u32(&mut regs, 6, on_battery_s.min(u32::MAX as i64) as u32);
+    regs[8] = power.transfers.min(u16::MAX as u64) as u16;
+    regs[9] = snapshot.freshness.rtt_ms.min(u16::MAX as u128 - 1) as u16;
+    regs[10] = quality.effective_interval_ms.min(u16::MAX as u128 - 1) as u16;
+    if let Some(rate) = quality
+        .windows
+        .first()
+        .and_then(|window| window.success_rate)
+    {
+        regs[11] = (rate * 10_000.0).round().clamp(0.0, 10_000.0) as u16;
+    }
+    put_u32(&mut regs, 12, saturate_u32(quality.reads_ok.into()));
+    put_u32(&mut regs, 14, saturate_u32(quality.reads_err.into()));
+    regs[16] = quality.reconnects.min(u16::MAX as u64 - 1) as u16;
+    regs
+}
+
+fn put_u32(regs: &mut [u16], addr: usize, value: u32) {
+    regs[addr] = (value >> 16) as u16;
+    regs[addr + 1] = value as u16;
+}
+
+/// Leaves `u32::MAX` free for "no value".
+fn saturate_u32(value: u128) -> u32 {
+    value.min(u32::MAX as u128 - 1) as u32
+}
+
+/// Answers request PDUs for one unit.
+pub struct Server {
+    pub config: ModbusSink,
+}
+
+impl Server {
+    /// The response PDU for a request PDU addressed to `unit`.
+    pub fn handle(&self, unit: u8, pdu: &[u8], live: Option<&Live>) -> Vec<u8> {
+        let Some(&function) = pdu.first() else {
+            return exception(0, ILLEGAL_FUNCTION);
+        };
+        if unit != self.config.unit_id && unit != UNIT_TCP {
+            return exception(function, GATEWAY_TARGET_FAILED);
+        }
+        if !matches!(function, READ_HOLDING_REGISTERS | READ_INPUT_REGISTERS) {
+            debug!(function, "refusing modbus function");
+            return exception(function, ILLEGAL_FUNCTION);
+        }
+        let [start_hi, start_lo, count_hi, count_lo] = pdu[1..] else {
+            return exception(function, ILLEGAL_DATA_VALUE);
+        };
+        let start = u16::from_be_bytes([start_hi, start_lo]) as usize;
+        let count = u16::from_be_bytes([count_hi, count_lo]) as usize;
+        if !(1..=MAX_READ).contains(&count) {
+            return exception(function, ILLEGAL_DATA_VALUE);
+        }
+        if start + count > REGISTERS {
+            return exception(function, ILLEGAL_DATA_ADDRESS);
+        }
+        let regs = registers(live);
+        let mut out = vec![function, (count * 2) as u8];
+        for reg in &regs[start..start + count] {
+            out.extend_from_slice(&reg.to_be_bytes());
+        }
+        out
+    }
+}
+
+fn exception(function: u8, code: u8) -> Vec<u8> {
+    vec![function | 0x80, code]
+}
+
+pub async fn run_modbus(
+    config: ModbusSink,
+    snapshots: impl Stream<Item = Snapshot> + Unpin,
+    listener: TcpListener,
+) -> Result<()> {
+    let server = Arc::new(Server { config });
+    net::serve_clients("modbus", snapshots, listener, |live, stream| {
+        let server = server.clone();
+        async move { serve_client(&server, live, stream).await }
+    })
+    .await;
+    Ok(())
+}
+
+/// One MBAP frame at a time: transaction id, protocol id 0, length, unit id.
+async fn serve_client(server: &Server, live: LiveReceiver, mut stream: TcpStream) -> Result<()> {
+    loop {
+        let mut header = [0; 7];
+        match stream.read_exact(&mut header).await {
+            Ok(_) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err.into()),
+        }
+        let protocol = u16::from_be_bytes([header[2], header[3]]);
+        let len = u16::from_be_bytes([header[4], header[5]]) as usize;
+        if protocol != 0 || !(2..=MAX_FRAME).contains(&len) {
+            bail!("not a modbus frame: protocol {protocol}, length {len}");
+        }
+        let mut pdu = vec![0; len - 1];
+        stream.read_exact(&mut pdu).await?;
+        let reply = {
+            let current = live.borrow().clone();
+            server.handle(header[6], &pdu, current.as_deref())
+        };
+        let mut frame = Vec::with_capacity(7 + reply.len());
+        frame.extend_from_slice(&header[..4]);
+        frame.extend_from_slice(&(reply.len() as u16 + 1).to_be_bytes());
+        frame.push(header[6]);
+        frame.extend_from_slice(&reply);
+        stream.write_all(&frame).await?;
+    }
+}
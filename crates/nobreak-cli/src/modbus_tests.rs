@@ -0,0 +1,193 @@
+use nobreak_core::{EventPhase, PowerEventKind, Validity, VarMeta};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::fixtures::{event, feed, live, loopback, snapshot, until_ready};
+use crate::modbus::{
+    registers, run_modbus, Server, ILLEGAL_DATA_ADDRESS, ILLEGAL_DATA_VALUE, ILLEGAL_FUNCTION,
+    NA_I16, NA_U16, STATUS_BATTERY_LOW, STATUS_CONNECTED, STATUS_HELD, STATUS_IMPLAUSIBLE,
+    STATUS_ON_BATTERY, STATUS_STALE, STATUS_VALID, VARS_HELD, VARS_IMPLAUSIBLE, VARS_PRESENT,
+};
+use crate::power::PowerState;
+use crate::settings::ModbusSink;
+
+fn server() -> Server {
+    Server {
+        config: ModbusSink::default(),
+    }
+}
+
+fn read(function: u8, start: u16, count: u16) -> Vec<u8> {
+    let mut pdu = vec![function];
+    pdu.extend_from_slice(&start.to_be_bytes());
+    pdu.extend_from_slice(&count.to_be_bytes());
+    pdu
+}
+
+#[test]
+fn registers_carry_scaled_vars_and_status() {
+    // Arrange
+    let current = live(snapshot(), &mut PowerState::default());
+
+    // Act
+    let regs = registers(Some(&current));
+
+    // Assert
+    assert_eq!(regs[0], STATUS_VALID | STATUS_CONNECTED);
+    assert_eq!(regs[1], 60);
+    assert_eq!(
+        (regs[2] as u32) << 16 | regs[3] as u32,
+        current.snapshot.ts.timestamp() as u32
+    );
+    assert_eq!(regs[5], 180);
+    assert_eq!(regs[11], 9667);
+    assert_eq!(
+        &regs[100..110],
+        &[1274, 1200, 6000, 350, NA_I16, 2720, 1000, 380, 1500, NA_I16]
+    );
+    assert_eq!(&regs[110..112], &[0, 5121]);
+    assert_eq!(regs[VARS_PRESENT], 0b101_1110_1111);
+    assert_eq!((regs[VARS_HELD], regs[VARS_IMPLAUSIBLE]), (0, 0));
+}
+
+#[test]
+fn registers_follow_power_events_and_quality() {
+    // Arrange
+    let mut power = PowerState::default();
+    let mut lost = snapshot();
+    lost.events = vec![
+        event(PowerEventKind::MainsLost, EventPhase::Start),
+        event(PowerEventKind::BatteryLow, EventPhase::Start),
+    ];
+    lost.vars_meta.insert(
+        "vInput".to_string(),
+        VarMeta {
+            age_ms: 2000,
+            held: true,
+        },
+    );
+    lost.validity
+        .insert("cBattery".to_string(), Validity::Spike);
+    let on_battery = live(lost, &mut power);
+    let mut stale = on_battery.clone();
+    stale.snapshot.freshness.stale = true;
+
+    // Act
+    let regs = registers(Some(&on_battery));
+    let stale_regs = registers(Some(&stale));
+    let empty = registers(None);
+
+    // Assert
+    assert_eq!(
+        regs[0],
+        STATUS_VALID
+            | STATUS_CONNECTED
+            | STATUS_ON_BATTERY
+            | STATUS_BATTERY_LOW
+            | STATUS_HELD
+            | STATUS_IMPLAUSIBLE
+    );
+    assert_eq!(regs[8], 1);
+    assert_eq!(regs[100], NA_I16);
+    assert_eq!(regs[VARS_HELD], 0b1);
+    assert_eq!(regs[VARS_IMPLAUSIBLE], 0b100_0000);
+    assert_eq!(stale_regs[0] & (STATUS_VALID | STATUS_STALE), STATUS_STALE);
+    assert_eq!((stale_regs[101], stale_regs[VARS_PRESENT]), (NA_I16, 0));
+    assert_eq!((empty[0], empty[11], empty[101]), (0, NA_U16, NA_I16));
+    assert_eq!(&empty[110..112], &[0xFFFF, 0xFFFF]);
+}
+
+#[test]
+fn input_and_holding_registers_are_the_same_map() {
+    // Arrange
+    let current = live(snapshot(), &mut PowerState::default());
+
+    // Act
+    let input = server().handle(1, &read(0x04, 100, 2), Some(&current));
+    let holding = server().handle(255, &read(0x03, 100, 2), Some(&current));
+
+    // Assert
+    assert_eq!(input, vec![0x04, 4, 0x04, 0xFA, 0x04, 0xB0]);
+    assert_eq!(holding[1..], input[1..]);
+    assert_eq!(holding[0], 0x03);
+}
+
+#[test]
+fn writes_and_bad_reads_get_exceptions() {
+    // Arrange
+    let current = live(snapshot(), &mut PowerState::default());
+    let server = server();
+    let write_single = vec![0x06, 0, 100, 0, 1];
+    let write_multiple = vec![0x10, 0, 100, 0, 1, 2, 0, 1];
+
+    // Act
+    let answers = [
+        server.handle(1, &write_single, Some(&current)),
+        server.handle(1, &write_multiple, Some(&current)),
+        server.handle(1, &[0x05, 0, 0, 0xFF, 0], Some(&current)),
+        server.handle(1, &read(0x04, 111, 2), Some(&current)),
+        server.handle(1, &read(0x04, 0, 0), Some(&current)),
+        server.handle(1, &read(0x03, 0, 126), Some(&current)),
+        server.handle(1, &[0x04, 0, 0], Some(&current)),
+        server.handle(7, &read(0x04, 0, 1), Some(&current)),
+    ];
+
+    // Assert
+    assert_eq!(
+        answers,
+        [
+            vec![0x86, ILLEGAL_FUNCTION],
+            vec![0x90, ILLEGAL_FUNCTION],
+            vec![0x85, ILLEGAL_FUNCTION],
+            vec![0x84, ILLEGAL_DATA_ADDRESS],
+            vec![0x84, ILLEGAL_DATA_VALUE],
+            vec![0x83, ILLEGAL_DATA_VALUE],
+            vec![0x84, ILLEGAL_DATA_VALUE],
+            vec![0x84, 0x0B],
+        ]
+    );
+}
+
+#[tokio::test]
+async fn serves_registers_over_tcp() {
+    // Arrange
+    let (listener, addr) = loopback().await;
+    let server = tokio::spawn(run_modbus(
+        ModbusSink::default(),
+        feed(snapshot()),
+        listener,
+    ));
+
+    // Act
+    let reply = until_ready(|| async move {
+        let mut stream = TcpStream::connect(addr).await.expect("connect");
+        let pdu = read(0x04, 0, 1);
+        let mut frame = vec![0x12, 0x34, 0, 0, 0, pdu.len() as u8 + 1, 1];
+        frame.extend_from_slice(&pdu);
+        stream.write_all(&frame).await.expect("write");
+        let mut reply = [0; 11];
+        stream.read_exact(&mut reply).await.expect("reply");
+        let ready = u16::from_be_bytes([reply[9], reply[10]]) & STATUS_VALID != 0;
+        ready.then_some(reply)
+    })
+    .await;
+    server.abort();
+
+    // Assert
+    assert_eq!(
+        reply,
+        [
+            0x12,
+            0x34,
+            0,
+            0,
+            0,
+            5,
+            1,
+            0x04,
+            2,
+            0,
+            (STATUS_VALID | STATUS_CONNECTED) as u8
+        ]
+    );
+}
@@ -0,0 +1,422 @@
+//! MQTT sink: retained state per var, an availability topic and Home
+//! Assistant discovery.
+//!
+//! Topics live under `<topic_prefix>/<node_id>/`. A value is published when
+//! it changes, and everything is published again after each (re)connect,
+//! so a restarted broker without persistence is repopulated.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Result;
+use nobreak_core::Snapshot;
+use rumqttc::{AsyncClient, ClientError, Event, EventLoop, LastWill, MqttOptions, Packet, QoS};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio_stream::{Stream, StreamExt};
+use tracing::{info, warn};
+
+use crate::settings::MqttSink;
+
+const RETRY_AFTER: Duration = Duration::from_secs(5);
+const REQUEST_CAPACITY: usize = 256;
+
+/// Home Assistant metadata for a var we know.
+struct Sensor {
+    var: &'static str,
+    name: &'static str,
+    device_class: Option<&'static str>,
+    unit: Option<&'static str>,
+    state_class: Option<&'static str>,
+}
+
+const fn sensor(
+    var: &'static str,
+    name: &'static str,
+    device_class: Option<&'static str>,
+    unit: Option<&'static str>,
+    state_class: Option<&'static str>,
+) -> Sensor {
+    Sensor {
+        var,
+        name,
+        device_class,
+        unit,
+        state_class,
+    }
+}
+
+const MEASUREMENT: Option<&str> = Some("measurement");
+
+/// Debug vars that change on every read and mean nothing on their own.
+const UNPUBLISHED: &[&str] = &["rawFrameHex"];
+
+const SENSORS: &[Sensor] = &[
+    sensor(
+        "vInput",
+        "Input voltage",
+        Some("voltage"),
+        Some("V"),
+        MEASUREMENT,
+    ),
+    sensor(
+        "vOutput",
+        "Output voltage",
+        Some("voltage"),
+        Some("V"),
+        MEASUREMENT,
+    ),
+    sensor(
+        "fOutput",
+        "Output frequency",
+        Some("frequency"),
+        Some("Hz"),
+        MEASUREMENT,
+    ),
+    sensor("pOutput", "Load", None, Some("%"), MEASUREMENT),
+    sensor(
+        "pOutputWatts",
+        "Output power",
+        Some("power"),
+        Some("W"),
+        MEASUREMENT,
+    ),
+    sensor(
+        "vBattery",
+        "Battery voltage",
+        Some("voltage"),
+        Some("V"),
+        MEASUREMENT,
+    ),
+    sensor(
+        "cBattery",
+        "Battery charge",
+        Some("battery"),
+        Some("%"),
+        MEASUREMENT,
+    ),
+    sensor(
+        "temperature",
+        "Temperature",
+        Some("temperature"),
+        Some("°C"),
+        MEASUREMENT,
+    ),
+    sensor(
+        "runtimeRemainingSec",
+        "Runtime remaining",
+        Some("duration"),
+        Some("s"),
+        MEASUREMENT,
+    ),
+    sensor(
+        "batteryHealthPct",
+        "Battery health",
+        None,
+        Some("%"),
+        MEASUREMENT,
+    ),
+    sensor(
+        "energyTotalWh",
+        "Energy delivered",
+        Some("energy"),
+        Some("Wh"),
+        Some("total_increasing"),
+    ),
+    // Resets at local midnight, so it is not offered as an energy sensor;
+    // `energyTotalWh` is the one for the energy dashboard.
+    sensor("energyDayWh", "Energy today", None, Some("Wh"), None),
+    sensor("vInputClass", "Mains class", Some("enum"), None, None),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outgoing {
+    pub topic: String,
+    pub payload: String,
+    pub retain: bool,
+}
+
+/// Decides what to publish; no I/O, so it can be tested on its own.
+pub struct Planner {
+    config: MqttSink,
+    published: HashMap<String, String>,
+}
+
+impl Planner {
+    pub fn new(config: MqttSink) -> Self {
+        Self {
+            config,
+            published: HashMap::new(),
+        }
+    }
+
+    pub fn base(&self) -> String {
+        format!("{}/{}", self.config.topic_prefix, self.config.node_id)
+    }
+
+    pub fn availability_topic(&self) -> String {
+        format!("{}/availability", self.base())
+    }
+
+    /// Forget what was sent, so the next snapshot republishes everything.
+    pub fn reset(&mut self) {
+        self.published.clear();
+    }
+
+    /// State messages for one snapshot: changed vars, status topics and
+    /// availability, plus any events, which are never retained.
+    pub fn plan(&mut self, snapshot: &Snapshot) -> Vec<Outgoing> {
+        let base = self.base();
+        let availability_topic = self.availability_topic();
+        let mut out = Vec::new();
+        let mut retained = |topic: String, payload: String| {
+            if self.published.get(&topic) != Some(&payload) {
+                self.published.insert(topic.clone(), payload.clone());
+                out.push(Outgoing {
+                    topic,
+                    payload,
+                    retain: true,
+                });
+            }
+        };
+
+        let on_off = |b: bool| if b { "ON" } else { "OFF" }.to_string();
+        for (name, value) in &snapshot.vars {
+            // Held values are not news; availability already says why.
+            if snapshot.vars_meta.contains_key(name) || UNPUBLISHED.contains(&name.as_str()) {
+                continue;
+            }
+            let payload = match value {
+                Value::Number(n) => n.to_string(),
+                Value::String(s) => s.clone(),
+                Value::Bool(b) => on_off(*b),
+                _ => continue,
+            };
+            retained(format!("{base}/{name}"), payload);
+        }
+        retained(format!("{base}/status"), snapshot.status.code.clone());
+        retained(
+            format!("{base}/connected"),
+            on_off(snapshot.device.connected),
+        );
+        retained(format!("{base}/stale"), on_off(snapshot.freshness.stale));
+        retained(availability_topic, availability(snapshot).to_string());
+
+        for event in &snapshot.events {
+            out.push(Outgoing {
+                topic: format!("{base}/event"),
+                payload: json!(event).to_string(),
+                retain: false,
+            });
+        }
+        for alert in &snapshot.alerts {
+            out.push(Outgoing {
+                topic: format!("{base}/alert"),
+                payload: json!(alert).to_string(),
+                retain: false,
+            });
+        }
+        out
+    }
+
+    /// Retained Home Assistant discovery configs, one per known entity.
+    pub fn discovery(&self, model: &str) -> Vec<Outgoing> {
+        if !self.config.discovery {
+            return Vec::new();
+        }
+        let base = self.base();
+        let node = &self.config.node_id;
+        let device = json!({
+            "identifiers": [node],
+            "name": model,
+            "manufacturer": "RagTech",
+            "model": model,
+            "sw_version": env!("CARGO_PKG_VERSION"),
+        });
+        let mut out = Vec::new();
+        let mut push = |component: &str, object: &str, config: Value| {
+            out.push(Outgoing {
+                topic: format!(
+                    "{}/{component}/{node}/{object}/config",
+                    self.config.discovery_prefix
+                ),
+                payload: config.to_string(),
+                retain: true,
+            });
+        };
+
+        for sensor in SENSORS {
+            let var = sensor.var;
+            let mut config = json!({
+                "name": sensor.name,
+                "unique_id": format!("{node}_{var}"),
+                "object_id": format!("{node}_{var}"),
+                "state_topic": format!("{base}/{var}"),
+                "availability_topic": self.availability_topic(),
+                "device": device,
+            });
+            for (key, value) in [
+                ("device_class", sensor.device_class),
+                ("unit_of_measurement", sensor.unit),
+                ("state_class", sensor.state_class),
+            ] {
+                if let Some(value) = value {
+                    config[key] = json!(value);
+                }
+            }
+            if var == "vInputClass" {
+                config["options"] = json!(["adequate", "precarious", "critical", "interruption"]);
+            }
+            push("sensor", var, config);
+        }
+
+        // No availability topic: these must stay readable while the UPS is away.
+        for (object, name, device_class) in [
+            ("connected", "Connected", "connectivity"),
+            ("stale", "Data stale", "problem"),
+        ] {
+            push(
+                "binary_sensor",
+                object,
+                json!({
+                    "name": name,
+                    "unique_id": format!("{node}_{object}"),
+                    "object_id": format!("{node}_{object}"),
+                    "state_topic": format!("{base}/{object}"),
+                    "device_class": device_class,
+                    "device": device,
+                }),
+            );
+        }
+        push(
+            "sensor",
+            "status",
+            json!({
+                "name": "Status",
+                "unique_id": format!("{node}_status"),
+                "object_id": format!("{node}_status"),
+                "state_topic": format!("{base}/status"),
+                "device": device,
+            }),
+        );
+        out
+    }
+}
+
+/// `online` only while the device answers and the data is fresh.
+pub fn availability(snapshot: &Snapshot) -> &'static str {
+    if snapshot.device.connected && !snapshot.freshness.stale {
+        "online"
+    } else {
+        "offline"
+    }
+}
+
+pub async fn run_mqtt(
+    config: MqttSink,
+    mut snapshots: impl Stream<Item = Snapshot> + Unpin,
+) -> Result<()> {
+    let mut planner = Planner::new(config.clone());
+    let qos = rumqttc::qos(config.qos)?;
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    options.set_last_will(LastWill::new(
+        planner.availability_topic(),
+        "offline",
+        qos,
+        true,
+    ));
+    if let Some(username) = &config.username {
+        options.set_credentials(username, config.password.as_deref().unwrap_or_default());
+    }
+
+    let (client, events) = AsyncClient::new(options, REQUEST_CAPACITY);
+    let (link_tx, mut link) = mpsc::unbounded_channel();
+    // `EventLoop::poll` drops a half-open connection when cancelled, so it
+    // is never raced against the snapshot stream; only its outcome is.
+    let driver = drive(events, link_tx);
+    tokio::pin!(driver);
+    let mut latest: Option<Snapshot> = None;
+    let mut connected = false;
+
+    loop {
+        tokio::select! {
+            () = &mut driver => break,
+            Some(up) = link.recv() => {
+                connected = up;
+                if !up {
+                    continue;
+                }
+                info!(host = %config.host, port = config.port, "mqtt connected");
+                planner.reset();
+                if let Some(snapshot) = &latest {
+                    let discovery = planner.discovery(&snapshot.device.model);
+                    if !publish(&client, qos, discovery) || !publish(&client, qos, planner.plan(snapshot)) {
+                        planner.reset();
+                    }
+                }
+            }
+            snapshot = snapshots.next() => {
+                let Some(snapshot) = snapshot else { break };
+                if connected {
+                    let mut sent = true;
+                    if latest.is_none() {
+                        sent = publish(&client, qos, planner.discovery(&snapshot.device.model));
+                    }
+                    if !sent || !publish(&client, qos, planner.plan(&snapshot)) {
+                        planner.reset();
+                    }
+                }
+                latest = Some(snapshot);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Polls the event loop for good, reporting each connect (`true`) and
+/// disconnect (`false`). Returns only once nobody listens any more.
+async fn drive(mut events: EventLoop, link: mpsc::UnboundedSender<bool>) {
+    let mut up = false;
+    loop {
+        match events.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                up = true;
+                if link.send(true).is_err() {
+                    return;
+                }
+            }
+            Ok(_) => {}
+            Err(err) => {
+                if up {
+                    warn!(error = %err, "mqtt connection lost, retrying");
+                }
+                up = false;
+                if link.send(false).is_err() {
+                    return;
+                }
+                tokio::time::sleep(RETRY_AFTER).await;
+            }
+        }
+    }
+}
+
+/// Never waits: the event loop is driven from this same task, so a full request
+/// queue drops messages instead of deadlocking. Returns false when something
+/// was dropped; the caller then resets the planner to republish everything.
+fn publish(client: &AsyncClient, qos: QoS, messages: Vec<Outgoing>) -> bool {
+    for message in messages {
+        match client.try_publish(message.topic, qos, message.retain, message.payload) {
+            Ok(()) => {}
+            Err(ClientError::TryRequest(_)) => {
+                warn!("mqtt request queue full, dropping messages");
+                return false;
+            }
+            Err(err) => {
+                warn!(error = %err, "mqtt publish failed");
+                return false;
+            }
+        }
+    }
+    true
+}
@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::fixtures::snapshot;
+use crate::mqtt::{run_mqtt, Planner};
+use crate::settings::MqttSink;
+
+#[test]
+fn only_changed_values_are_republished() {
+    // Arrange
+    let mut planner = Planner::new(MqttSink::default());
+    let first = snapshot();
+    let mut second = snapshot();
+    second
+        .vars
+        .insert("vInput".to_string(), serde_json::json!(119.8));
+    second.vars_meta.insert(
+        "temperature".to_string(),
+        nobreak_core::VarMeta {
+            age_ms: 3000,
+            held: true,
+        },
+    );
+    second
+        .vars
+        .insert("temperature".to_string(), serde_json::json!(41.0));
+
+    // Act
+    let initial = planner.plan(&first);
+    let repeat = planner.plan(&first);
+    let changed = planner.plan(&second);
+
+    // Assert
+    let topics = |out: &[crate::mqtt::Outgoing]| {
+        out.iter()
+            .map(|m| (m.topic.clone(), m.payload.clone()))
+            .collect::<HashMap<_, _>>()
+    };
+    let initial = topics(&initial);
+    assert_eq!(initial["nobreak/ups/vInput"], "127.4");
+    assert_eq!(initial["nobreak/ups/vInputClass"], "adequate");
+    assert_eq!(initial["nobreak/ups/availability"], "online");
+    assert_eq!(initial["nobreak/ups/connected"], "ON");
+    assert!(!initial.contains_key("nobreak/ups/rawFrameHex"));
+    assert!(repeat.is_empty(), "nothing changed: {repeat:?}");
+    assert_eq!(
+        topics(&changed),
+        HashMap::from([("nobreak/ups/vInput".to_string(), "119.8".to_string())]),
+        "held temperature is not published"
+    );
+}
+
+#[test]
+fn discovery_describes_known_vars_for_home_assistant() {
+    // Arrange
+    let planner = Planner::new(MqttSink::default());
+
+    // Act
+    let configs = planner.discovery("RagTech 3200VA");
+
+    // Assert
+    let find = |topic: &str| {
+        let message = configs.iter().find(|m| m.topic == topic).expect(topic);
+        assert!(message.retain);
+        serde_json::from_str::<serde_json::Value>(&message.payload).expect("json")
+    };
+    let voltage = find("homeassistant/sensor/ups/vInput/config");
+    assert_eq!(voltage["device_class"], "voltage");
+    assert_eq!(voltage["unit_of_measurement"], "V");
+    assert_eq!(voltage["state_topic"], "nobreak/ups/vInput");
+    assert_eq!(voltage["availability_topic"], "nobreak/ups/availability");
+    assert_eq!(voltage["device"]["identifiers"][0], "ups");
+    let charge = find("homeassistant/sensor/ups/cBattery/config");
+    assert_eq!(charge["device_class"], "battery");
+    let total = find("homeassistant/sensor/ups/energyTotalWh/config");
+    assert_eq!(total["state_class"], "total_increasing");
+    let today = find("homeassistant/sensor/ups/energyDayWh/config");
+    assert!(today.get("device_class").is_none());
+    assert!(today.get("state_class").is_none());
+    let connected = find("homeassistant/binary_sensor/ups/connected/config");
+    assert!(connected.get("availability_topic").is_none());
+}
+
+async fn read_packet(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+    let header = stream.read_u8().await.expect("header");
+    let (mut len, mut shift) = (0usize, 0);
+    loop {
+        let byte = stream.read_u8().await.expect("length");
+        len |= usize::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body).await.expect("body");
+    (header, body)
+}
+
+#[tokio::test]
+async fn publishes_retained_state_to_a_broker() {
+    // Arrange: a minimal broker that accepts one client and records publishes.
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let config = MqttSink {
+        enabled: true,
+        host: "127.0.0.1".to_string(),
+        port: listener.local_addr().expect("addr").port(),
+        ..MqttSink::default()
+    };
+    let snapshots =
+        tokio_stream::StreamExt::chain(tokio_stream::iter([snapshot()]), tokio_stream::pending());
+    let client = tokio::spawn(run_mqtt(config, snapshots));
+
+    // Act
+    let received = tokio::time::timeout(Duration::from_secs(5), async {
+        let (mut stream, _) = listener.accept().await.expect("accept");
+        let (connect, _) = read_packet(&mut stream).await;
+        assert_eq!(connect >> 4, 1, "CONNECT first");
+        stream
+            .write_all(&[0x20, 0x02, 0x00, 0x00])
+            .await
+            .expect("connack");
+
+        let mut received = HashMap::new();
+        while !received.contains_key("nobreak/ups/availability") {
+            let (header, body) = read_packet(&mut stream).await;
+            if header >> 4 != 3 {
+                continue;
+            }
+            let topic_len = usize::from(u16::from_be_bytes([body[0], body[1]]));
+            let topic = String::from_utf8(body[2..2 + topic_len].to_vec()).expect("topic");
+            let mut rest = 2 + topic_len;
+            if (header >> 1) & 0x03 > 0 {
+                stream
+                    .write_all(&[0x40, 0x02, body[rest], body[rest + 1]])
+                    .await
+                    .expect("puback");
+                rest += 2;
+            }
+            let payload = String::from_utf8(body[rest..].to_vec()).expect("payload");
+            received.insert(topic, (payload, header & 0x01 == 1));
+        }
+        received
+    })
+    .await
+    .expect("broker saw the availability topic");
+    client.abort();
+
+    // Assert
+    assert_eq!(received["nobreak/ups/vInput"], ("127.4".to_string(), true));
+    assert_eq!(
+        received["nobreak/ups/availability"],
+        ("online".to_string(), true)
+    );
+    assert!(received.contains_key("homeassistant/sensor/ups/vInput/config"));
+}
@@ -0,0 +1,64 @@
+//! TCP plumbing shared by the servers.
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use nobreak_core::Snapshot;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_stream::Stream;
+use tracing::{debug, info, warn};
+
+use crate::power::{self, LiveReceiver};
+
+/// Pause after a failed accept, so running out of descriptors does not spin.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Binds the `name` server's listener. Servers are bound before the sinks
+/// are spawned, so a port in use fails the command instead of a background
+/// task.
+pub async fn bind_tcp(name: &str, listen: SocketAddr) -> Result<TcpListener> {
+    let listener = TcpListener::bind(listen)
+        .await
+        .with_context(|| format!("binding {name} listener on {listen}"))?;
+    info!(%listen, "{name} server listening");
+    Ok(listener)
+}
+
+/// Follows `snapshots` and runs `serve` on its own task for every client,
+/// until the snapshot stream ends. A failed accept is logged and retried
+/// after [`ACCEPT_BACKOFF`]; a failed client only drops that client.
+pub async fn serve_clients<F, C>(
+    name: &'static str,
+    snapshots: impl Stream<Item = Snapshot> + Unpin,
+    listener: TcpListener,
+    serve: F,
+) where
+    F: Fn(LiveReceiver, TcpStream) -> C,
+    C: Future<Output = Result<()>> + Send + 'static,
+{
+    let (live, pump) = power::follow(snapshots);
+    let accept = async {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    warn!(error = %err, "{name} accept failed, retrying");
+                    tokio::time::sleep(ACCEPT_BACKOFF).await;
+                    continue;
+                }
+            };
+            let client = serve(live.clone(), stream);
+            tokio::spawn(async move {
+                if let Err(err) = client.await {
+                    debug!(%peer, error = %err, "{name} client dropped");
+                }
+            });
+        }
+    };
+    tokio::select! {
+        _ = accept => {}
+        _ = pump => {}
+    }
+}
@@ -0,0 +1,414 @@
+//! apcupsd Network Information Server (NIS) on 3551, for `apcaccess`,
+//! Zabbix templates and desktop applets.
+//!
+//! Every message is a record with a two-byte big-endian length. A request
+//! is one record (`status` or `events`); the reply is one record per line,
+//! ended by an empty record.
+
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, FixedOffset, Local, Utc};
+use nobreak_core::{EventPhase, PowerEvent, PowerEventKind, Snapshot};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task;
+use tokio_stream::{Stream, StreamExt};
+use tracing::warn;
+
+use crate::net;
+use crate::power::{Live, LiveReceiver};
+use crate::settings::NisSink;
+
+/// apcupsd's own limit on a request record.
+const MAX_REQUEST: usize = 512;
+/// Lines kept for `events`, oldest dropped first.
+const EVENT_LOG_LINES: usize = 100;
+
+// STATFLAG bits, as apcupsd defines them.
+const FLAG_ONLINE: u32 = 0x08;
+const FLAG_ONBATT: u32 = 0x10;
+const FLAG_OVERLOAD: u32 = 0x20;
+const FLAG_BATTLOW: u32 = 0x40;
+const FLAG_COMMLOST: u32 = 0x100;
+const FLAG_PLUGGED: u32 = 0x0100_0000;
+const FLAG_BATTPRESENT: u32 = 0x0400_0000;
+
+/// What the report needs beyond the snapshot.
+pub struct Nis {
+    pub config: NisSink,
+    pub hostname: String,
+    pub started: DateTime<Utc>,
+    /// `MBATTCHG`: the `BATTERY_LOW` threshold.
+    pub battery_low_pct: f64,
+    /// `NOMPOWER`, from the model profile.
+    pub rated_w: f64,
+    /// Times are local, as apcupsd prints them.
+    pub offset: FixedOffset,
+}
+
+impl Nis {
+    /// The `status` report, one line per record. Before the first snapshot,
+    /// and while the device is away or stale, `STATUS` is `COMMLOST`.
+    pub fn status(&self, live: Option<&Live>, now: DateTime<Utc>) -> Vec<String> {
+        let mut fields: Vec<(&str, String)> = vec![
+            ("DATE", self.time(now)),
+            ("HOSTNAME", self.hostname.clone()),
+            (
+                "VERSION",
+                format!("nobreakd {} (apcupsd NIS)", env!("CARGO_PKG_VERSION")),
+            ),
+            ("UPSNAME", self.config.ups_name.clone()),
+            ("CABLE", "USB Cable".to_string()),
+            ("DRIVER", "nobreakd".to_string()),
+            ("UPSMODE", "Stand Alone".to_string()),
+            ("STARTTIME", self.time(self.started)),
+        ];
+        if let Some(live) = live {
+            fields.push(("MODEL", live.snapshot.device.model.clone()));
+        }
+        fields.push(("STATUS", status_text(live)));
+        if let Some(live) = live {
+            for (key, var, unit) in [
+                ("LINEV", "vInput", "Volts"),
+                ("LOADPCT", "pOutput", "Percent"),
+                ("BCHARGE", "cBattery", "Percent"),
+            ] {
+                if let Some(value) = live.fresh(var) {
+                    fields.push((key, format!("{value:.1} {unit}")));
+                }
+            }
+            if let Some(seconds) = live.fresh("runtimeRemainingSec") {
+                fields.push(("TIMELEFT", format!("{:.1} Minutes", seconds / 60.0)));
+            }
+            fields.push(("MBATTCHG", format!("{:.0} Percent", self.battery_low_pct)));
+            for (key, var, unit) in [
+                ("OUTPUTV", "vOutput", "Volts"),
+                ("ITEMP", "temperature", "C"),
+                ("BATTV", "vBattery", "Volts"),
+            ] {
+                if let Some(value) = live.fresh(var) {
+                    fields.push((key, format!("{value:.1} {unit}")));
+                }
+            }
+
+            let power = &live.power;
+            let on_battery_s = power
+                .on_battery_since
+                .map_or(0, |since| (now - since).num_seconds().max(0) as u64);
+            let optional_time =
+                |at: Option<DateTime<Utc>>| at.map_or("N/A".to_string(), |at| self.time(at));
+            fields.extend([
+                ("NUMXFERS", power.transfers.to_string()),
+                ("XONBATT", optional_time(power.last_transfer)),
+                ("TONBATT", format!("{on_battery_s} Seconds")),
+                (
+                    "CUMONBATT",
+                    format!("{} Seconds", power.battery_ms / 1000 + on_battery_s),
+                ),
+                ("XOFFBATT", optional_time(power.last_return)),
+            ]);
+        }
+        fields.push(("STATFLAG", format!("0x{:08X}", status_flags(live))));
+        if let Some(nominal) = live.and_then(|live| live.fresh("vInputNominal")) {
+            fields.push(("NOMINV", format!("{nominal:.0} Volts")));
+        }
+        fields.push(("NOMPOWER", format!("{:.0} Watts", self.rated_w)));
+        fields.push(("END APC", self.time(now)));
+
+        let lines: Vec<String> = fields
+            .iter()
+            .map(|(key, value)| format!("{key:<9}: {value}\n"))
+            .collect();
+        let bytes: usize = lines.iter().map(String::len).sum();
+        let mut out = vec![format!(
+            "APC      : 001,{:03},{bytes:04}\n",
+            lines.len() + 1
+        )];
+        out.extend(lines);
+        out
+    }
+
+    /// One `events` line for a power event, in apcupsd's wording where it
+    /// has one.
+    pub fn event_line(&self, event: &PowerEvent) -> String {
+        let start = event.phase == EventPhase::Start;
+        let text = match (event.kind, start) {
+            (PowerEventKind::MainsLost, _) => "Power failure.",
+            (PowerEventKind::MainsRestored, _) => "Power is back. UPS running on mains.",
+            (PowerEventKind::BatteryLow, true) => "Remaining battery charge below limit.",
+            (PowerEventKind::BatteryLow, false) => "Battery charge back above limit.",
+            (PowerEventKind::Overload, true) => "UPS overload condition.",
+            (PowerEventKind::Overload, false) => "UPS overload condition cleared.",
+            (PowerEventKind::Brownout, true) => "Input voltage below brownout threshold.",
+            (PowerEventKind::Brownout, false) => "Input voltage back above brownout threshold.",
+            (PowerEventKind::Overvoltage, true) => "Input voltage above overvoltage threshold.",
+            (PowerEventKind::Overvoltage, false) => {
+                "Input voltage back below overvoltage threshold."
+            }
+            (PowerEventKind::DeviceLost, _) => "Communications with UPS lost.",
+            (PowerEventKind::DeviceFound, _) => "Communications with UPS restored.",
+        };
+        let at = if start {
+            event.started_at
+        } else {
+            event.ended_at.unwrap_or(event.started_at)
+        };
+        format!("{}  {text}\n", self.time(at))
+    }
+
+    fn time(&self, at: DateTime<Utc>) -> String {
+        at.with_timezone(&self.offset)
+            .format("%Y-%m-%d %H:%M:%S %z")
+            .to_string()
+    }
+}
+
+fn reachable(live: Option<&Live>) -> Option<&Live> {
+    live.filter(|live| live.reachable())
+}
+
+fn status_text(live: Option<&Live>) -> String {
+    let Some(live) = reachable(live) else {
+        return "COMMLOST".to_string();
+    };
+    let power = &live.power;
+    let mut words = vec![if power.on_battery() {
+        "ONBATT"
+    } else {
+        "ONLINE"
+    }];
+    if power.battery_low {
+        words.push("LOWBATT");
+    }
+    if power.overload {
+        words.push("OVERLOAD");
+    }
+    words.join(" ")
+}
+
+fn status_flags(live: Option<&Live>) -> u32 {
+    let Some(live) = reachable(live) else {
+        return FLAG_COMMLOST;
+    };
+    let power = &live.power;
+    let mut flags = FLAG_PLUGGED | FLAG_BATTPRESENT;
+    flags |= if power.on_battery() {
+        FLAG_ONBATT
+    } else {
+        FLAG_ONLINE
+    };
+    if power.battery_low {
+        flags |= FLAG_BATTLOW;
+    }
+    if power.overload {
+        flags |= FLAG_OVERLOAD;
+    }
+    flags
+}
+
+/// Records framed for the wire, ended by the empty record.
+pub fn frame(records: &[String]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for record in records {
+        // Lines are far below 64 KiB; cut rather than wrap the length.
+        let bytes = &record.as_bytes()[..record.len().min(u16::MAX as usize)];
+        out.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+        out.extend_from_slice(bytes);
+    }
+    out.extend_from_slice(&[0, 0]);
+    out
+}
+
+/// Recent `events` lines. With a path, every line is also appended to
+/// that file, so the history survives restarts. The file is rewritten with
+/// just the kept lines once it holds as many dropped ones, so it never grows
+/// past twice [`EVENT_LOG_LINES`].
+#[derive(Debug, Default)]
+pub struct EventLog {
+    lines: VecDeque<String>,
+    path: Option<PathBuf>,
+    /// Lines dropped from `lines` that are still in the file.
+    dropped: usize,
+}
+
+/// File work owed by [`EventLog::record`]. It blocks, so the server runs it
+/// with `spawn_blocking` rather than on the runtime.
+#[derive(Debug)]
+pub struct PendingWrite {
+    path: PathBuf,
+    text: String,
+    replace: bool,
+}
+
+impl PendingWrite {
+    pub fn write(self) {
+        let result = if self.replace {
+            replace(&self.path, &self.text)
+        } else {
+            append(&self.path, &self.text)
+        };
+        if let Err(err) = result {
+            warn!(path = %self.path.display(), error = %err, "nis events not saved");
+        }
+    }
+}
+
+impl EventLog {
+    /// Starts from the last lines of `path`, and trims the file to them.
+    pub fn open(path: Option<PathBuf>) -> Result<Self> {
+        let mut log = Self::default();
+        let Some(path) = path else {
+            return Ok(log);
+        };
+        match fs::read_to_string(&path) {
+            Ok(raw) => {
+                for line in raw.lines() {
+                    log.push(format!("{line}\n"));
+                }
+                if log.dropped > 0 {
+                    fs::write(&path, log.lines().concat())
+                        .with_context(|| format!("trimming {}", path.display()))?;
+                    log.dropped = 0;
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => {
+                return Err(err).with_context(|| format!("reading {}", path.display()));
+            }
+        }
+        log.path = Some(path);
+        Ok(log)
+    }
+
+    /// Keeps the snapshot's event lines and returns what the file needs, if
+    /// anything.
+    #[must_use = "the file is only written by PendingWrite::write"]
+    pub fn record(&mut self, nis: &Nis, snapshot: &Snapshot) -> Option<PendingWrite> {
+        let mut text = String::new();
+        for event in &snapshot.events {
+            let line = nis.event_line(event);
+            text.push_str(&line);
+            self.push(line);
+        }
+        let path = self.path.clone().filter(|_| !text.is_empty())?;
+        if self.dropped < EVENT_LOG_LINES {
+            return Some(PendingWrite {
+                path,
+                text,
+                replace: false,
+            });
+        }
+        self.dropped = 0;
+        Some(PendingWrite {
+            path,
+            text: self.lines().concat(),
+            replace: true,
+        })
+    }
+
+    fn push(&mut self, line: String) {
+        if self.lines.len() == EVENT_LOG_LINES {
+            self.lines.pop_front();
+            self.dropped += 1;
+        }
+        self.lines.push_back(line);
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.iter().cloned().collect()
+    }
+}
+
+fn append(path: &Path, line: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line.as_bytes())
+}
+
+fn replace(path: &Path, text: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, text)
+}
+
+pub async fn run_nis(
+    nis: Nis,
+    log: EventLog,
+    snapshots: impl Stream<Item = Snapshot> + Unpin,
+    listener: TcpListener,
+) -> Result<()> {
+    let nis = Arc::new(nis);
+    let log = Arc::new(Mutex::new(log));
+    let recorded = {
+        let (nis, log) = (nis.clone(), log.clone());
+        Box::pin(snapshots.then(move |snapshot| {
+            let pending = log.lock().expect("event log lock").record(&nis, &snapshot);
+            async move {
+                if let Some(pending) = pending {
+                    if let Err(err) = task::spawn_blocking(move || pending.write()).await {
+                        warn!(error = %err, "nis events writer failed");
+                    }
+                }
+                snapshot
+            }
+        }))
+    };
+    net::serve_clients("nis", recorded, listener, |live, stream| {
+        let (nis, log) = (nis.clone(), log.clone());
+        async move { serve_client(&nis, &log, live, stream).await }
+    })
+    .await;
+    Ok(())
+}
+
+async fn serve_client(
+    nis: &Nis,
+    log: &Mutex<EventLog>,
+    live: LiveReceiver,
+    mut stream: TcpStream,
+) -> Result<()> {
+    loop {
+        let len = match stream.read_u16().await {
+            Ok(len) => len as usize,
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        if len > MAX_REQUEST {
+            bail!("request of {len} bytes");
+        }
+        let mut request = vec![0; len];
+        stream.read_exact(&mut request).await?;
+        let records = match String::from_utf8_lossy(&request).trim() {
+            "status" => {
+                let current = live.borrow().clone();
+                nis.status(current.as_deref(), Utc::now())
+            }
+            "events" => log.lock().expect("event log lock").lines(),
+            _ => vec!["Invalid command\n".to_string()],
+        };
+        stream.write_all(&frame(&records)).await?;
+    }
+}
+
+/// The local UTC offset now, for [`Nis::offset`].
+pub fn local_offset() -> FixedOffset {
+    *Local::now().offset()
+}
+
+/// For `HOSTNAME`; falls back to `localhost`.
+pub fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
@@ -0,0 +1,234 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use chrono::{FixedOffset, TimeZone, Utc};
+use nobreak_core::{EventPhase, PowerEventKind};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::fixtures::{event, feed, live, loopback, snapshot, until_ready};
+use crate::nis::{frame, run_nis, EventLog, Nis};
+use crate::power::PowerState;
+use crate::settings::NisSink;
+
+fn nis() -> Nis {
+    Nis {
+        config: NisSink::default(),
+        hostname: "rack1".to_string(),
+        started: Utc.with_ymd_and_hms(2026, 3, 1, 8, 0, 0).unwrap(),
+        battery_low_pct: 30.0,
+        rated_w: 2400.0,
+        offset: FixedOffset::west_opt(3 * 3600).unwrap(),
+    }
+}
+
+fn field<'a>(report: &'a [String], key: &str) -> Option<&'a str> {
+    report
+        .iter()
+        .find(|line| line[..9].trim_end() == key)
+        .map(|line| line[11..].trim_end_matches('\n'))
+}
+
+#[test]
+fn status_report_reads_like_apcaccess() {
+    // Arrange
+    let current = live(snapshot(), &mut PowerState::default());
+    let now = current.snapshot.ts;
+
+    // Act
+    let report = nis().status(Some(&current), now);
+
+    // Assert
+    assert_eq!(field(&report, "STATUS"), Some("ONLINE"));
+    assert_eq!(field(&report, "LINEV"), Some("127.4 Volts"));
+    assert_eq!(field(&report, "LOADPCT"), Some("35.0 Percent"));
+    assert_eq!(field(&report, "BCHARGE"), Some("100.0 Percent"));
+    assert_eq!(field(&report, "TIMELEFT"), Some("25.0 Minutes"));
+    assert_eq!(field(&report, "MBATTCHG"), Some("30 Percent"));
+    assert_eq!(field(&report, "ITEMP"), Some("38.0 C"));
+    assert_eq!(field(&report, "NOMPOWER"), Some("2400 Watts"));
+    assert_eq!(field(&report, "STATFLAG"), Some("0x05000008"));
+    assert_eq!(field(&report, "DATE"), Some("2026-03-01 09:00:00 -0300"));
+    assert_eq!(field(&report, "XONBATT"), Some("N/A"));
+    assert!(report.last().unwrap().starts_with("END APC  : "));
+    let bytes: usize = report[1..].iter().map(String::len).sum();
+    assert_eq!(
+        report[0],
+        format!("APC      : 001,{:03},{bytes:04}\n", report.len())
+    );
+}
+
+#[test]
+fn status_follows_mains_loss_and_staleness() {
+    // Arrange
+    let mut power = PowerState::default();
+    let mut lost = snapshot();
+    lost.events = vec![
+        event(PowerEventKind::MainsLost, EventPhase::Start),
+        event(PowerEventKind::BatteryLow, EventPhase::Start),
+    ];
+    let on_battery = live(lost, &mut power);
+    let mut stale = on_battery.clone();
+    stale.snapshot.freshness.stale = true;
+    let later = on_battery.snapshot.ts + chrono::Duration::seconds(300);
+
+    // Act
+    let report = nis().status(Some(&on_battery), later);
+    let stale_report = nis().status(Some(&stale), later);
+    let empty_report = nis().status(None, later);
+
+    // Assert
+    assert_eq!(field(&report, "STATUS"), Some("ONBATT LOWBATT"));
+    assert_eq!(field(&report, "STATFLAG"), Some("0x05000050"));
+    assert_eq!(field(&report, "NUMXFERS"), Some("1"));
+    assert_eq!(field(&report, "TONBATT"), Some("300 Seconds"));
+    assert_eq!(field(&report, "CUMONBATT"), Some("300 Seconds"));
+    assert_eq!(field(&report, "XONBATT"), Some("2026-03-01 09:00:00 -0300"));
+    assert_eq!(field(&stale_report, "STATUS"), Some("COMMLOST"));
+    assert_eq!(field(&stale_report, "STATFLAG"), Some("0x00000100"));
+    assert_eq!(field(&empty_report, "STATUS"), Some("COMMLOST"));
+    assert_eq!(field(&empty_report, "LINEV"), None);
+}
+
+#[test]
+fn events_use_apcupsd_wording() {
+    // Arrange
+    let mut log = EventLog::default();
+    let mut edges = snapshot();
+    edges.events = vec![
+        event(PowerEventKind::MainsLost, EventPhase::Start),
+        event(PowerEventKind::MainsRestored, EventPhase::End),
+    ];
+
+    // Act
+    let pending = log.record(&nis(), &edges);
+
+    // Assert
+    assert_eq!(
+        log.lines(),
+        vec![
+            "2026-03-01 09:00:00 -0300  Power failure.\n".to_string(),
+            "2026-03-01 09:00:00 -0300  Power is back. UPS running on mains.\n".to_string(),
+        ]
+    );
+    assert!(pending.is_none(), "no file to write");
+}
+
+fn temp_log(name: &str) -> PathBuf {
+    let uniq = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("unix epoch")
+        .as_nanos();
+    env::temp_dir().join(format!("nobreak-tests-{name}-{uniq}/nis-events.log"))
+}
+
+#[test]
+fn events_outlive_a_restart_and_keep_the_last_hundred() {
+    // Arrange
+    let path = temp_log("nis-events");
+    let mut edges = snapshot();
+    edges.events = vec![event(PowerEventKind::MainsLost, EventPhase::Start); 60];
+    let mut first = EventLog::open(Some(path.clone())).expect("no file yet");
+    for _ in 0..2 {
+        first.record(&nis(), &edges).expect("appends").write();
+    }
+
+    // Act
+    let reopened = EventLog::open(Some(path.clone())).expect("reads the file");
+    let on_disk = fs::read_to_string(&path).expect("log file");
+
+    // Assert
+    assert_eq!(reopened.lines().len(), 100);
+    assert_eq!(
+        reopened.lines()[0],
+        "2026-03-01 09:00:00 -0300  Power failure.\n"
+    );
+    assert_eq!(on_disk.lines().count(), 100, "trimmed on open");
+    fs::remove_dir_all(path.parent().expect("temp dir")).expect("cleanup");
+}
+
+#[test]
+fn events_file_is_trimmed_while_running() {
+    // Arrange
+    let path = temp_log("nis-trim");
+    let mut edges = snapshot();
+    edges.events = vec![event(PowerEventKind::MainsLost, EventPhase::Start); 60];
+    let mut log = EventLog::open(Some(path.clone())).expect("no file yet");
+
+    // Act
+    let mut sizes = Vec::new();
+    for _ in 0..4 {
+        log.record(&nis(), &edges).expect("writes").write();
+        let on_disk = fs::read_to_string(&path).expect("log file");
+        sizes.push(on_disk.lines().count());
+    }
+
+    // Assert
+    assert_eq!(
+        sizes,
+        [60, 120, 180, 100],
+        "rewritten once 100 lines dropped"
+    );
+    assert_eq!(log.lines().len(), 100);
+    fs::remove_dir_all(path.parent().expect("temp dir")).expect("cleanup");
+}
+
+#[test]
+fn records_are_length_prefixed_and_terminated() {
+    assert_eq!(
+        frame(&["ab\n".to_string()]),
+        vec![0, 3, b'a', b'b', b'\n', 0, 0]
+    );
+}
+
+async fn request(stream: &mut TcpStream, command: &str) -> Vec<String> {
+    stream
+        .write_all(&(command.len() as u16).to_be_bytes())
+        .await
+        .expect("write");
+    stream.write_all(command.as_bytes()).await.expect("write");
+    let mut records = Vec::new();
+    loop {
+        let len = stream.read_u16().await.expect("length") as usize;
+        if len == 0 {
+            return records;
+        }
+        let mut record = vec![0; len];
+        stream.read_exact(&mut record).await.expect("record");
+        records.push(String::from_utf8(record).expect("utf-8"));
+    }
+}
+
+#[tokio::test]
+async fn serves_apcaccess_over_tcp() {
+    // Arrange
+    let (listener, addr) = loopback().await;
+    let mut lost = snapshot();
+    lost.events = vec![event(PowerEventKind::MainsLost, EventPhase::Start)];
+    let server = tokio::spawn(run_nis(nis(), EventLog::default(), feed(lost), listener));
+
+    // Act
+    let (status, events, invalid) = until_ready(|| async move {
+        let mut stream = TcpStream::connect(addr).await.expect("connect");
+        let status = request(&mut stream, "status").await;
+        if field(&status, "STATUS") == Some("COMMLOST") {
+            return None;
+        }
+        let events = request(&mut stream, "events").await;
+        let invalid = request(&mut stream, "shutdown").await;
+        Some((status, events, invalid))
+    })
+    .await;
+    server.abort();
+
+    // Assert
+    assert_eq!(field(&status, "STATUS"), Some("ONBATT"));
+    assert_eq!(field(&status, "UPSNAME"), Some("ups"));
+    assert_eq!(
+        events,
+        vec!["2026-03-01 09:00:00 -0300  Power failure.\n".to_string()]
+    );
+    assert_eq!(invalid, vec!["Invalid command\n".to_string()]);
+}
@@ -0,0 +1,393 @@
+//! Read-only server for the NUT network protocol (what `upsd` speaks on
+//! 3493), so `upsc`, `upsmon` and NUT-aware NAS boxes can watch this UPS.
+//!
+//! Vars carry their standard NUT names. `SET`, `INSTCMD`, `FSD` and
+//! `PRIMARY` are refused: nothing here can reach the device.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use anyhow::Result;
+use nobreak_core::Snapshot;
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_stream::Stream;
+
+use crate::net;
+use crate::power::{Live, LiveReceiver};
+use crate::settings::NutSink;
+
+/// Longer lines close the connection; real requests are a few dozen bytes.
+const MAX_LINE: u64 = 1024;
+
+const NETVER: &str = "1.3";
+
+/// Snapshot vars and the NUT names they are served under.
+const VAR_MAP: &[(&str, &str)] = &[
+    ("battery.charge", "cBattery"),
+    ("battery.runtime", "runtimeRemainingSec"),
+    ("battery.voltage", "vBattery"),
+    ("input.voltage", "vInput"),
+    ("input.voltage.nominal", "vInputNominal"),
+    ("output.frequency", "fOutput"),
+    ("output.voltage", "vOutput"),
+    ("ups.load", "pOutput"),
+    ("ups.realpower", "pOutputWatts"),
+    ("ups.temperature", "temperature"),
+];
+
+/// `ups.status` flags: `OL` or `OB`, then `LB` and `OVER` when active.
+pub fn ups_status(live: &Live) -> String {
+    let power = &live.power;
+    let mut flags = vec![if power.on_battery() { "OB" } else { "OL" }];
+    if power.battery_low {
+        flags.push("LB");
+    }
+    if power.overload {
+        flags.push("OVER");
+    }
+    flags.join(" ")
+}
+
+/// Every var served for this state, sorted by name. Held vars are left out,
+/// as the other sinks do.
+pub fn variables(live: &Live) -> Vec<(&'static str, String)> {
+    let snapshot = &live.snapshot;
+    let model = snapshot.device.model.clone();
+    let mut vars = vec![
+        ("device.mfr", "RagTech".to_string()),
+        ("device.model", model.clone()),
+        ("device.type", "ups".to_string()),
+        ("driver.name", "nobreakd".to_string()),
+        ("driver.version", env!("CARGO_PKG_VERSION").to_string()),
+        ("ups.mfr", "RagTech".to_string()),
+        ("ups.model", model),
+        ("ups.status", ups_status(live)),
+    ];
+    for (name, var) in VAR_MAP {
+        if let Some(value) = fresh_number(snapshot, var) {
+            // NUT clients expect whole seconds here.
+            let value = if *name == "battery.runtime" {
+                format!("{:.0}", value.as_f64().unwrap_or_default())
+            } else {
+                value.to_string()
+            };
+            vars.push((name, value));
+        }
+    }
+    vars.sort_by_key(|(name, _)| *name);
+    vars
+}
+
+fn fresh_number<'a>(snapshot: &'a Snapshot, var: &str) -> Option<&'a serde_json::Number> {
+    if snapshot.vars_meta.contains_key(var) {
+        return None;
+    }
+    match snapshot.vars.get(var) {
+        Some(Value::Number(n)) => Some(n),
+        _ => None,
+    }
+}
+
+/// State shared by every connection.
+pub struct Upsd {
+    config: NutSink,
+    logins: AtomicUsize,
+}
+
+/// One reply, already terminated by `\n`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reply {
+    pub text: String,
+    pub close: bool,
+}
+
+impl Reply {
+    fn lines(lines: &[String]) -> Self {
+        let mut text = lines.join("\n");
+        text.push('\n');
+        Self { text, close: false }
+    }
+
+    fn line(line: impl Into<String>) -> Self {
+        Self::lines(&[line.into()])
+    }
+
+    fn err(code: &str) -> Self {
+        Self::line(format!("ERR {code}"))
+    }
+}
+
+/// Per-connection login state.
+#[derive(Debug, Default)]
+pub struct Session {
+    username: Option<String>,
+    password: Option<String>,
+    logged_in: bool,
+}
+
+impl Upsd {
+    pub fn new(config: NutSink) -> Self {
+        Self {
+            config,
+            logins: AtomicUsize::new(0),
+        }
+    }
+
+    /// Answers one request line. `live` is `None` until the first snapshot.
+    pub fn handle(&self, session: &mut Session, live: Option<&Live>, line: &str) -> Reply {
+        let Some(args) = split(line) else {
+            return Reply::err("INVALID-ARGUMENT");
+        };
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let Some((command, rest)) = args.split_first() else {
+            return Reply::err("UNKNOWN-COMMAND");
+        };
+        match (command.to_ascii_uppercase().as_str(), rest) {
+            ("HELP", []) => {
+                Reply::line("Commands: HELP VER NETVER GET LIST USERNAME PASSWORD LOGIN LOGOUT")
+            }
+            ("VER", []) => Reply::line(format!(
+                "nobreakd {} (NUT network protocol {NETVER}, read-only)",
+                env!("CARGO_PKG_VERSION")
+            )),
+            ("NETVER", []) => Reply::line(NETVER),
+            ("LIST", rest) => self.list(live, rest),
+            ("GET", rest) => self.get(live, rest),
+            ("USERNAME", [name]) => match session.username {
+                Some(_) => Reply::err("ALREADY-SET-USERNAME"),
+                None => {
+                    session.username = Some(name.to_string());
+                    Reply::line("OK")
+                }
+            },
+            ("PASSWORD", [password]) => match session.password {
+                Some(_) => Reply::err("ALREADY-SET-PASSWORD"),
+                None => {
+                    session.password = Some(password.to_string());
+                    Reply::line("OK")
+                }
+            },
+            ("LOGIN", [ups]) => self.login(session, ups),
+            ("LOGOUT", []) => Reply {
+                text: "OK Goodbye\n".to_string(),
+                close: true,
+            },
+            ("SET" | "INSTCMD" | "FSD" | "PRIMARY" | "MASTER", _) => Reply::err("ACCESS-DENIED"),
+            ("STARTTLS", []) => Reply::err("FEATURE-NOT-CONFIGURED"),
+            (
+                "HELP" | "VER" | "NETVER" | "USERNAME" | "PASSWORD" | "LOGIN" | "LOGOUT"
+                | "STARTTLS",
+                _,
+            ) => Reply::err("INVALID-ARGUMENT"),
+            _ => Reply::err("UNKNOWN-COMMAND"),
+        }
+    }
+
+    /// Drops the session's login from `NUMLOGINS`.
+    pub fn end(&self, session: &mut Session) {
+        if std::mem::take(&mut session.logged_in) {
+            self.logins.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    fn login(&self, session: &mut Session, ups: &str) -> Reply {
+        if session.logged_in {
+            return Reply::err("ALREADY-LOGGED-IN");
+        }
+        let (Some(username), Some(password)) = (&session.username, &session.password) else {
+            return Reply::err(if session.username.is_none() {
+                "USERNAME-REQUIRED"
+            } else {
+                "PASSWORD-REQUIRED"
+            });
+        };
+        if let (Some(want_user), Some(want_password)) =
+            (&self.config.username, &self.config.password)
+        {
+            if username != want_user || password != want_password {
+                return Reply::err("ACCESS-DENIED");
+            }
+        }
+        if ups != self.config.ups_name {
+            return Reply::err("UNKNOWN-UPS");
+        }
+        session.logged_in = true;
+        self.logins.fetch_add(1, Ordering::Relaxed);
+        Reply::line("OK")
+    }
+
+    fn list(&self, live: Option<&Live>, args: &[&str]) -> Reply {
+        let ups = &self.config.ups_name;
+        match args {
+            ["UPS"] => Reply::lines(&[
+                "BEGIN LIST UPS".to_string(),
+                format!("UPS {ups} \"{}\"", quote(&self.description(live))),
+                "END LIST UPS".to_string(),
+            ]),
+            ["VAR", name] => self.with_data(name, live, |live| {
+                let mut lines = vec![format!("BEGIN LIST VAR {ups}")];
+                for (var, value) in variables(live) {
+                    lines.push(format!("VAR {ups} {var} \"{}\"", quote(&value)));
+                }
+                lines.push(format!("END LIST VAR {ups}"));
+                Reply::lines(&lines)
+            }),
+            // Nothing is writable and there are no commands.
+            [kind @ ("RW" | "CMD" | "CLIENT"), name] => self.with_ups(name, || {
+                Reply::lines(&[
+                    format!("BEGIN LIST {kind} {ups}"),
+                    format!("END LIST {kind} {ups}"),
+                ])
+            }),
+            [kind @ ("ENUM" | "RANGE"), name, var] => self.with_ups(name, || {
+                Reply::lines(&[
+                    format!("BEGIN LIST {kind} {ups} {var}"),
+                    format!("END LIST {kind} {ups} {var}"),
+                ])
+            }),
+            _ => Reply::err("INVALID-ARGUMENT"),
+        }
+    }
+
+    fn get(&self, live: Option<&Live>, args: &[&str]) -> Reply {
+        let ups = &self.config.ups_name;
+        match args {
+            ["VAR", name, var] => self.with_data(name, live, |live| {
+                match variables(live).into_iter().find(|(name, _)| name == var) {
+                    Some((_, value)) => {
+                        Reply::line(format!("VAR {ups} {var} \"{}\"", quote(&value)))
+                    }
+                    None => Reply::err("VAR-NOT-SUPPORTED"),
+                }
+            }),
+            ["TYPE", name, var] => self.with_data(name, live, |live| {
+                match variables(live).into_iter().find(|(name, _)| name == var) {
+                    Some((_, value)) if value.parse::<f64>().is_ok() => {
+                        Reply::line(format!("TYPE {ups} {var} NUMBER"))
+                    }
+                    Some(_) => Reply::line(format!("TYPE {ups} {var} STRING:64")),
+                    None => Reply::err("VAR-NOT-SUPPORTED"),
+                }
+            }),
+            ["DESC", name, var] => self.with_ups(name, || {
+                Reply::line(format!("DESC {ups} {var} \"Description unavailable\""))
+            }),
+            ["UPSDESC", name] => self.with_ups(name, || {
+                Reply::line(format!(
+                    "UPSDESC {ups} \"{}\"",
+                    quote(&self.description(live))
+                ))
+            }),
+            ["NUMLOGINS", name] => self.with_ups(name, || {
+                Reply::line(format!(
+                    "NUMLOGINS {ups} {}",
+                    self.logins.load(Ordering::Relaxed)
+                ))
+            }),
+            _ => Reply::err("INVALID-ARGUMENT"),
+        }
+    }
+
+    fn with_ups(&self, name: &str, reply: impl FnOnce() -> Reply) -> Reply {
+        if name == self.config.ups_name {
+            reply()
+        } else {
+            Reply::err("UNKNOWN-UPS")
+        }
+    }
+
+    /// Like `upsd` with a silent driver: no snapshot yet, or a disconnected
+    /// or stale device, is an error rather than old values.
+    fn with_data(
+        &self,
+        name: &str,
+        live: Option<&Live>,
+        reply: impl FnOnce(&Live) -> Reply,
+    ) -> Reply {
+        self.with_ups(name, || match live {
+            None => Reply::err("DRIVER-NOT-CONNECTED"),
+            Some(live) if !live.reachable() => Reply::err("DATA-STALE"),
+            Some(live) => reply(live),
+        })
+    }
+
+    fn description(&self, live: Option<&Live>) -> String {
+        match live {
+            Some(live) => format!("{} via nobreakd", live.snapshot.device.model),
+            None => "nobreakd".to_string(),
+        }
+    }
+}
+
+/// Splits a request into words; double quotes group, backslash escapes.
+/// `None` for an unterminated quote.
+pub fn split(line: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut chars = line.trim_end_matches(['\r', '\n']).chars();
+    let mut word: Option<String> = None;
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => word.get_or_insert_with(String::new).push(chars.next()?),
+            '"' => {
+                quoted = !quoted;
+                word.get_or_insert_with(String::new);
+            }
+            c if c.is_whitespace() && !quoted => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quoted {
+        return None;
+    }
+    words.extend(word);
+    Some(words)
+}
+
+fn quote(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+pub async fn run_nut(
+    config: NutSink,
+    snapshots: impl Stream<Item = Snapshot> + Unpin,
+    listener: TcpListener,
+) -> Result<()> {
+    let upsd = Arc::new(Upsd::new(config));
+    net::serve_clients("nut", snapshots, listener, |live, stream| {
+        let upsd = upsd.clone();
+        async move { serve_client(&upsd, live, stream).await }
+    })
+    .await;
+    Ok(())
+}
+
+async fn serve_client(upsd: &Upsd, live: LiveReceiver, stream: TcpStream) -> Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut read = BufReader::new(read);
+    let mut session = Session::default();
+    let mut line = String::new();
+    let result = async {
+        loop {
+            line.clear();
+            let n = (&mut read).take(MAX_LINE).read_line(&mut line).await?;
+            if n == 0 {
+                return Ok(());
+            }
+            if !line.ends_with('\n') {
+                anyhow::bail!("request line too long");
+            }
+            let current = live.borrow().clone();
+            let reply = upsd.handle(&mut session, current.as_deref(), &line);
+            write.write_all(reply.text.as_bytes()).await?;
+            if reply.close {
+                return Ok(());
+            }
+        }
+    }
+    .await;
+    upsd.end(&mut session);
+    result
+}
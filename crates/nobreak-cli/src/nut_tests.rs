@@ -0,0 +1,197 @@
+use nobreak_core::{EventPhase, PowerEventKind};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use crate::fixtures::{event, feed, live, loopback, snapshot, until_ready};
+use crate::nut::{run_nut, split, ups_status, variables, Session, Upsd};
+use crate::power::{Live, PowerState};
+use crate::settings::NutSink;
+
+#[test]
+fn ups_status_follows_power_events() {
+    // Arrange
+    let mut power = PowerState::default();
+    let mut lost = snapshot();
+    lost.events = vec![
+        event(PowerEventKind::MainsLost, EventPhase::Start),
+        event(PowerEventKind::BatteryLow, EventPhase::Start),
+    ];
+    let mut restored = snapshot();
+    restored.events = vec![
+        event(PowerEventKind::MainsRestored, EventPhase::End),
+        event(PowerEventKind::BatteryLow, EventPhase::End),
+    ];
+
+    // Act
+    let online = ups_status(&live(snapshot(), &mut power));
+    let on_battery = ups_status(&live(lost, &mut power));
+    let still_on_battery = ups_status(&live(snapshot(), &mut power));
+    let back = ups_status(&live(restored, &mut power));
+
+    // Assert
+    assert_eq!(online, "OL");
+    assert_eq!(on_battery, "OB LB");
+    assert_eq!(
+        still_on_battery, "OB LB",
+        "no event on this tick, state holds"
+    );
+    assert_eq!(back, "OL");
+    assert_eq!(power.transfers, 1);
+}
+
+#[test]
+fn vars_use_standard_nut_names() {
+    // Arrange
+    let mut held = snapshot();
+    held.vars_meta.insert(
+        "temperature".to_string(),
+        nobreak_core::VarMeta {
+            age_ms: 3000,
+            held: true,
+        },
+    );
+
+    // Act
+    let vars = variables(&live(held, &mut PowerState::default()));
+
+    // Assert
+    let get = |name: &str| {
+        vars.iter()
+            .find(|(var, _)| *var == name)
+            .map(|(_, value)| value.as_str())
+    };
+    assert_eq!(get("input.voltage"), Some("127.4"));
+    assert_eq!(get("battery.charge"), Some("100.0"));
+    assert_eq!(get("battery.runtime"), Some("1500"));
+    assert_eq!(get("ups.load"), Some("35.0"));
+    assert_eq!(get("ups.status"), Some("OL"));
+    assert_eq!(get("ups.model"), Some("RagTech 3200VA"));
+    assert_eq!(get("ups.temperature"), None, "held vars are not served");
+    assert!(vars.windows(2).all(|w| w[0].0 < w[1].0), "sorted by name");
+}
+
+#[test]
+fn answers_read_commands_and_refuses_writes() {
+    // Arrange
+    let upsd = Upsd::new(NutSink::default());
+    let current = live(snapshot(), &mut PowerState::default());
+    let mut stale = current.clone();
+    stale.snapshot.freshness.stale = true;
+    let mut session = Session::default();
+    let mut ask = |live: Option<&Live>, line: &str| upsd.handle(&mut session, live, line).text;
+
+    // Act
+    let list_ups = ask(Some(&current), "LIST UPS\n");
+    let get = ask(Some(&current), "GET VAR ups input.voltage\n");
+    let unknown_var = ask(Some(&current), "GET VAR ups battery.temperature\n");
+    let unknown_ups = ask(Some(&current), "GET VAR other input.voltage\n");
+    let stale_get = ask(Some(&stale), "GET VAR ups input.voltage\n");
+    let no_data = ask(None, "LIST VAR ups\n");
+    let set = ask(Some(&current), "SET VAR ups ups.delay.shutdown 30\n");
+    let instcmd = ask(Some(&current), "INSTCMD ups shutdown.return\n");
+    let list_var = ask(Some(&current), "LIST VAR ups\n");
+
+    // Assert
+    assert_eq!(
+        list_ups,
+        "BEGIN LIST UPS\nUPS ups \"RagTech 3200VA via nobreakd\"\nEND LIST UPS\n"
+    );
+    assert_eq!(get, "VAR ups input.voltage \"127.4\"\n");
+    assert_eq!(unknown_var, "ERR VAR-NOT-SUPPORTED\n");
+    assert_eq!(unknown_ups, "ERR UNKNOWN-UPS\n");
+    assert_eq!(stale_get, "ERR DATA-STALE\n");
+    assert_eq!(no_data, "ERR DRIVER-NOT-CONNECTED\n");
+    assert_eq!(set, "ERR ACCESS-DENIED\n");
+    assert_eq!(instcmd, "ERR ACCESS-DENIED\n");
+    assert!(list_var.starts_with("BEGIN LIST VAR ups\n"));
+    assert!(list_var.contains("VAR ups ups.status \"OL\"\n"));
+    assert!(list_var.ends_with("END LIST VAR ups\n"));
+}
+
+#[test]
+fn login_needs_credentials_and_counts_sessions() {
+    // Arrange
+    let upsd = Upsd::new(NutSink {
+        username: Some("monuser".to_string()),
+        password: Some("secret".to_string()),
+        ..NutSink::default()
+    });
+    let (mut good, mut bad) = (Session::default(), Session::default());
+
+    // Act
+    let early = upsd.handle(&mut good, None, "LOGIN ups").text;
+    for line in ["USERNAME monuser", "PASSWORD secret"] {
+        upsd.handle(&mut good, None, line);
+    }
+    let login = upsd.handle(&mut good, None, "LOGIN ups").text;
+    let again = upsd.handle(&mut good, None, "LOGIN ups").text;
+    for line in ["USERNAME monuser", "PASSWORD \"wrong one\""] {
+        upsd.handle(&mut bad, None, line);
+    }
+    let denied = upsd.handle(&mut bad, None, "LOGIN ups").text;
+    let primary = upsd.handle(&mut good, None, "PRIMARY ups").text;
+    let logins = upsd.handle(&mut bad, None, "GET NUMLOGINS ups").text;
+    upsd.end(&mut good);
+    let after = upsd.handle(&mut bad, None, "GET NUMLOGINS ups").text;
+
+    // Assert
+    assert_eq!(early, "ERR USERNAME-REQUIRED\n");
+    assert_eq!(login, "OK\n");
+    assert_eq!(again, "ERR ALREADY-LOGGED-IN\n");
+    assert_eq!(denied, "ERR ACCESS-DENIED\n");
+    assert_eq!(primary, "ERR ACCESS-DENIED\n");
+    assert_eq!(logins, "NUMLOGINS ups 1\n");
+    assert_eq!(after, "NUMLOGINS ups 0\n");
+}
+
+#[test]
+fn requests_split_like_upsd() {
+    assert_eq!(
+        split("SET VAR ups ups.id \"rack \\\"A\\\"\"\r\n"),
+        Some(vec![
+            "SET".to_string(),
+            "VAR".to_string(),
+            "ups".to_string(),
+            "ups.id".to_string(),
+            "rack \"A\"".to_string(),
+        ])
+    );
+    assert_eq!(
+        split("GET VAR \"\" x"),
+        Some(vec!["GET".into(), "VAR".into(), String::new(), "x".into()])
+    );
+    assert_eq!(split("GET \"open"), None);
+}
+
+#[tokio::test]
+async fn serves_upsc_over_tcp() {
+    // Arrange
+    let (listener, addr) = loopback().await;
+    let server = tokio::spawn(run_nut(NutSink::default(), feed(snapshot()), listener));
+
+    // Act
+    let (reply, bye, closed) = until_ready(|| async move {
+        let stream = TcpStream::connect(addr).await.expect("connect");
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        write
+            .write_all(b"GET VAR ups battery.charge\n")
+            .await
+            .expect("write");
+        let reply = lines.next_line().await.expect("read").expect("line");
+        if reply == "ERR DRIVER-NOT-CONNECTED" {
+            return None;
+        }
+        write.write_all(b"LOGOUT\n").await.expect("write");
+        let bye = lines.next_line().await.expect("read").expect("line");
+        let closed = lines.next_line().await.expect("read");
+        Some((reply, bye, closed))
+    })
+    .await;
+    server.abort();
+
+    // Assert
+    assert_eq!(reply, "VAR ups battery.charge \"100.0\"");
+    assert_eq!(bye, "OK Goodbye");
+    assert_eq!(closed, None);
+}
@@ -0,0 +1,106 @@
+//! Power state for the protocol servers, which answer "on battery?" at any
+//! time while snapshots only carry events on the tick they start or end.
+//!
+//! [`PowerState`] replays those edges, so a server has to follow the feed
+//! from startup; [`follow`] does that and keeps the latest snapshot with it.
+
+use std::future::Future;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use nobreak_core::{EventPhase, PowerEventKind, Snapshot};
+use tokio::sync::watch;
+use tokio_stream::{Stream, StreamExt};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PowerState {
+    /// Start of the current mains loss, if any.
+    pub on_battery_since: Option<DateTime<Utc>>,
+    pub battery_low: bool,
+    pub overload: bool,
+    pub brownout: bool,
+    pub overvoltage: bool,
+    /// Mains losses seen since startup.
+    pub transfers: u64,
+    pub last_transfer: Option<DateTime<Utc>>,
+    pub last_return: Option<DateTime<Utc>>,
+    /// Time on battery in finished mains losses since startup.
+    pub battery_ms: u64,
+}
+
+impl PowerState {
+    pub fn observe(&mut self, snapshot: &Snapshot) {
+        for event in &snapshot.events {
+            let start = event.phase == EventPhase::Start;
+            match event.kind {
+                PowerEventKind::MainsLost if start => self.transfer(event.started_at),
+                PowerEventKind::MainsRestored => {
+                    self.on_battery_since = None;
+                    self.last_return = event.ended_at;
+                    self.battery_ms += event.duration_ms.unwrap_or_default();
+                }
+                PowerEventKind::BatteryLow => self.battery_low = start,
+                PowerEventKind::Overload => self.overload = start,
+                PowerEventKind::Brownout => self.brownout = start,
+                PowerEventKind::Overvoltage => self.overvoltage = start,
+                _ => {}
+            }
+        }
+    }
+
+    fn transfer(&mut self, at: DateTime<Utc>) {
+        self.on_battery_since = Some(at);
+        self.last_transfer = Some(at);
+        self.transfers += 1;
+    }
+
+    pub fn on_battery(&self) -> bool {
+        self.on_battery_since.is_some()
+    }
+}
+
+/// The latest snapshot and the power state after it.
+#[derive(Debug, Clone)]
+pub struct Live {
+    pub snapshot: Snapshot,
+    pub power: PowerState,
+}
+
+impl Live {
+    /// The device answers and the data is fresh.
+    pub fn reachable(&self) -> bool {
+        self.snapshot.device.connected && !self.snapshot.freshness.stale
+    }
+
+    /// A numeric var, unless it is held.
+    pub fn fresh(&self, var: &str) -> Option<f64> {
+        if self.snapshot.vars_meta.contains_key(var) {
+            return None;
+        }
+        self.snapshot
+            .vars
+            .get(var)
+            .and_then(serde_json::Value::as_f64)
+    }
+}
+
+pub type LiveReceiver = watch::Receiver<Option<Arc<Live>>>;
+
+/// A receiver of the latest [`Live`] state and the task that keeps it
+/// current; the task runs until the snapshots end.
+pub fn follow(
+    mut snapshots: impl Stream<Item = Snapshot> + Unpin,
+) -> (LiveReceiver, impl Future<Output = ()>) {
+    let (tx, rx) = watch::channel(None);
+    let pump = async move {
+        let mut power = PowerState::default();
+        while let Some(snapshot) = snapshots.next().await {
+            power.observe(&snapshot);
+            tx.send_replace(Some(Arc::new(Live {
+                snapshot,
+                power: power.clone(),
+            })));
+        }
+    };
+    (rx, pump)
+}
@@ -0,0 +1,785 @@
+//! `nobreakd.toml` and how it layers with flags and `NOBREAK_*` env vars.
+//!
+//! Precedence is flag, then env (both resolved by clap), then the file, then
+//! the built-in defaults. Side files passed by flag (`--alert-rules`,
+//! `--tariff`, `--plausibility-limits`, `--filters`) replace or extend the
+//! matching file section.
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use nobreak_core::{
+    AlertRule, AlertRuleSet, BatteryHealthConfig, EnergyConfig, EventThresholds, FilterConfig,
+    MetricLimits, ModelProfile, MonitorConfig, PlausibilityConfig, PqStatsConfig, ProdistConfig,
+    Tariff,
+};
+use serde::Deserialize;
+
+use crate::{Cli, OutputFormat};
+
+pub const DEFAULT_CONFIG_PATH: &str = "/etc/nobreak/nobreakd.toml";
+pub const DEFAULT_HTTP_LISTEN: &str = "127.0.0.1:9750";
+pub const DEFAULT_NUT_LISTEN: &str = "127.0.0.1:3493";
+pub const DEFAULT_NIS_LISTEN: &str = "127.0.0.1:3551";
+pub const DEFAULT_SNMP_LISTEN: &str = "127.0.0.1:1161";
+/// 502 is privileged; put a redirect or `CAP_NET_BIND_SERVICE` in front for it.
+pub const DEFAULT_MODBUS_LISTEN: &str = "127.0.0.1:5020";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    pub device: DeviceSection,
+    pub state_dir: Option<String>,
+    /// Applies to both energy and power-quality buckets.
+    pub utc_offset_minutes: Option<i32>,
+    pub monitor: MonitorSection,
+    pub events: EventThresholds,
+    pub profile: ModelProfile,
+    pub plausibility: PlausibilityConfig,
+    pub filters: FilterConfig,
+    pub battery_health: BatteryHealthConfig,
+    pub energy: EnergyConfig,
+    pub prodist: ProdistConfig,
+    pub pq_stats: PqStatsConfig,
+    pub alerts: Vec<AlertRule>,
+    pub sinks: SinksSection,
+}
+
+/// The `--plausibility-limits` file. Unlike `[plausibility]`, a key it leaves
+/// out keeps the config file's value instead of the default.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct PlausibilityOverrides {
+    limits: BTreeMap<String, MetricLimits>,
+    spike_confirm_samples: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceSection {
+    pub vendor_dir: Option<String>,
+    pub id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MonitorSection {
+    pub interval_ms: Option<u64>,
+    pub interval_min_ms: Option<u64>,
+    pub interval_max_ms: Option<u64>,
+    pub stale_after_ms: Option<u64>,
+    pub disconnected_after_ms: Option<u64>,
+    pub poll_timeout_ms: Option<u64>,
+    pub error_threshold: Option<u32>,
+    pub auto_tune: Option<bool>,
+    pub power_aware_sampling: Option<bool>,
+    pub stable_after_sec: Option<u64>,
+    pub hold_last_good: Option<bool>,
+    pub quality_windows_sec: Option<Vec<u64>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SinksSection {
+    pub stdout: StdoutSink,
+    pub export: ExportSink,
+    pub http: HttpSink,
+    pub mqtt: MqttSink,
+    pub nut: NutSink,
+    pub nis: NisSink,
+    pub snmp: SnmpSink,
+    pub modbus: ModbusSink,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StdoutSink {
+    pub format: Option<OutputFormat>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExportSink {
+    /// Also export from `run`/`watch`/`view`, in the same process.
+    pub enabled: bool,
+    pub output_dir: Option<String>,
+    pub retention_days: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpSink {
+    /// Serve the HTTP API next to any streaming command.
+    pub enabled: bool,
+    pub listen: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttSink {
+    /// Publish to an MQTT broker next to any streaming command.
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Topics are `<topic_prefix>/<node_id>/<var>`.
+    pub topic_prefix: String,
+    /// Stable id for topics and Home Assistant unique ids.
+    pub node_id: String,
+    pub qos: u8,
+    /// Publish Home Assistant discovery configs.
+    pub discovery: bool,
+    pub discovery_prefix: String,
+}
+
+impl Default for MqttSink {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: "localhost".to_string(),
+            port: 1883,
+            client_id: "nobreakd".to_string(),
+            username: None,
+            password: None,
+            topic_prefix: "nobreak".to_string(),
+            node_id: "ups".to_string(),
+            qos: 1,
+            discovery: true,
+            discovery_prefix: "homeassistant".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NutSink {
+    /// Answer NUT clients (`upsc`, `upsmon`) next to any streaming command.
+    pub enabled: bool,
+    pub listen: Option<String>,
+    /// What clients call the UPS: `upsc <ups_name>@host`.
+    pub ups_name: String,
+    /// When both are set, `LOGIN` needs this `USERNAME`/`PASSWORD`.
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Default for NutSink {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: None,
+            ups_name: "ups".to_string(),
+            username: None,
+            password: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NisSink {
+    /// Answer apcupsd NIS clients (`apcaccess`) next to any streaming command.
+    pub enabled: bool,
+    pub listen: Option<String>,
+    /// `UPSNAME` in the status report.
+    pub ups_name: String,
+    /// Where `events` lines are kept; defaults to `nis-events.log` under
+    /// `state_dir`.
+    pub events_path: Option<PathBuf>,
+}
+
+impl Default for NisSink {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: None,
+            ups_name: "ups".to_string(),
+            events_path: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnmpSink {
+    /// Answer SNMP GETs for the UPS-MIB next to any streaming command.
+    pub enabled: bool,
+    pub listen: Option<String>,
+    /// The v1/v2c read community; empty answers v3 only.
+    pub community: String,
+    /// SNMPv2-Trap receivers, as `ip:port`.
+    pub traps: Vec<String>,
+    /// Defaults to `community`.
+    pub trap_community: Option<String>,
+    /// Send traps as SNMPv3 from this user instead of v2c.
+    pub trap_user: Option<String>,
+    /// SNMPv3 users, all read-only.
+    pub users: Vec<SnmpUser>,
+    /// `snmpEngineID` in hex; defaults to one derived from the hostname.
+    pub engine_id: Option<String>,
+    /// Where `snmpEngineBoots` is kept; defaults to `snmp-engine.json`
+    /// under `state_dir`.
+    pub engine_path: Option<PathBuf>,
+    /// `upsIdentName`.
+    pub ups_name: String,
+    pub location: String,
+    pub contact: String,
+}
+
+impl Default for SnmpSink {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: None,
+            community: "public".to_string(),
+            traps: Vec::new(),
+            trap_community: None,
+            trap_user: None,
+            users: Vec::new(),
+            engine_id: None,
+            engine_path: None,
+            ups_name: "ups".to_string(),
+            location: String::new(),
+            contact: String::new(),
+        }
+    }
+}
+
+/// An SNMPv3 user. Requests below the user's level are refused.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SnmpUser {
+    pub name: String,
+    #[serde(default)]
+    pub auth: SnmpAuth,
+    pub auth_password: String,
+    /// With `priv_password`, requests must be encrypted too.
+    #[serde(default, rename = "priv")]
+    pub privacy: Option<SnmpPriv>,
+    pub priv_password: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum SnmpAuth {
+    /// HMAC-SHA-96 (RFC 3414).
+    #[default]
+    #[serde(rename = "SHA")]
+    Sha,
+    /// HMAC-192-SHA-256 (RFC 7860).
+    #[serde(rename = "SHA-256")]
+    Sha256,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum SnmpPriv {
+    /// AES-128 in CFB mode (RFC 3826).
+    #[serde(rename = "AES")]
+    Aes,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModbusSink {
+    /// Serve the register map over Modbus TCP next to any streaming command.
+    pub enabled: bool,
+    pub listen: Option<String>,
+    /// Requests for other units get a gateway exception; 255 is always answered.
+    pub unit_id: u8,
+}
+
+impl Default for ModbusSink {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: None,
+            unit_id: 1,
+        }
+    }
+}
+
+impl SnmpSink {
+    pub fn trap_community(&self) -> &str {
+        self.trap_community.as_deref().unwrap_or(&self.community)
+    }
+}
+
+/// Everything a command needs, after layering.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub source: Option<PathBuf>,
+    pub vendor_dir: String,
+    pub device_id: Option<String>,
+    pub state_dir: Option<PathBuf>,
+    pub monitor: MonitorConfig,
+    pub sinks: SinksSection,
+}
+
+impl Settings {
+    pub fn export_dir(&self) -> String {
+        self.sinks
+            .export
+            .output_dir
+            .clone()
+            .unwrap_or_else(|| "./data/metrics".to_string())
+    }
+
+    pub fn retention_days(&self) -> u64 {
+        self.sinks.export.retention_days.unwrap_or(90)
+    }
+
+    /// Checked by [`validate`], so only fails on unvalidated settings.
+    pub fn http_listen(&self) -> Result<SocketAddr> {
+        listen_addr(
+            "http",
+            self.sinks.http.listen.as_deref(),
+            DEFAULT_HTTP_LISTEN,
+        )
+    }
+
+    /// Checked by [`validate`], so only fails on unvalidated settings.
+    pub fn nut_listen(&self) -> Result<SocketAddr> {
+        listen_addr("nut", self.sinks.nut.listen.as_deref(), DEFAULT_NUT_LISTEN)
+    }
+
+    /// Checked by [`validate`], so only fails on unvalidated settings.
+    pub fn nis_listen(&self) -> Result<SocketAddr> {
+        listen_addr("nis", self.sinks.nis.listen.as_deref(), DEFAULT_NIS_LISTEN)
+    }
+
+    /// Checked by [`validate`], so only fails on unvalidated settings.
+    pub fn snmp_listen(&self) -> Result<SocketAddr> {
+        listen_addr(
+            "snmp",
+            self.sinks.snmp.listen.as_deref(),
+            DEFAULT_SNMP_LISTEN,
+        )
+    }
+
+    /// Checked by [`validate`], so only fails on unvalidated settings.
+    pub fn modbus_listen(&self) -> Result<SocketAddr> {
+        listen_addr(
+            "modbus",
+            self.sinks.modbus.listen.as_deref(),
+            DEFAULT_MODBUS_LISTEN,
+        )
+    }
+
+    /// Checked by [`validate`], so only fails on unvalidated settings.
+    pub fn snmp_traps(&self) -> Result<Vec<SocketAddr>> {
+        self.sinks
+            .snmp
+            .traps
+            .iter()
+            .map(|target| {
+                target.parse().with_context(|| {
+                    format!("sinks.snmp.traps: {target:?} is not an ip:port address")
+                })
+            })
+            .collect()
+    }
+
+    pub fn stdout_format(&self) -> OutputFormat {
+        self.sinks.stdout.format.unwrap_or(OutputFormat::Human)
+    }
+
+    /// `next` with every setting that needs a restart kept at its running
+    /// value, and the sections where the two differed.
+    pub fn reloaded(&self, mut next: Settings) -> (Settings, Vec<&'static str>) {
+        let mut kept = Vec::new();
+        let running = &self.monitor;
+        let monitor = &mut next.monitor;
+        keep(&mut kept, "device", &mut next.vendor_dir, &self.vendor_dir);
+        keep(&mut kept, "device", &mut next.device_id, &self.device_id);
+        keep(&mut kept, "state_dir", &mut next.state_dir, &self.state_dir);
+        keep(
+            &mut kept,
+            "battery_health",
+            &mut monitor.battery_health,
+            &running.battery_health,
+        );
+        keep(
+            &mut kept,
+            "energy",
+            &mut monitor.energy.state_path,
+            &running.energy.state_path,
+        );
+        keep(
+            &mut kept,
+            "energy",
+            &mut monitor.energy.max_gap_sec,
+            &running.energy.max_gap_sec,
+        );
+        keep(
+            &mut kept,
+            "energy",
+            &mut monitor.energy.utc_offset_minutes,
+            &running.energy.utc_offset_minutes,
+        );
+        keep(&mut kept, "prodist", &mut monitor.prodist, &running.prodist);
+        monitor.events.nominal_v = monitor.prodist.nominal_v;
+        keep(
+            &mut kept,
+            "pq_stats",
+            &mut monitor.pq_stats,
+            &running.pq_stats,
+        );
+        keep(&mut kept, "sinks", &mut next.sinks, &self.sinks);
+        kept.dedup();
+        (next, kept)
+    }
+}
+
+fn keep<T: Clone + PartialEq>(
+    kept: &mut Vec<&'static str>,
+    section: &'static str,
+    next: &mut T,
+    running: &T,
+) {
+    if next != running {
+        kept.push(section);
+        next.clone_from(running);
+    }
+}
+
+/// Reads `--config`, or the default path when it exists.
+pub fn load_file(cli: &Cli) -> Result<(Option<PathBuf>, FileConfig)> {
+    let path = match &cli.config {
+        Some(path) => PathBuf::from(path),
+        None if Path::new(DEFAULT_CONFIG_PATH).exists() => PathBuf::from(DEFAULT_CONFIG_PATH),
+        None => return Ok((None, FileConfig::default())),
+    };
+    let raw =
+        std::fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+    let file = parse_file(&raw).with_context(|| format!("parsing {}", path.display()))?;
+    Ok((Some(path), file))
+}
+
+pub fn parse_file(raw: &str) -> Result<FileConfig> {
+    Ok(toml::from_str(raw)?)
+}
+
+pub fn load(cli: &Cli) -> Result<Settings> {
+    let (source, file) = load_file(cli)?;
+    let settings = resolve(cli, file, source)?;
+    validate(&settings)?;
+    Ok(settings)
+}
+
+pub fn resolve(cli: &Cli, file: FileConfig, source: Option<PathBuf>) -> Result<Settings> {
+    let defaults = MonitorConfig::default();
+    let m = &file.monitor;
+    let millis = |flag: Option<u64>, file: Option<u64>, default: Duration| {
+        flag.or(file).map(Duration::from_millis).unwrap_or(default)
+    };
+
+    let state_dir = cli
+        .state_dir
+        .clone()
+        .or(file.state_dir.clone())
+        .map(PathBuf::from);
+    let state_path = |explicit: Option<PathBuf>, name: &str| {
+        explicit.or_else(|| state_dir.as_ref().map(|dir| dir.join(name)))
+    };
+    let utc_offset = cli.utc_offset_minutes.or(file.utc_offset_minutes);
+
+    let mut alerts = file.alerts;
+    if let Some(path) = cli.alert_rules.as_deref() {
+        alerts = load_alert_rules(path)?.alerts;
+    }
+
+    let mut plausibility = PlausibilityConfig::default();
+    plausibility.limits.extend(file.plausibility.limits);
+    plausibility.spike_confirm_samples = file.plausibility.spike_confirm_samples;
+    if let Some(path) = cli.plausibility_limits.as_deref() {
+        let overrides: PlausibilityOverrides = load_toml(path)?;
+        plausibility.limits.extend(overrides.limits);
+        if let Some(samples) = overrides.spike_confirm_samples {
+            plausibility.spike_confirm_samples = samples;
+        }
+    }
+
+    let mut filters = file.filters;
+    if let Some(path) = cli.filters.as_deref() {
+        let overrides: FilterConfig = load_toml(path)?;
+        filters.vars.extend(overrides.vars);
+    }
+
+    let mut energy = file.energy;
+    energy.state_path = state_path(energy.state_path, "energy.json");
+    energy.utc_offset_minutes = utc_offset.unwrap_or(energy.utc_offset_minutes);
+    if let Some(path) = cli.tariff.as_deref() {
+        energy.tariff = Some(load_toml::<Tariff>(path)?);
+    }
+
+    let mut pq_stats = file.pq_stats;
+    pq_stats.state_path = state_path(pq_stats.state_path, "power-quality.json");
+    pq_stats.utc_offset_minutes = utc_offset.unwrap_or(pq_stats.utc_offset_minutes);
+
+    let mut prodist = file.prodist;
+    prodist.state_path = state_path(prodist.state_path, "prodist.json");
+    prodist.nominal_v = cli.nominal_input_v.or(prodist.nominal_v);
+
+    let mut events = file.events;
+    events.nominal_v = prodist.nominal_v;
+
+    let mut battery_health = file.battery_health;
+    battery_health.state_path = state_path(battery_health.state_path, "battery-health.json");
+
+    let monitor = MonitorConfig {
+        sample_interval: millis(cli.interval_ms, m.interval_ms, defaults.sample_interval),
+        sample_interval_min: millis(
+            cli.interval_min_ms,
+            m.interval_min_ms,
+            defaults.sample_interval_min,
+        ),
+        sample_interval_max: millis(
+            cli.interval_max_ms,
+            m.interval_max_ms,
+            defaults.sample_interval_max,
+        ),
+        stale_after: millis(cli.stale_after_ms, m.stale_after_ms, defaults.stale_after),
+        disconnected_after: millis(
+            cli.disconnected_after_ms,
+            m.disconnected_after_ms,
+            defaults.disconnected_after,
+        ),
+        poll_timeout: millis(
+            cli.poll_timeout_ms,
+            m.poll_timeout_ms,
+            defaults.poll_timeout,
+        ),
+        error_threshold: cli
+            .error_threshold
+            .or(m.error_threshold)
+            .unwrap_or(defaults.error_threshold),
+        auto_tune: cli
+            .no_auto_tune
+            .map(|off| !off)
+            .or(m.auto_tune)
+            .unwrap_or(defaults.auto_tune),
+        power_aware_sampling: cli
+            .no_power_aware_sampling
+            .map(|off| !off)
+            .or(m.power_aware_sampling)
+            .unwrap_or(defaults.power_aware_sampling),
+        stable_after: cli
+            .stable_after_sec
+            .or(m.stable_after_sec)
+            .map(Duration::from_secs)
+            .unwrap_or(defaults.stable_after),
+        hold_last_good: cli
+            .hold_last_good
+            .or(m.hold_last_good)
+            .unwrap_or(defaults.hold_last_good),
+        quality_windows: cli
+            .quality_windows_sec
+            .clone()
+            .or(m.quality_windows_sec.clone())
+            .map(|secs| secs.into_iter().map(Duration::from_secs).collect())
+            .unwrap_or(defaults.quality_windows),
+        plausibility,
+        filters,
+        events,
+        alerts,
+        profile: file.profile,
+        battery_health,
+        energy,
+        prodist,
+        pq_stats,
+    };
+
+    let mut sinks = file.sinks;
+    if let Some(listen) = &cli.http_listen {
+        sinks.http.enabled = true;
+        sinks.http.listen = Some(listen.clone());
+    }
+    if let Some(host) = &cli.mqtt_host {
+        sinks.mqtt.enabled = true;
+        sinks.mqtt.host = host.clone();
+    }
+    if let Some(password) = &cli.mqtt_password {
+        sinks.mqtt.password = Some(password.clone());
+    }
+    if let Some(listen) = &cli.nut_listen {
+        sinks.nut.enabled = true;
+        sinks.nut.listen = Some(listen.clone());
+    }
+    if let Some(listen) = &cli.nis_listen {
+        sinks.nis.enabled = true;
+        sinks.nis.listen = Some(listen.clone());
+    }
+    sinks.nis.events_path = state_path(sinks.nis.events_path.take(), "nis-events.log");
+    sinks.snmp.engine_path = state_path(sinks.snmp.engine_path.take(), "snmp-engine.json");
+    if let Some(listen) = &cli.snmp_listen {
+        sinks.snmp.enabled = true;
+        sinks.snmp.listen = Some(listen.clone());
+    }
+    if let Some(community) = &cli.snmp_community {
+        sinks.snmp.community = community.clone();
+    }
+    if let Some(listen) = &cli.modbus_listen {
+        sinks.modbus.enabled = true;
+        sinks.modbus.listen = Some(listen.clone());
+    }
+
+    Ok(Settings {
+        source,
+        vendor_dir: cli
+            .vendor_dir
+            .clone()
+            .or(file.device.vendor_dir)
+            .unwrap_or_else(|| "./vendor".to_string()),
+        device_id: cli.device_id.clone().or(file.device.id),
+        state_dir,
+        monitor,
+        sinks,
+    })
+}
+
+/// Semantic checks beyond what parsing already enforces.
+pub fn validate(settings: &Settings) -> Result<()> {
+    let m = &settings.monitor;
+    if m.sample_interval_min.is_zero() {
+        bail!("monitor.interval_min_ms must be positive");
+    }
+    if m.sample_interval_min > m.sample_interval_max {
+        bail!("monitor.interval_min_ms must not exceed monitor.interval_max_ms");
+    }
+    if m.sample_interval < m.sample_interval_min || m.sample_interval > m.sample_interval_max {
+        bail!("monitor.interval_ms must lie within interval_min_ms..=interval_max_ms");
+    }
+    if m.poll_timeout.is_zero() {
+        bail!("monitor.poll_timeout_ms must be positive");
+    }
+    if m.stale_after > m.disconnected_after {
+        bail!("monitor.stale_after_ms must not exceed monitor.disconnected_after_ms");
+    }
+    if m.error_threshold == 0 {
+        bail!("monitor.error_threshold must be positive");
+    }
+    if m.quality_windows.is_empty() || m.quality_windows.iter().any(|w| w.is_zero()) {
+        bail!("monitor.quality_windows_sec must list positive windows");
+    }
+    if m.profile.rated_w <= 0.0 || m.profile.battery.runtime_curve.is_empty() {
+        bail!("profile needs a positive rated_w and a runtime_curve");
+    }
+    if let Some(tariff) = &m.energy.tariff {
+        if let Some(band) = tariff
+            .bands
+            .iter()
+            .find(|band| band.start_hour > 23 || band.end_hour > 24)
+        {
+            bail!("tariff band {}: hours must be within 0..=24", band.name);
+        }
+    }
+    settings.http_listen()?;
+    let mqtt = &settings.sinks.mqtt;
+    if mqtt.qos > 2 {
+        bail!("sinks.mqtt.qos must be 0, 1 or 2");
+    }
+    for (key, value) in [
+        ("topic_prefix", &mqtt.topic_prefix),
+        ("node_id", &mqtt.node_id),
+    ] {
+        if value.is_empty() || value.contains(['+', '#']) || value.starts_with('/') {
+            bail!("sinks.mqtt.{key} must be a non-empty topic without wildcards or a leading /");
+        }
+    }
+    if mqtt.node_id.contains('/') {
+        bail!("sinks.mqtt.node_id must be a single topic level");
+    }
+    settings.nut_listen()?;
+    let nut = &settings.sinks.nut;
+    if nut.ups_name.is_empty()
+        || !nut
+            .ups_name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+    {
+        bail!("sinks.nut.ups_name must be a non-empty name of letters, digits, '-', '_' or '.'");
+    }
+    if nut.username.is_some() != nut.password.is_some() {
+        bail!("sinks.nut needs both username and password, or neither");
+    }
+    settings.nis_listen()?;
+    settings.snmp_listen()?;
+    settings.snmp_traps()?;
+    let snmp = &settings.sinks.snmp;
+    if snmp.community.is_empty() && snmp.users.is_empty() {
+        bail!("sinks.snmp.community must not be empty without sinks.snmp.users");
+    }
+    if snmp.trap_user.is_none() && !snmp.traps.is_empty() && snmp.trap_community().is_empty() {
+        bail!("sinks.snmp.traps need a trap_community or a trap_user");
+    }
+    for (i, user) in snmp.users.iter().enumerate() {
+        if user.name.is_empty() || user.name.len() > 32 {
+            bail!("sinks.snmp.users: names must be 1 to 32 bytes long");
+        }
+        if snmp.users[..i].iter().any(|other| other.name == user.name) {
+            bail!("sinks.snmp.users: {:?} is listed twice", user.name);
+        }
+        if user.privacy.is_some() != user.priv_password.is_some() {
+            bail!(
+                "sinks.snmp.users: {:?} needs both priv and priv_password, or neither",
+                user.name
+            );
+        }
+        let mut passwords = std::iter::once(&user.auth_password).chain(&user.priv_password);
+        if passwords.any(|password| password.len() < 8) {
+            bail!(
+                "sinks.snmp.users: {:?} passwords must be at least 8 characters",
+                user.name
+            );
+        }
+    }
+    if let Some(name) = &snmp.trap_user {
+        if !snmp.users.iter().any(|user| &user.name == name) {
+            bail!("sinks.snmp.trap_user: {name:?} is not in sinks.snmp.users");
+        }
+    }
+    if let Some(id) = &snmp.engine_id {
+        match crate::usm::parse_hex(id) {
+            Some(bytes) if (5..=32).contains(&bytes.len()) => {}
+            _ => bail!("sinks.snmp.engine_id must be 5 to 32 bytes in hex"),
+        }
+    }
+    settings.modbus_listen()?;
+    if !(1..=247).contains(&settings.sinks.modbus.unit_id) {
+        bail!("sinks.modbus.unit_id must be between 1 and 247");
+    }
+    AlertRuleSet {
+        alerts: m.alerts.clone(),
+    }
+    .validate()
+    .map_err(|err| anyhow!("alerts: {err}"))?;
+    m.plausibility
+        .validate()
+        .map_err(|err| anyhow!("plausibility: {err}"))?;
+    m.filters
+        .validate()
+        .map_err(|err| anyhow!("filters: {err}"))?;
+    Ok(())
+}
+
+fn load_alert_rules(path: &str) -> Result<AlertRuleSet> {
+    let rules: AlertRuleSet = load_toml(path)?;
+    rules.validate().map_err(|err| anyhow!("{path}: {err}"))?;
+    Ok(rules)
+}
+
+fn load_toml<T: serde::de::DeserializeOwned>(path: &str) -> Result<T> {
+    let raw = std::fs::read_to_string(path).with_context(|| format!("reading {path}"))?;
+    toml::from_str(&raw).with_context(|| format!("parsing {path}"))
+}
+
+fn listen_addr(sink: &str, listen: Option<&str>, default: &str) -> Result<SocketAddr> {
+    let listen = listen.unwrap_or(default);
+    listen
+        .parse()
+        .with_context(|| format!("sinks.{sink}.listen: {listen:?} is not an ip:port address"))
+}
@@ -0,0 +1,384 @@
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use std::{env, fs};
+
+use clap::Parser;
+
+use crate::settings::{parse_file, resolve, validate};
+use crate::{Cli, OutputFormat};
+
+const FILE: &str = r#"
+state_dir = "/var/lib/nobreak"
+utc_offset_minutes = -180
+
+[device]
+vendor_dir = "/opt/nobreak/vendor"
+id = "cdc:/dev/ttyACM0"
+
+[monitor]
+interval_ms = 2000
+interval_max_ms = 5000
+error_threshold = 5
+hold_last_good = true
+
+[events]
+brownout_below_v = 105.0
+
+[[alerts]]
+name = "stale"
+severity = "warning"
+condition = { kind = "stale" }
+
+[sinks.stdout]
+format = "ndjson"
+
+[sinks.export]
+enabled = true
+output_dir = "/data/metrics"
+"#;
+
+/// Held while parsing, so one test's `NOBREAK_*` vars never reach another's parse.
+static ENV: Mutex<()> = Mutex::new(());
+
+fn cli(args: &[&str]) -> Cli {
+    cli_with_env(&[], args)
+}
+
+fn cli_with_env(vars: &[(&str, &str)], args: &[&str]) -> Cli {
+    let _env = ENV.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    for (name, value) in vars {
+        std::env::set_var(name, value);
+    }
+    let cli = Cli::try_parse_from([&["nobreakd"], args, &["run"]].concat());
+    for (name, _) in vars {
+        std::env::remove_var(name);
+    }
+    cli.expect("valid args")
+}
+
+#[test]
+fn file_values_fill_every_layer_below_flags() {
+    // Arrange
+    let file = parse_file(FILE).expect("valid file");
+
+    // Act
+    let settings = resolve(&cli(&[]), file, None).expect("resolves");
+
+    // Assert
+    let m = &settings.monitor;
+    assert_eq!(m.sample_interval, Duration::from_secs(2));
+    assert_eq!(m.sample_interval_min, Duration::from_secs(1));
+    assert_eq!(m.sample_interval_max, Duration::from_secs(5));
+    assert_eq!(m.error_threshold, 5);
+    assert!(m.hold_last_good);
+    assert_eq!(m.events.brownout_below_v, Some(105.0));
+    assert_eq!(m.alerts.len(), 1);
+    assert_eq!(m.energy.utc_offset_minutes, -180);
+    assert_eq!(m.pq_stats.utc_offset_minutes, -180);
+    assert_eq!(
+        m.energy.state_path.as_deref(),
+        Some(std::path::Path::new("/var/lib/nobreak/energy.json"))
+    );
+    assert_eq!(settings.vendor_dir, "/opt/nobreak/vendor");
+    assert_eq!(settings.device_id.as_deref(), Some("cdc:/dev/ttyACM0"));
+    assert_eq!(settings.stdout_format(), OutputFormat::Ndjson);
+    assert!(settings.sinks.export.enabled);
+    validate(&settings).expect("valid settings");
+}
+
+#[test]
+fn flags_override_the_file() {
+    // Arrange
+    let file = parse_file(FILE).expect("valid file");
+    let cli = cli(&[
+        "--interval-ms",
+        "3000",
+        "--device-id",
+        "hid:1",
+        "--no-auto-tune",
+    ]);
+
+    // Act
+    let settings = resolve(&cli, file, None).expect("resolves");
+
+    // Assert
+    assert_eq!(settings.monitor.sample_interval, Duration::from_secs(3));
+    assert!(!settings.monitor.auto_tune);
+    assert_eq!(settings.device_id.as_deref(), Some("hid:1"));
+}
+
+#[test]
+fn env_overrides_the_file() {
+    // Arrange
+    let cli = cli_with_env(&[("NOBREAK_POLL_TIMEOUT_MS", "900")], &[]);
+    let file = parse_file("[monitor]\npoll_timeout_ms = 400\n").expect("valid file");
+
+    // Act
+    let settings = resolve(&cli, file, None).expect("resolves");
+
+    // Assert
+    assert_eq!(settings.monitor.poll_timeout, Duration::from_millis(900));
+}
+
+#[test]
+fn boolean_env_can_turn_off_what_the_file_turns_on() {
+    // Arrange
+    let from_env = cli_with_env(
+        &[
+            ("NOBREAK_HOLD_LAST_GOOD", "false"),
+            ("NOBREAK_NO_AUTO_TUNE", "true"),
+        ],
+        &[],
+    );
+    let from_flags = cli(&["--hold-last-good=false", "--no-power-aware-sampling"]);
+    let file = "[monitor]\nhold_last_good = true\nauto_tune = true\npower_aware_sampling = true\n";
+
+    // Act
+    let [from_env, from_flags] = [from_env, from_flags]
+        .map(|cli| resolve(&cli, parse_file(file).expect("valid file"), None).expect("resolves"));
+
+    // Assert
+    assert!(!from_env.monitor.hold_last_good);
+    assert!(!from_env.monitor.auto_tune);
+    assert!(from_env.monitor.power_aware_sampling);
+    assert!(!from_flags.monitor.hold_last_good);
+    assert!(from_flags.monitor.auto_tune);
+    assert!(!from_flags.monitor.power_aware_sampling);
+}
+
+#[test]
+fn plausibility_limits_file_keeps_what_it_leaves_out() {
+    // Arrange
+    let uniq = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("unix epoch")
+        .as_nanos();
+    let path = env::temp_dir().join(format!("nobreak-tests-plausibility-{uniq}.toml"));
+    fs::write(&path, "[limits.vInput]\nmin = 80.0\nmax = 280.0\n").expect("writes");
+    let file = "[plausibility]\nspike_confirm_samples = 5\n";
+    let cli = cli(&["--plausibility-limits", path.to_str().expect("utf-8 path")]);
+
+    // Act
+    let settings = resolve(&cli, parse_file(file).expect("valid file"), None).expect("resolves");
+    fs::remove_file(&path).expect("removes");
+
+    // Assert
+    let plausibility = &settings.monitor.plausibility;
+    assert_eq!(plausibility.spike_confirm_samples, 5);
+    assert_eq!(plausibility.limits["vInput"].min, 80.0);
+    assert!(plausibility.limits.contains_key("vBattery"));
+}
+
+#[test]
+fn check_rejects_typos_and_inconsistent_values() {
+    // Arrange
+    let typo = "[monitor]\ninterval_msec = 1000\n";
+    let inverted = parse_file("[monitor]\ninterval_min_ms = 4000\n").expect("valid file");
+
+    // Act
+    let parsed = parse_file(typo);
+    let resolved = resolve(&cli(&[]), inverted, None).expect("resolves");
+
+    // Assert
+    assert!(parsed.is_err(), "unknown keys are rejected");
+    assert!(validate(&resolved).is_err());
+}
+
+#[test]
+fn http_listen_flag_enables_the_server() {
+    // Arrange
+    let file = parse_file("[sinks.http]\nlisten = \"not-an-address\"\n").expect("valid file");
+    let flagged = cli(&["--http-listen", "0.0.0.0:9750"]);
+
+    // Act
+    let from_file = resolve(&cli(&[]), file.clone(), None).expect("resolves");
+    let from_flag = resolve(&flagged, file, None).expect("resolves");
+
+    // Assert
+    assert!(!from_file.sinks.http.enabled);
+    assert!(
+        validate(&from_file).is_err(),
+        "listen is checked even when disabled"
+    );
+    assert!(from_flag.sinks.http.enabled);
+    assert_eq!(from_flag.http_listen().expect("valid").port(), 9750);
+}
+
+#[test]
+fn mqtt_host_flag_enables_the_sink() {
+    // Arrange
+    let file =
+        parse_file("[sinks.mqtt]\nport = 8883\nnode_id = \"rack/ups\"\n").expect("valid file");
+    let flagged = cli(&["--mqtt-host", "broker.lan"]);
+
+    // Act
+    let from_file = resolve(&cli(&[]), file.clone(), None).expect("resolves");
+    let from_flag = resolve(&flagged, file, None).expect("resolves");
+
+    // Assert
+    assert!(!from_file.sinks.mqtt.enabled);
+    assert!(
+        validate(&from_file).is_err(),
+        "node_id must be one topic level"
+    );
+    assert!(from_flag.sinks.mqtt.enabled);
+    assert_eq!(from_flag.sinks.mqtt.host, "broker.lan");
+    assert_eq!(from_flag.sinks.mqtt.port, 8883);
+}
+
+#[test]
+fn nut_listen_flag_enables_the_server() {
+    // Arrange
+    let file = parse_file("[sinks.nut]\nups_name = \"rack ups\"\nusername = \"mon\"\n")
+        .expect("valid file");
+    let flagged = cli(&["--nut-listen", "0.0.0.0:3493"]);
+
+    // Act
+    let from_file = resolve(&cli(&[]), file, None).expect("resolves");
+    let from_flag = resolve(&flagged, parse_file("").expect("empty file"), None).expect("resolves");
+
+    // Assert
+    assert!(!from_file.sinks.nut.enabled);
+    assert!(
+        validate(&from_file).is_err(),
+        "ups_name has a space and password is missing"
+    );
+    assert!(from_flag.sinks.nut.enabled);
+    assert_eq!(from_flag.nut_listen().expect("valid").port(), 3493);
+    assert_eq!(from_flag.sinks.nut.ups_name, "ups");
+}
+
+#[test]
+fn nis_listen_flag_enables_the_server() {
+    // Arrange
+    let file = parse_file("[sinks.nis]\nlisten = \"3551\"\n").expect("valid file");
+    let flagged = cli(&["--nis-listen", "0.0.0.0:3551"]);
+
+    // Act
+    let from_file = resolve(&cli(&[]), file.clone(), None).expect("resolves");
+    let from_flag = resolve(&flagged, file, None).expect("resolves");
+
+    // Assert
+    assert!(!from_file.sinks.nis.enabled);
+    assert!(validate(&from_file).is_err(), "listen needs an ip");
+    assert!(from_flag.sinks.nis.enabled);
+    assert_eq!(from_flag.nis_listen().expect("valid").port(), 3551);
+}
+
+#[test]
+fn snmp_listen_flag_enables_the_agent() {
+    // Arrange
+    let file = parse_file("[sinks.snmp]\ntraps = [\"10.0.0.5:162\"]\n").expect("valid file");
+    let flagged = cli(&["--snmp-listen", "0.0.0.0:161", "--snmp-community", "rack"]);
+    let bad_trap = parse_file("[sinks.snmp]\ntraps = [\"nms:162\"]\n").expect("valid file");
+
+    // Act
+    let from_file = resolve(&cli(&[]), file.clone(), None).expect("resolves");
+    let from_flag = resolve(&flagged, file, None).expect("resolves");
+    let with_bad_trap = resolve(&cli(&[]), bad_trap, None).expect("resolves");
+
+    // Assert
+    assert!(!from_file.sinks.snmp.enabled);
+    assert!(from_flag.sinks.snmp.enabled);
+    assert_eq!(from_flag.snmp_listen().expect("valid").port(), 161);
+    assert_eq!(from_flag.sinks.snmp.trap_community(), "rack");
+    assert_eq!(from_flag.snmp_traps().expect("valid")[0].port(), 162);
+    assert!(validate(&with_bad_trap).is_err(), "trap targets need an ip");
+}
+
+#[test]
+fn snmp_users_are_checked() {
+    // Arrange
+    let users = r#"
+state_dir = "/var/lib/nobreak"
+
+[sinks.snmp]
+community = ""
+trap_user = "nms"
+engine_id = "800000000401020304"
+
+[[sinks.snmp.users]]
+name = "nms"
+auth = "SHA-256"
+auth_password = "auth passphrase"
+priv = "AES"
+priv_password = "priv passphrase"
+"#;
+    let short = "[[sinks.snmp.users]]\nname = \"nms\"\nauth_password = \"short\"\n";
+    let half_priv =
+        "[[sinks.snmp.users]]\nname = \"nms\"\nauth_password = \"long enough\"\npriv = \"AES\"\n";
+
+    // Act
+    let valid = resolve(&cli(&[]), parse_file(users).expect("valid file"), None).expect("resolves");
+    let [short, half_priv] = [short, half_priv].map(|file| {
+        let file = parse_file(file).expect("valid file");
+        resolve(&cli(&[]), file, None).expect("resolves")
+    });
+
+    // Assert
+    assert!(validate(&valid).is_ok(), "v3 only needs no community");
+    assert_eq!(
+        valid.sinks.snmp.engine_path.as_deref(),
+        Some(std::path::Path::new("/var/lib/nobreak/snmp-engine.json"))
+    );
+    assert!(validate(&short).is_err(), "passwords need 8 characters");
+    assert!(validate(&half_priv).is_err(), "priv needs its password");
+    assert!(
+        parse_file("[[sinks.snmp.users]]\nname = \"nms\"\nauth = \"MD5\"\n").is_err(),
+        "MD5 is not offered"
+    );
+}
+
+#[test]
+fn modbus_listen_flag_enables_the_server() {
+    // Arrange
+    let file = parse_file("[sinks.modbus]\nunit_id = 0\n").expect("valid file");
+    let flagged = cli(&["--modbus-listen", "0.0.0.0:502"]);
+
+    // Act
+    let from_file = resolve(&cli(&[]), file, None).expect("resolves");
+    let from_flag = resolve(&flagged, Default::default(), None).expect("resolves");
+
+    // Assert
+    assert!(!from_file.sinks.modbus.enabled);
+    assert!(validate(&from_file).is_err(), "unit 0 is broadcast");
+    assert!(from_flag.sinks.modbus.enabled);
+    assert_eq!(from_flag.sinks.modbus.unit_id, 1);
+    assert_eq!(from_flag.modbus_listen().expect("valid").port(), 502);
+}
+
+#[test]
+fn reload_keeps_restart_only_settings_and_names_them() {
+    // Arrange
+    let running =
+        resolve(&cli(&[]), parse_file(FILE).expect("valid file"), None).expect("resolves");
+    let edited = FILE
+        .replace("utc_offset_minutes = -180", "utc_offset_minutes = 0")
+        .replace("brownout_below_v = 105.0", "brownout_below_v = 100.0")
+        .replace("enabled = true", "enabled = false")
+        + "\n[battery_health]\nmin_episode_sec = 30.0\n[energy]\nmax_gap_sec = 60.0\n";
+    let next =
+        resolve(&cli(&[]), parse_file(&edited).expect("valid file"), None).expect("resolves");
+
+    // Act
+    let (applied, kept) = running.reloaded(next.clone());
+    let (_, again) = applied.reloaded(next);
+    let (_, reverted) = applied.reloaded(running.clone());
+
+    // Assert
+    assert_eq!(kept, ["battery_health", "energy", "pq_stats", "sinks"]);
+    assert_eq!(applied.monitor.events.brownout_below_v, Some(100.0));
+    assert_eq!(
+        applied.monitor.battery_health,
+        running.monitor.battery_health
+    );
+    assert_eq!(
+        applied.monitor.energy.max_gap_sec,
+        running.monitor.energy.max_gap_sec
+    );
+    assert_eq!(applied.monitor.energy.utc_offset_minutes, -180);
+    assert_eq!(applied.monitor.pq_stats, running.monitor.pq_stats);
+    assert!(applied.sinks.export.enabled);
+    assert_eq!(again, kept, "still pending until a restart");
+    assert!(reverted.is_empty());
+}
@@ -0,0 +1,686 @@
+//! Read-only SNMP agent for the UPS-MIB (RFC 1628), with SNMPv2-Trap
+//! notifications on battery and alarm changes.
+//!
+//! v1 and v2c requests are checked against the community; v3 requests go
+//! through [`crate::usm`], and every configured user may read the whole
+//! view. The MIB view is rebuilt from the latest snapshot on every request.
+//! Sets are refused.
+
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use nobreak_core::Snapshot;
+use tokio::net::UdpSocket;
+use tokio_stream::{Stream, StreamExt};
+use tracing::{debug, info, warn};
+
+use crate::ber::{
+    self, Message, MessageV3, Oid, Pdu, ScopedData, ScopedPdu, UsmParams, Value, FLAG_AUTH,
+    FLAG_PRIV, FLAG_REPORTABLE, PDU_GET, PDU_GET_BULK, PDU_GET_NEXT, PDU_REPORT, PDU_RESPONSE,
+    PDU_SET, PDU_TRAP_V2,
+};
+use crate::power::{Live, PowerState};
+use crate::settings::SnmpSink;
+use crate::usm::{self, Engine, Failure, Usm};
+
+const SNMP_V1: i64 = 0;
+const SNMP_V2C: i64 = 1;
+const SNMP_V3: i64 = 3;
+
+// Error statuses.
+const NO_SUCH_NAME: i64 = 2;
+const NOT_WRITABLE: i64 = 17;
+const AUTHORIZATION_ERROR: i64 = 16;
+
+/// A GetBulk reply stops growing here, to stay within one Ethernet frame.
+const BULK_BYTES: usize = 1400;
+/// The largest datagram we accept.
+const MAX_REQUEST: usize = 4096;
+/// RFC 1628: `upsTrapOnBattery` repeats every minute while on battery.
+const ON_BATTERY_REPEAT_TICKS: u32 = 6000;
+
+const SYSTEM: &[u32] = &[1, 3, 6, 1, 2, 1, 1];
+const UPS_MIB: &[u32] = &[1, 3, 6, 1, 2, 1, 33];
+const SNMP_TRAP_OID: &[u32] = &[1, 3, 6, 1, 6, 3, 1, 1, 4, 1, 0];
+const SNMP_UNKNOWN_CONTEXTS: &[u32] = &[1, 3, 6, 1, 6, 3, 12, 1, 5, 0];
+
+/// `upsWellKnownAlarms` (upsAlarm.3) we can raise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alarm {
+    OnBattery = 2,
+    LowBattery = 3,
+    InputBad = 6,
+    OutputOverload = 8,
+    CommunicationsLost = 20,
+}
+
+impl Alarm {
+    fn descr(self) -> Oid {
+        oid(UPS_MIB, &[1, 6, 3, self as u32])
+    }
+}
+
+/// Alarms implied by the current state, in table order.
+pub fn present_alarms(live: &Live) -> Vec<Alarm> {
+    let power = &live.power;
+    [
+        (power.on_battery(), Alarm::OnBattery),
+        (power.battery_low, Alarm::LowBattery),
+        (power.brownout || power.overvoltage, Alarm::InputBad),
+        (power.overload, Alarm::OutputOverload),
+        (!live.reachable(), Alarm::CommunicationsLost),
+    ]
+    .into_iter()
+    .filter_map(|(active, alarm)| active.then_some(alarm))
+    .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct AlarmEntry {
+    id: u32,
+    alarm: Alarm,
+    /// sysUpTime when it was added.
+    since: u32,
+}
+
+/// Static facts for the MIB view.
+pub struct Identity {
+    pub sys_name: String,
+    pub rated_va: f64,
+    pub rated_w: f64,
+}
+
+pub struct Agent {
+    config: SnmpSink,
+    identity: Identity,
+    alarms: Vec<AlarmEntry>,
+    next_alarm_id: u32,
+    last_on_battery_trap: Option<u32>,
+    next_trap_id: i32,
+    usm: Usm,
+    unknown_contexts: u32,
+}
+
+impl Agent {
+    pub fn new(config: SnmpSink, identity: Identity, engine: Engine) -> Self {
+        let usm = Usm::new(engine, &config.users);
+        Self {
+            config,
+            identity,
+            alarms: Vec::new(),
+            next_alarm_id: 1,
+            last_on_battery_trap: None,
+            next_trap_id: 1,
+            usm,
+            unknown_contexts: 0,
+        }
+    }
+
+    /// Updates the alarm table for a new state and returns the traps it
+    /// calls for, already encoded.
+    pub fn observe(&mut self, live: &Live, uptime: u32) -> Vec<Vec<u8>> {
+        let present = present_alarms(live);
+        let mut traps = Vec::new();
+
+        let (kept, removed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.alarms)
+            .into_iter()
+            .partition(|entry| present.contains(&entry.alarm));
+        self.alarms = kept;
+        for entry in removed {
+            traps.extend(self.alarm_trap(4, entry, uptime));
+        }
+        for alarm in present {
+            if self.alarms.iter().any(|entry| entry.alarm == alarm) {
+                continue;
+            }
+            let entry = AlarmEntry {
+                id: self.next_alarm_id,
+                alarm,
+                since: uptime,
+            };
+            self.next_alarm_id += 1;
+            self.alarms.push(entry);
+            // RFC 1628: no "entry added" for on-battery; it has its own trap.
+            if alarm != Alarm::OnBattery {
+                traps.extend(self.alarm_trap(3, entry, uptime));
+            }
+        }
+
+        if live.power.on_battery() {
+            let due = self
+                .last_on_battery_trap
+                .is_none_or(|last| uptime.wrapping_sub(last) >= ON_BATTERY_REPEAT_TICKS);
+            if due {
+                self.last_on_battery_trap = Some(uptime);
+                let mut varbinds = Vec::new();
+                if let Some(minutes) = live.fresh("runtimeRemainingSec") {
+                    varbinds.push((
+                        oid(UPS_MIB, &[1, 2, 3, 0]),
+                        Value::Integer((minutes / 60.0).round() as i64),
+                    ));
+                }
+                varbinds.push((
+                    oid(UPS_MIB, &[1, 2, 2, 0]),
+                    Value::Integer(seconds_on_battery(live)),
+                ));
+                traps.extend(self.trap(oid(UPS_MIB, &[2, 1]), varbinds, uptime));
+            }
+        } else {
+            self.last_on_battery_trap = None;
+        }
+        traps
+    }
+
+    /// `upsTrapAlarmEntryAdded` (3) or `upsTrapAlarmEntryRemoved` (4).
+    fn alarm_trap(&mut self, trap: u32, entry: AlarmEntry, uptime: u32) -> Option<Vec<u8>> {
+        let varbinds = vec![
+            (
+                oid(UPS_MIB, &[1, 6, 2, 1, 1, entry.id]),
+                Value::Integer(entry.id.into()),
+            ),
+            (
+                oid(UPS_MIB, &[1, 6, 2, 1, 2, entry.id]),
+                Value::Oid(entry.alarm.descr()),
+            ),
+        ];
+        self.trap(oid(UPS_MIB, &[2, trap]), varbinds, uptime)
+    }
+
+    /// A v2c trap, or a v3 one from `trap_user` at that user's level.
+    fn trap(&mut self, trap_oid: Oid, objects: Vec<(Oid, Value)>, uptime: u32) -> Option<Vec<u8>> {
+        let mut varbinds = vec![
+            (oid(SYSTEM, &[3, 0]), Value::TimeTicks(uptime)),
+            (SNMP_TRAP_OID.to_vec(), Value::Oid(trap_oid)),
+        ];
+        varbinds.extend(objects);
+        let request_id = self.next_trap_id;
+        self.next_trap_id = self.next_trap_id.wrapping_add(1).max(1);
+        let pdu = Pdu {
+            kind: PDU_TRAP_V2,
+            request_id,
+            error_status: 0,
+            error_index: 0,
+            varbinds,
+        };
+        match self.config.trap_user.clone() {
+            Some(user) => {
+                let user = user.into_bytes();
+                let flags = self.usm.level(&user)?;
+                self.send_v3(request_id.into(), flags, user, pdu)
+            }
+            None => Some(
+                Message {
+                    version: SNMP_V2C,
+                    community: self.config.trap_community().as_bytes().to_vec(),
+                    pdu,
+                }
+                .encode(),
+            ),
+        }
+    }
+
+    /// Answers one request datagram. Unparseable requests, a wrong
+    /// community and PDUs an agent does not answer get no reply; v3
+    /// requests that fail the USM checks get a Report when they ask for
+    /// one.
+    pub fn handle(&mut self, request: &[u8], live: Option<&Live>, uptime: u32) -> Option<Vec<u8>> {
+        match ber::version(request) {
+            Ok(SNMP_V1 | SNMP_V2C) => self.handle_community(request, live, uptime),
+            Ok(SNMP_V3) => self.handle_v3(request, live, uptime),
+            Ok(_) => None,
+            Err(err) => {
+                debug!(error = %err, "ignoring malformed snmp request");
+                None
+            }
+        }
+    }
+
+    fn handle_community(
+        &self,
+        request: &[u8],
+        live: Option<&Live>,
+        uptime: u32,
+    ) -> Option<Vec<u8>> {
+        let request = match Message::decode(request) {
+            Ok(request) => request,
+            Err(err) => {
+                debug!(error = %err, "ignoring malformed snmp request");
+                return None;
+            }
+        };
+        if self.config.community.is_empty() || request.community != self.config.community.as_bytes()
+        {
+            debug!("ignoring snmp request with a wrong community");
+            return None;
+        }
+        let reply = |pdu: Pdu| {
+            Message {
+                version: request.version,
+                community: request.community.clone(),
+                pdu,
+            }
+            .encode()
+        };
+        let v1 = request.version == SNMP_V1;
+        let pdu = self.answer(&request.pdu, v1, live, uptime, |pdu| {
+            reply(pdu.clone()).len() <= BULK_BYTES
+        })?;
+        Some(reply(pdu))
+    }
+
+    /// RFC 3412 and 3414, for a request to this engine.
+    fn handle_v3(&mut self, raw: &[u8], live: Option<&Live>, uptime: u32) -> Option<Vec<u8>> {
+        let request = match MessageV3::decode(raw) {
+            Ok(request) => request,
+            Err(err) => {
+                debug!(error = %err, "ignoring malformed snmpv3 request");
+                return None;
+            }
+        };
+        if request.flags & (FLAG_AUTH | FLAG_PRIV) == FLAG_PRIV {
+            debug!("ignoring snmpv3 request with privacy but no authentication");
+            return None;
+        }
+        let reportable = request.flags & FLAG_REPORTABLE != 0;
+        let user = request.usm.user.clone();
+        let scoped = match self.usm.incoming(raw, &request) {
+            Ok(scoped) => scoped,
+            Err(failure) => {
+                debug!(?failure, "refusing snmpv3 request");
+                let request_id = match &request.data {
+                    ScopedData::Plain(scoped) => scoped.pdu.request_id,
+                    ScopedData::Encrypted(_) => 0,
+                };
+                // Only a user whose digest checked out can sync its clock.
+                let flags = if failure == Failure::NotInTimeWindow {
+                    FLAG_AUTH
+                } else {
+                    0
+                };
+                let counter = self.usm.stat(failure);
+                return reportable
+                    .then(|| self.report(&request, flags, request_id, counter))
+                    .flatten();
+            }
+        };
+        let level = request.flags & (FLAG_AUTH | FLAG_PRIV);
+        let engine_id = &self.usm.engine.id;
+        if !scoped.context_name.is_empty() || &scoped.context_engine_id != engine_id {
+            self.unknown_contexts = self.unknown_contexts.wrapping_add(1);
+            let counter = (
+                SNMP_UNKNOWN_CONTEXTS.to_vec(),
+                Value::Counter32(self.unknown_contexts),
+            );
+            return reportable
+                .then(|| self.report(&request, level, scoped.pdu.request_id, counter))
+                .flatten();
+        }
+        let room = BULK_BYTES.saturating_sub(usm::envelope_len(engine_id, &user));
+        let mut pdu = self.answer(&scoped.pdu, false, live, uptime, |pdu| {
+            ScopedPdu {
+                context_engine_id: engine_id.clone(),
+                context_name: Vec::new(),
+                pdu: pdu.clone(),
+            }
+            .encode()
+            .len()
+                <= room
+        })?;
+        // Every user reads the whole view, but only at its own level.
+        let needed = self.usm.level(&user).unwrap_or(FLAG_AUTH | FLAG_PRIV);
+        if needed & !level != 0 {
+            debug!("refusing snmpv3 request below the user's security level");
+            pdu = response(
+                &scoped.pdu,
+                (AUTHORIZATION_ERROR, 0),
+                scoped.pdu.varbinds.clone(),
+            );
+        }
+        self.send_v3(request.msg_id, level, user, pdu)
+    }
+
+    fn report(
+        &mut self,
+        request: &MessageV3,
+        flags: u8,
+        request_id: i32,
+        counter: (Oid, Value),
+    ) -> Option<Vec<u8>> {
+        let pdu = Pdu {
+            kind: PDU_REPORT,
+            request_id,
+            error_status: 0,
+            error_index: 0,
+            varbinds: vec![counter],
+        };
+        self.send_v3(request.msg_id, flags, request.usm.user.clone(), pdu)
+    }
+
+    /// `pdu` in a v3 message from this engine, protected at `flags`.
+    fn send_v3(&mut self, msg_id: i64, flags: u8, user: Vec<u8>, pdu: Pdu) -> Option<Vec<u8>> {
+        let context_engine_id = self.usm.engine.id.clone();
+        self.usm.outgoing(MessageV3 {
+            msg_id,
+            max_size: MAX_REQUEST as i64,
+            flags,
+            usm: UsmParams {
+                user,
+                ..UsmParams::default()
+            },
+            data: ScopedData::Plain(ScopedPdu {
+                context_engine_id,
+                context_name: Vec::new(),
+                pdu,
+            }),
+        })
+    }
+
+    /// The response to a request PDU, or `None` for PDUs an agent does not
+    /// answer. `fits` says whether a GetBulk response is still small
+    /// enough to send.
+    fn answer(
+        &self,
+        request: &Pdu,
+        v1: bool,
+        live: Option<&Live>,
+        uptime: u32,
+        fits: impl Fn(&Pdu) -> bool,
+    ) -> Option<Pdu> {
+        let mib = self.mib(live, uptime);
+        let asked = &request.varbinds;
+        let mut error = (0, 0);
+        let varbinds = match request.kind {
+            PDU_GET => asked
+                .iter()
+                .map(|(oid, _)| (oid.clone(), get(&mib, oid)))
+                .collect(),
+            PDU_GET_NEXT => asked.iter().map(|(oid, _)| next(&mib, oid)).collect(),
+            PDU_GET_BULK if !v1 => {
+                let non_repeaters = request.error_status.clamp(0, asked.len() as i64) as usize;
+                let repetitions = request.error_index.max(0) as usize;
+                bulk(&mib, asked, non_repeaters, repetitions, |varbinds| {
+                    fits(&response(request, (0, 0), varbinds.to_vec()))
+                })
+            }
+            PDU_SET => {
+                error = (if v1 { NO_SUCH_NAME } else { NOT_WRITABLE }, 1);
+                asked.clone()
+            }
+            _ => return None,
+        };
+        // v1 has no exception values: the first one becomes an error.
+        if v1 && error.0 == 0 {
+            if let Some(i) = varbinds
+                .iter()
+                .position(|(_, value): &(Oid, Value)| is_exception(value))
+            {
+                return Some(response(
+                    request,
+                    (NO_SUCH_NAME, i as i64 + 1),
+                    asked.clone(),
+                ));
+            }
+        }
+        Some(response(request, error, varbinds))
+    }
+
+    /// The whole view, sorted by OID.
+    pub fn mib(&self, live: Option<&Live>, uptime: u32) -> Vec<(Oid, Value)> {
+        let identity = &self.identity;
+        let model = live.map_or("", |live| live.snapshot.device.model.as_str());
+        let mut mib = vec![
+            (
+                oid(SYSTEM, &[1, 0]),
+                Value::string(&format!(
+                    "nobreakd {} read-only UPS monitor {model}",
+                    env!("CARGO_PKG_VERSION")
+                )),
+            ),
+            (oid(SYSTEM, &[2, 0]), Value::Oid(UPS_MIB.to_vec())),
+            (oid(SYSTEM, &[3, 0]), Value::TimeTicks(uptime)),
+            (oid(SYSTEM, &[4, 0]), Value::string(&self.config.contact)),
+            (oid(SYSTEM, &[5, 0]), Value::string(&identity.sys_name)),
+            (oid(SYSTEM, &[6, 0]), Value::string(&self.config.location)),
+            (oid(SYSTEM, &[7, 0]), Value::Integer(72)),
+            // upsIdent
+            (oid(UPS_MIB, &[1, 1, 1, 0]), Value::string("RagTech")),
+            (oid(UPS_MIB, &[1, 1, 2, 0]), Value::string(model)),
+            (oid(UPS_MIB, &[1, 1, 3, 0]), Value::string("")),
+            (
+                oid(UPS_MIB, &[1, 1, 4, 0]),
+                Value::string(&format!("nobreakd {}", env!("CARGO_PKG_VERSION"))),
+            ),
+            (
+                oid(UPS_MIB, &[1, 1, 5, 0]),
+                Value::string(&self.config.ups_name),
+            ),
+            (oid(UPS_MIB, &[1, 1, 6, 0]), Value::string("")),
+        ];
+        let reachable = live.filter(|live| live.reachable());
+        let battery_status = match reachable {
+            None => 1,
+            Some(live) if live.power.battery_low => 3,
+            Some(_) => 2,
+        };
+        mib.push((oid(UPS_MIB, &[1, 2, 1, 0]), Value::Integer(battery_status)));
+
+        if let Some(live) = reachable {
+            mib.push((
+                oid(UPS_MIB, &[1, 2, 2, 0]),
+                Value::Integer(seconds_on_battery(live)),
+            ));
+            let scaled = |var: &str, scale: f64| {
+                live.fresh(var)
+                    .map(|value| Value::Integer((value * scale).round() as i64))
+            };
+            for (arcs, value) in [
+                // upsBattery: minutes, %, 0.1 V, °C
+                (&[1, 2, 3, 0][..], scaled("runtimeRemainingSec", 1.0 / 60.0)),
+                (&[1, 2, 4, 0], scaled("cBattery", 1.0)),
+                (&[1, 2, 5, 0], scaled("vBattery", 10.0)),
+                (&[1, 2, 7, 0], scaled("temperature", 1.0)),
+                // upsInput: one line
+                (&[1, 3, 2, 0], Some(Value::Integer(1))),
+                (&[1, 3, 3, 1, 1, 1], Some(Value::Integer(1))),
+                (&[1, 3, 3, 1, 3, 1], scaled("vInput", 1.0)),
+                // upsOutput: source, 0.1 Hz, one line
+                (
+                    &[1, 4, 1, 0],
+                    Some(Value::Integer(if live.power.on_battery() { 5 } else { 3 })),
+                ),
+                (&[1, 4, 2, 0], scaled("fOutput", 10.0)),
+                (&[1, 4, 3, 0], Some(Value::Integer(1))),
+                (&[1, 4, 4, 1, 1, 1], Some(Value::Integer(1))),
+                (&[1, 4, 4, 1, 2, 1], scaled("vOutput", 1.0)),
+                (&[1, 4, 4, 1, 4, 1], scaled("pOutputWatts", 1.0)),
+                (&[1, 4, 4, 1, 5, 1], scaled("pOutput", 1.0)),
+                (&[1, 9, 1, 0], scaled("vInputNominal", 1.0)),
+            ] {
+                if let Some(value) = value {
+                    mib.push((oid(UPS_MIB, arcs), value));
+                }
+            }
+        }
+        if let Some(live) = live {
+            mib.push((
+                oid(UPS_MIB, &[1, 3, 1, 0]),
+                Value::Counter32(live.power.transfers as u32),
+            ));
+        }
+        mib.push((
+            oid(UPS_MIB, &[1, 6, 1, 0]),
+            Value::Gauge32(self.alarms.len() as u32),
+        ));
+        for entry in &self.alarms {
+            mib.extend([
+                (
+                    oid(UPS_MIB, &[1, 6, 2, 1, 1, entry.id]),
+                    Value::Integer(entry.id.into()),
+                ),
+                (
+                    oid(UPS_MIB, &[1, 6, 2, 1, 2, entry.id]),
+                    Value::Oid(entry.alarm.descr()),
+                ),
+                (
+                    oid(UPS_MIB, &[1, 6, 2, 1, 3, entry.id]),
+                    Value::TimeTicks(entry.since),
+                ),
+            ]);
+        }
+        mib.push((
+            oid(UPS_MIB, &[1, 9, 5, 0]),
+            Value::Integer(identity.rated_va.round() as i64),
+        ));
+        mib.push((
+            oid(UPS_MIB, &[1, 9, 6, 0]),
+            Value::Integer(identity.rated_w.round() as i64),
+        ));
+        mib.sort_by(|a, b| a.0.cmp(&b.0));
+        mib
+    }
+}
+
+fn response(request: &Pdu, error: (i64, i64), varbinds: Vec<(Oid, Value)>) -> Pdu {
+    Pdu {
+        kind: PDU_RESPONSE,
+        request_id: request.request_id,
+        error_status: error.0,
+        error_index: error.1,
+        varbinds,
+    }
+}
+
+fn oid(base: &[u32], arcs: &[u32]) -> Oid {
+    [base, arcs].concat()
+}
+
+fn seconds_on_battery(live: &Live) -> i64 {
+    live.power
+        .on_battery_since
+        .map_or(0, |since| (live.snapshot.ts - since).num_seconds().max(0))
+}
+
+fn is_exception(value: &Value) -> bool {
+    matches!(
+        value,
+        Value::NoSuchObject | Value::NoSuchInstance | Value::EndOfMibView
+    )
+}
+
+fn get(mib: &[(Oid, Value)], wanted: &Oid) -> Value {
+    match mib.binary_search_by(|(oid, _)| oid.cmp(wanted)) {
+        Ok(i) => mib[i].1.clone(),
+        // A known object with another instance, e.g. `.1` for a scalar.
+        Err(_)
+            if wanted.len() > 1
+                && mib.iter().any(|(oid, _)| {
+                    oid.len() == wanted.len() && oid[..oid.len() - 1] == wanted[..wanted.len() - 1]
+                }) =>
+        {
+            Value::NoSuchInstance
+        }
+        Err(_) => Value::NoSuchObject,
+    }
+}
+
+fn next(mib: &[(Oid, Value)], after: &Oid) -> (Oid, Value) {
+    let i = mib.partition_point(|(oid, _)| oid <= after);
+    match mib.get(i) {
+        Some(entry) => entry.clone(),
+        None => (after.clone(), Value::EndOfMibView),
+    }
+}
+
+/// RFC 3416 GetBulk: each non-repeater once, then the rest `repetitions`
+/// times, stopping early once `fits` says the reply would be too large.
+fn bulk(
+    mib: &[(Oid, Value)],
+    asked: &[(Oid, Value)],
+    non_repeaters: usize,
+    repetitions: usize,
+    fits: impl Fn(&[(Oid, Value)]) -> bool,
+) -> Vec<(Oid, Value)> {
+    let mut out: Vec<(Oid, Value)> = asked[..non_repeaters]
+        .iter()
+        .map(|(oid, _)| next(mib, oid))
+        .collect();
+    let mut cursors: Vec<Oid> = asked[non_repeaters..]
+        .iter()
+        .map(|(oid, _)| oid.clone())
+        .collect();
+    for _ in 0..repetitions {
+        if cursors.is_empty() {
+            break;
+        }
+        let row: Vec<(Oid, Value)> = cursors.iter().map(|oid| next(mib, oid)).collect();
+        let grown = [out.as_slice(), row.as_slice()].concat();
+        if !fits(&grown) {
+            break;
+        }
+        let done = row.iter().all(|(_, value)| *value == Value::EndOfMibView);
+        cursors = row.iter().map(|(oid, _)| oid.clone()).collect();
+        out = grown;
+        if done {
+            break;
+        }
+    }
+    out
+}
+
+/// The UDP counterpart of [`crate::net::bind_tcp`].
+pub async fn bind(listen: SocketAddr) -> Result<UdpSocket> {
+    let socket = UdpSocket::bind(listen)
+        .await
+        .with_context(|| format!("binding snmp socket on {listen}"))?;
+    info!(%listen, "snmp agent listening");
+    Ok(socket)
+}
+
+pub async fn run_snmp(
+    mut agent: Agent,
+    trap_targets: Vec<SocketAddr>,
+    mut snapshots: impl Stream<Item = Snapshot> + Unpin,
+    socket: UdpSocket,
+) -> Result<()> {
+    let started = Instant::now();
+    // sysUpTime, in hundredths of a second; wraps after 497 days, as it should.
+    let uptime = || (started.elapsed().as_millis() / 10) as u32;
+    let mut power = PowerState::default();
+    let mut live: Option<Live> = None;
+    let mut buf = vec![0; MAX_REQUEST];
+    loop {
+        tokio::select! {
+            snapshot = snapshots.next() => {
+                let Some(snapshot) = snapshot else { break };
+                power.observe(&snapshot);
+                let current = Live { snapshot, power: power.clone() };
+                for trap in agent.observe(&current, uptime()) {
+                    for target in &trap_targets {
+                        if let Err(err) = socket.send_to(&trap, target).await {
+                            warn!(%target, error = %err, "snmp trap not sent");
+                        }
+                    }
+                }
+                live = Some(current);
+            }
+            received = socket.recv_from(&mut buf) => {
+                // An ICMP error from an earlier trap can surface here; it is
+                // not the agent's problem.
+                let (len, peer) = match received {
+                    Ok(received) => received,
+                    Err(err) => {
+                        debug!(error = %err, "snmp receive failed");
+                        continue;
+                    }
+                };
+                if let Some(reply) = agent.handle(&buf[..len], live.as_ref(), uptime()) {
+                    if let Err(err) = socket.send_to(&reply, peer).await {
+                        debug!(%peer, error = %err, "snmp reply not sent");
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
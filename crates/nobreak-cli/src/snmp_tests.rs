@@ -0,0 +1,635 @@
+use std::time::Duration;
+
+use nobreak_core::{EventPhase, PowerEventKind};
+use tokio::net::UdpSocket;
+
+use crate::ber::{
+    Message, MessageV3, Oid, Pdu, ScopedData, ScopedPdu, UsmParams, Value, FLAG_AUTH, FLAG_PRIV,
+    FLAG_REPORTABLE, PDU_GET, PDU_GET_BULK, PDU_GET_NEXT, PDU_REPORT, PDU_RESPONSE, PDU_SET,
+    PDU_TRAP_V2,
+};
+use crate::fixtures::{event, feed, live, snapshot};
+use crate::power::{Live, PowerState};
+use crate::settings::{SnmpAuth, SnmpPriv, SnmpSink, SnmpUser};
+use crate::snmp::{run_snmp, Agent, Identity};
+use crate::usm::{localized_key, Engine, Usm};
+
+const UPS_MIB: &[u32] = &[1, 3, 6, 1, 2, 1, 33];
+const ENGINE_ID: &[u8] = b"\x80\0\0\0\x04rack1";
+const USM_STATS: &[u32] = &[1, 3, 6, 1, 6, 3, 15, 1, 1];
+
+fn identity() -> Identity {
+    Identity {
+        sys_name: "rack1".to_string(),
+        rated_va: 3200.0,
+        rated_w: 2400.0,
+    }
+}
+
+fn agent() -> Agent {
+    Agent::new(
+        SnmpSink::default(),
+        identity(),
+        Engine::new(ENGINE_ID.to_vec(), 1),
+    )
+}
+
+fn ups(arcs: &[u32]) -> Oid {
+    [UPS_MIB, arcs].concat()
+}
+
+fn request(version: i64, community: &str, kind: u8, oids: &[Oid]) -> Vec<u8> {
+    Message {
+        version,
+        community: community.as_bytes().to_vec(),
+        pdu: Pdu {
+            kind,
+            request_id: 42,
+            error_status: 0,
+            error_index: 0,
+            varbinds: oids.iter().map(|oid| (oid.clone(), Value::Null)).collect(),
+        },
+    }
+    .encode()
+}
+
+fn ask(agent: &mut Agent, live: Option<&Live>, version: i64, kind: u8, oids: &[Oid]) -> Pdu {
+    let reply = agent
+        .handle(&request(version, "public", kind, oids), live, 100)
+        .expect("a reply");
+    let reply = Message::decode(&reply).expect("a valid reply");
+    assert_eq!(reply.version, version);
+    assert_eq!(reply.pdu.kind, PDU_RESPONSE);
+    assert_eq!(reply.pdu.request_id, 42);
+    reply.pdu
+}
+
+#[test]
+fn ber_messages_round_trip() {
+    // Arrange
+    let message = Message {
+        version: 1,
+        community: b"public".to_vec(),
+        pdu: Pdu {
+            kind: PDU_RESPONSE,
+            request_id: -7,
+            error_status: 0,
+            error_index: 0,
+            varbinds: vec![
+                (vec![1, 3, 6, 1, 4, 1, 2_000_000, 0], Value::Integer(-129)),
+                (vec![1, 3, 6, 1, 2, 1, 1, 3, 0], Value::TimeTicks(u32::MAX)),
+                (
+                    vec![1, 3, 6, 1, 2, 1, 1, 1, 0],
+                    Value::string(&"x".repeat(300)),
+                ),
+                (
+                    vec![1, 3, 6, 1, 2, 1, 1, 2, 0],
+                    Value::Oid(UPS_MIB.to_vec()),
+                ),
+                (vec![1, 3, 6, 1, 2, 1, 1, 9, 0], Value::EndOfMibView),
+            ],
+        },
+    };
+
+    // Act
+    let bytes = message.encode();
+
+    // Assert
+    assert_eq!(Message::decode(&bytes).expect("decodes"), message);
+    assert!(Message::decode(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn get_reads_the_ups_mib_from_the_snapshot() {
+    // Arrange
+    let current = live(snapshot(), &mut PowerState::default());
+
+    // Act
+    let pdu = ask(
+        &mut agent(),
+        Some(&current),
+        1,
+        PDU_GET,
+        &[
+            ups(&[1, 3, 3, 1, 3, 1]),
+            ups(&[1, 2, 1, 0]),
+            ups(&[1, 2, 4, 0]),
+            ups(&[1, 4, 4, 1, 5, 1]),
+            ups(&[1, 2, 5, 0]),
+            ups(&[1, 2, 3, 0]),
+            ups(&[1, 4, 1, 0]),
+            ups(&[1, 4, 2, 0]),
+            ups(&[1, 9, 6, 0]),
+            ups(&[1, 2, 1, 1]),
+            ups(&[1, 8, 1, 0]),
+        ],
+    );
+
+    // Assert
+    assert_eq!(pdu.error_status, 0);
+    let values: Vec<Value> = pdu.varbinds.into_iter().map(|(_, value)| value).collect();
+    assert_eq!(
+        values,
+        vec![
+            Value::Integer(127),
+            Value::Integer(2),
+            Value::Integer(100),
+            Value::Integer(35),
+            Value::Integer(272),
+            Value::Integer(25),
+            Value::Integer(3),
+            Value::Integer(600),
+            Value::Integer(2400),
+            Value::NoSuchInstance,
+            Value::NoSuchObject,
+        ]
+    );
+}
+
+#[test]
+fn unreachable_device_reports_unknown_battery_and_hides_readings() {
+    // Arrange
+    let mut stale = live(snapshot(), &mut PowerState::default());
+    stale.snapshot.freshness.stale = true;
+
+    // Act
+    let pdu = ask(
+        &mut agent(),
+        Some(&stale),
+        1,
+        PDU_GET,
+        &[ups(&[1, 2, 1, 0]), ups(&[1, 3, 3, 1, 3, 1])],
+    );
+    let empty = ask(&mut agent(), None, 1, PDU_GET, &[ups(&[1, 2, 1, 0])]);
+
+    // Assert
+    assert_eq!(pdu.varbinds[0].1, Value::Integer(1));
+    assert_eq!(pdu.varbinds[1].1, Value::NoSuchObject);
+    assert_eq!(empty.varbinds[0].1, Value::Integer(1));
+}
+
+#[test]
+fn get_next_walks_in_oid_order_to_the_end_of_the_view() {
+    // Arrange
+    let current = live(snapshot(), &mut PowerState::default());
+    let mut agent = agent();
+    let mut cursor: Oid = vec![1, 3];
+    let mut walked = Vec::new();
+
+    // Act
+    loop {
+        let pdu = ask(
+            &mut agent,
+            Some(&current),
+            1,
+            PDU_GET_NEXT,
+            &[cursor.clone()],
+        );
+        let (oid, value) = pdu.varbinds.into_iter().next().expect("a varbind");
+        if value == Value::EndOfMibView {
+            assert_eq!(oid, cursor);
+            break;
+        }
+        walked.push(oid.clone());
+        cursor = oid;
+    }
+
+    // Assert
+    assert_eq!(walked.first(), Some(&vec![1, 3, 6, 1, 2, 1, 1, 1, 0]));
+    assert_eq!(walked.last(), Some(&ups(&[1, 9, 6, 0])));
+    assert!(walked.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(walked.contains(&ups(&[1, 3, 3, 1, 3, 1])));
+}
+
+#[test]
+fn get_bulk_repeats_the_walk() {
+    // Arrange
+    let current = live(snapshot(), &mut PowerState::default());
+    let mut bytes = Message {
+        version: 1,
+        community: b"public".to_vec(),
+        pdu: Pdu {
+            kind: PDU_GET_BULK,
+            request_id: 42,
+            error_status: 1,
+            error_index: 3,
+            varbinds: vec![
+                (vec![1, 3, 6, 1, 2, 1, 1, 4], Value::Null),
+                (ups(&[1, 2]), Value::Null),
+            ],
+        },
+    }
+    .encode();
+
+    // Act
+    bytes = agent()
+        .handle(&bytes, Some(&current), 100)
+        .expect("a reply");
+    let pdu = Message::decode(&bytes).expect("a valid reply").pdu;
+
+    // Assert
+    let oids: Vec<Oid> = pdu.varbinds.into_iter().map(|(oid, _)| oid).collect();
+    assert_eq!(
+        oids,
+        vec![
+            vec![1, 3, 6, 1, 2, 1, 1, 4, 0],
+            ups(&[1, 2, 1, 0]),
+            ups(&[1, 2, 2, 0]),
+            ups(&[1, 2, 3, 0]),
+        ]
+    );
+}
+
+#[test]
+fn v1_reports_no_such_name_instead_of_exceptions() {
+    // Arrange
+    let current = live(snapshot(), &mut PowerState::default());
+
+    // Act
+    let pdu = ask(
+        &mut agent(),
+        Some(&current),
+        0,
+        PDU_GET,
+        &[ups(&[1, 2, 1, 0]), ups(&[1, 8, 1, 0])],
+    );
+    let bulk = agent().handle(
+        &request(0, "public", PDU_GET_BULK, &[ups(&[1])]),
+        Some(&current),
+        100,
+    );
+
+    // Assert
+    assert_eq!((pdu.error_status, pdu.error_index), (2, 2));
+    assert_eq!(pdu.varbinds[1], (ups(&[1, 8, 1, 0]), Value::Null));
+    assert_eq!(bulk, None);
+}
+
+#[test]
+fn sets_are_refused_and_wrong_communities_ignored() {
+    // Arrange
+    let current = live(snapshot(), &mut PowerState::default());
+    let mut agent = agent();
+
+    // Act
+    let v2c = ask(
+        &mut agent,
+        Some(&current),
+        1,
+        PDU_SET,
+        &[ups(&[1, 1, 5, 0])],
+    );
+    let v1 = ask(
+        &mut agent,
+        Some(&current),
+        0,
+        PDU_SET,
+        &[ups(&[1, 1, 5, 0])],
+    );
+    let wrong = agent.handle(
+        &request(1, "private", PDU_GET, &[ups(&[1, 2, 1, 0])]),
+        Some(&current),
+        100,
+    );
+
+    // Assert
+    assert_eq!((v2c.error_status, v2c.error_index), (17, 1));
+    assert_eq!((v1.error_status, v1.error_index), (2, 1));
+    assert_eq!(wrong, None);
+    assert_eq!(agent.handle(b"garbage", Some(&current), 100), None);
+}
+
+fn trap_oid(trap: &[u8]) -> Oid {
+    let message = Message::decode(trap).expect("a valid trap");
+    assert_eq!(message.pdu.kind, PDU_TRAP_V2);
+    assert_eq!(message.pdu.varbinds[0].0, vec![1, 3, 6, 1, 2, 1, 1, 3, 0]);
+    match &message.pdu.varbinds[1] {
+        (oid, Value::Oid(trap)) if *oid == vec![1, 3, 6, 1, 6, 3, 1, 1, 4, 1, 0] => trap.clone(),
+        other => panic!("no snmpTrapOID: {other:?}"),
+    }
+}
+
+#[test]
+fn power_events_raise_alarms_and_traps() {
+    // Arrange
+    let mut agent = agent();
+    let mut power = PowerState::default();
+    let mut lost = snapshot();
+    lost.events = vec![
+        event(PowerEventKind::MainsLost, EventPhase::Start),
+        event(PowerEventKind::BatteryLow, EventPhase::Start),
+    ];
+    let mut restored = snapshot();
+    restored.events = vec![
+        event(PowerEventKind::MainsRestored, EventPhase::End),
+        event(PowerEventKind::BatteryLow, EventPhase::End),
+    ];
+    let on_battery = live(lost, &mut power);
+    let still = live(snapshot(), &mut power);
+    let back = live(restored, &mut power);
+
+    // Act
+    let first: Vec<Oid> = agent
+        .observe(&on_battery, 100)
+        .iter()
+        .map(|t| trap_oid(t))
+        .collect();
+    let table = agent.mib(Some(&on_battery), 100);
+    let repeat_early = agent.observe(&still, 200).len();
+    let repeat_late: Vec<Oid> = agent
+        .observe(&still, 6200)
+        .iter()
+        .map(|t| trap_oid(t))
+        .collect();
+    let cleared: Vec<Oid> = agent
+        .observe(&back, 6300)
+        .iter()
+        .map(|t| trap_oid(t))
+        .collect();
+
+    // Assert
+    assert_eq!(first, vec![ups(&[2, 3]), ups(&[2, 1])]);
+    let get = |oid: Oid| {
+        table
+            .iter()
+            .find(|(o, _)| *o == oid)
+            .map(|(_, v)| v.clone())
+    };
+    assert_eq!(get(ups(&[1, 6, 1, 0])), Some(Value::Gauge32(2)));
+    assert_eq!(
+        get(ups(&[1, 6, 2, 1, 2, 1])),
+        Some(Value::Oid(ups(&[1, 6, 3, 2])))
+    );
+    assert_eq!(
+        get(ups(&[1, 6, 2, 1, 2, 2])),
+        Some(Value::Oid(ups(&[1, 6, 3, 3])))
+    );
+    assert_eq!(get(ups(&[1, 2, 1, 0])), Some(Value::Integer(3)));
+    assert_eq!(get(ups(&[1, 4, 1, 0])), Some(Value::Integer(5)));
+    assert_eq!(repeat_early, 0);
+    assert_eq!(repeat_late, vec![ups(&[2, 1])]);
+    assert_eq!(cleared, vec![ups(&[2, 4]), ups(&[2, 4])]);
+    assert_eq!(
+        agent
+            .mib(Some(&back), 6300)
+            .iter()
+            .filter(|(oid, _)| oid.starts_with(&ups(&[1, 6, 2])))
+            .count(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn answers_and_traps_over_udp() {
+    // Arrange
+    let socket = UdpSocket::bind("127.0.0.1:0").await.expect("bind");
+    let addr = socket.local_addr().expect("addr");
+    let receiver = UdpSocket::bind("127.0.0.1:0").await.expect("bind");
+    let target = receiver.local_addr().expect("addr");
+    let mut lost = snapshot();
+    lost.events = vec![event(PowerEventKind::MainsLost, EventPhase::Start)];
+    let server = tokio::spawn(run_snmp(agent(), vec![target], feed(lost), socket));
+
+    // Act
+    let exchange = async {
+        let mut buf = vec![0; 4096];
+        let (len, _) = receiver.recv_from(&mut buf).await.expect("trap");
+        let trap = trap_oid(&buf[..len]);
+        let client = UdpSocket::bind("127.0.0.1:0").await.expect("bind");
+        client
+            .send_to(&request(1, "public", PDU_GET, &[ups(&[1, 4, 1, 0])]), addr)
+            .await
+            .expect("send");
+        let (len, _) = client.recv_from(&mut buf).await.expect("reply");
+        (trap, Message::decode(&buf[..len]).expect("a valid reply"))
+    };
+    let (trap, reply) = tokio::time::timeout(Duration::from_secs(5), exchange)
+        .await
+        .expect("agent answers");
+    server.abort();
+
+    // Assert
+    assert_eq!(trap, ups(&[2, 1]));
+    assert_eq!(
+        reply.pdu.varbinds,
+        vec![(ups(&[1, 4, 1, 0]), Value::Integer(5))]
+    );
+}
+
+fn users() -> Vec<SnmpUser> {
+    vec![
+        SnmpUser {
+            name: "nms".to_string(),
+            auth: SnmpAuth::Sha,
+            auth_password: "auth passphrase".to_string(),
+            privacy: Some(SnmpPriv::Aes),
+            priv_password: Some("priv passphrase".to_string()),
+        },
+        SnmpUser {
+            name: "poller".to_string(),
+            auth: SnmpAuth::Sha256,
+            auth_password: "poller passphrase".to_string(),
+            privacy: None,
+            priv_password: None,
+        },
+    ]
+}
+
+fn v3_agent(trap_user: Option<&str>) -> Agent {
+    let config = SnmpSink {
+        users: users(),
+        trap_user: trap_user.map(str::to_string),
+        ..SnmpSink::default()
+    };
+    Agent::new(config, identity(), Engine::new(ENGINE_ID.to_vec(), 1))
+}
+
+/// The manager's side: the same users, keyed to the agent's engine.
+fn manager(boots: i64) -> Usm {
+    Usm::new(Engine::new(ENGINE_ID.to_vec(), boots), &users())
+}
+
+fn v3_get(manager: &mut Usm, user: &str, flags: u8, oids: &[Oid]) -> Vec<u8> {
+    manager
+        .outgoing(MessageV3 {
+            msg_id: 7,
+            max_size: 65507,
+            flags: flags | FLAG_REPORTABLE,
+            usm: UsmParams {
+                user: user.as_bytes().to_vec(),
+                ..UsmParams::default()
+            },
+            data: ScopedData::Plain(ScopedPdu {
+                context_engine_id: ENGINE_ID.to_vec(),
+                context_name: Vec::new(),
+                pdu: Pdu {
+                    kind: PDU_GET,
+                    request_id: 42,
+                    error_status: 0,
+                    error_index: 0,
+                    varbinds: oids.iter().map(|oid| (oid.clone(), Value::Null)).collect(),
+                },
+            }),
+        })
+        .expect("keys for the level")
+}
+
+/// Decodes a reply, checking its digest and decrypting it when it has one.
+fn v3_reply(manager: &mut Usm, reply: Option<Vec<u8>>) -> (MessageV3, Pdu) {
+    let bytes = reply.expect("a reply");
+    let message = MessageV3::decode(&bytes).expect("a valid reply");
+    assert_eq!(message.msg_id, 7);
+    assert_eq!(message.usm.engine_id, ENGINE_ID);
+    let scoped = match &message.data {
+        ScopedData::Plain(scoped) if message.flags & FLAG_AUTH == 0 => scoped.clone(),
+        _ => manager
+            .incoming(&bytes, &message)
+            .expect("an authentic reply"),
+    };
+    (message, scoped.pdu)
+}
+
+#[test]
+fn usm_keys_match_rfc_3414() {
+    // Arrange
+    let engine_id = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+
+    // Act
+    let key = localized_key(SnmpAuth::Sha, b"maplesyrup", &engine_id);
+
+    // Assert
+    assert_eq!(
+        key,
+        [
+            0x66, 0x95, 0xfe, 0xbc, 0x92, 0x88, 0xe3, 0x62, 0x82, 0x23, 0x5f, 0xc7, 0x15, 0x1f,
+            0x12, 0x84, 0x97, 0xb3, 0x8f, 0x3f
+        ]
+    );
+}
+
+#[test]
+fn v3_discovery_reports_the_engine() {
+    // Arrange
+    let probe = MessageV3 {
+        msg_id: 7,
+        max_size: 65507,
+        flags: FLAG_REPORTABLE,
+        usm: UsmParams::default(),
+        data: ScopedData::Plain(ScopedPdu {
+            context_engine_id: Vec::new(),
+            context_name: Vec::new(),
+            pdu: Pdu {
+                kind: PDU_GET,
+                request_id: 42,
+                error_status: 0,
+                error_index: 0,
+                varbinds: Vec::new(),
+            },
+        }),
+    };
+    let mut agent = v3_agent(None);
+
+    // Act
+    let reply = agent.handle(&probe.encode(), None, 100);
+    let silent = agent.handle(
+        &MessageV3 {
+            flags: 0,
+            ..probe.clone()
+        }
+        .encode(),
+        None,
+        100,
+    );
+
+    // Assert
+    let (message, pdu) = v3_reply(&mut manager(1), reply);
+    assert_eq!((message.flags, message.usm.boots), (0, 1));
+    assert_eq!((pdu.kind, pdu.request_id), (PDU_REPORT, 42));
+    assert_eq!(
+        pdu.varbinds,
+        vec![([USM_STATS, &[4, 0]].concat(), Value::Counter32(1))]
+    );
+    assert_eq!(silent, None);
+}
+
+#[test]
+fn v3_answers_authenticated_and_encrypted_gets() {
+    // Arrange
+    let current = live(snapshot(), &mut PowerState::default());
+    let mut agent = v3_agent(None);
+    let mut manager = manager(1);
+    let oids = [ups(&[1, 4, 1, 0])];
+    let private = v3_get(&mut manager, "nms", FLAG_AUTH | FLAG_PRIV, &oids);
+    let signed = v3_get(&mut manager, "poller", FLAG_AUTH, &oids);
+    let too_low = v3_get(&mut manager, "nms", FLAG_AUTH, &oids);
+
+    // Act
+    let private = agent.handle(&private, Some(&current), 100);
+    let signed = agent.handle(&signed, Some(&current), 100);
+    let too_low = agent.handle(&too_low, Some(&current), 100);
+
+    // Assert
+    let (message, pdu) = v3_reply(&mut manager, private);
+    assert_eq!(message.flags, FLAG_AUTH | FLAG_PRIV);
+    assert!(matches!(message.data, ScopedData::Encrypted(_)));
+    assert_eq!(pdu.kind, PDU_RESPONSE);
+    assert_eq!(pdu.varbinds, vec![(ups(&[1, 4, 1, 0]), Value::Integer(3))]);
+    let (message, pdu) = v3_reply(&mut manager, signed);
+    assert_eq!((message.flags, message.usm.auth.len()), (FLAG_AUTH, 24));
+    assert_eq!(pdu.varbinds, vec![(ups(&[1, 4, 1, 0]), Value::Integer(3))]);
+    let (_, pdu) = v3_reply(&mut manager, too_low);
+    assert_eq!(
+        (pdu.error_status, pdu.varbinds[0].1.clone()),
+        (16, Value::Null)
+    );
+}
+
+#[test]
+fn v3_refuses_forged_replayed_and_unknown_requests() {
+    // Arrange
+    let mut agent = v3_agent(None);
+    let oids = [ups(&[1, 2, 1, 0])];
+    let mut forged = v3_get(&mut manager(1), "poller", FLAG_AUTH, &oids);
+    let digest = MessageV3::auth_range(&forged).expect("a digest");
+    forged[digest.start] ^= 1;
+    let replayed = v3_get(&mut manager(2), "poller", FLAG_AUTH, &oids);
+    let unknown = v3_get(&mut manager(1), "nobody", 0, &oids);
+
+    // Act
+    let forged = agent.handle(&forged, None, 100);
+    let replayed = agent.handle(&replayed, None, 100);
+    let unknown = agent.handle(&unknown, None, 100);
+    let v2c = agent.handle(&request(1, "public", PDU_GET, &oids), None, 100);
+
+    // Assert
+    let (message, pdu) = v3_reply(&mut manager(1), forged);
+    assert_eq!((message.flags, pdu.kind), (0, PDU_REPORT));
+    assert_eq!(pdu.varbinds[0].0, [USM_STATS, &[5, 0]].concat());
+    let (message, pdu) = v3_reply(&mut manager(1), replayed);
+    assert_eq!((message.flags, message.usm.boots), (FLAG_AUTH, 1));
+    assert_eq!(pdu.varbinds[0].0, [USM_STATS, &[2, 0]].concat());
+    let (_, pdu) = v3_reply(&mut manager(1), unknown);
+    assert_eq!(pdu.varbinds[0].0, [USM_STATS, &[3, 0]].concat());
+    assert!(v2c.is_some());
+}
+
+#[test]
+fn v3_traps_come_from_the_trap_user() {
+    // Arrange
+    let mut agent = v3_agent(Some("nms"));
+    let mut lost = snapshot();
+    lost.events = vec![event(PowerEventKind::MainsLost, EventPhase::Start)];
+    let on_battery = live(lost, &mut PowerState::default());
+
+    // Act
+    let traps = agent.observe(&on_battery, 100);
+
+    // Assert
+    let trap = MessageV3::decode(&traps[0]).expect("a v3 trap");
+    assert_eq!(trap.flags, FLAG_AUTH | FLAG_PRIV);
+    let scoped = manager(1)
+        .incoming(&traps[0], &trap)
+        .expect("an authentic trap");
+    assert_eq!(scoped.pdu.kind, PDU_TRAP_V2);
+    assert_eq!(scoped.pdu.varbinds[1].1, Value::Oid(ups(&[2, 1])));
+}
@@ -0,0 +1,104 @@
+//! Push feed behind `/api/v1/stream`, shared by SSE and WebSocket clients.
+//!
+//! One hub numbers every message once, so all clients see the same `seq`
+//! for the same message and can spot the ones they missed.
+
+use std::sync::Arc;
+
+use nobreak_core::{Snapshot, SnapshotFeed};
+use serde_json::json;
+use tokio::sync::{broadcast, watch};
+use tokio_stream::StreamExt;
+
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamMessage {
+    pub seq: u64,
+    /// `snapshot`, `event` or `alert`; the SSE event name.
+    pub kind: &'static str,
+    /// `{"seq", "type", "data"}` envelope, identical for SSE and WebSocket.
+    pub json: String,
+}
+
+/// Splits one snapshot into its messages: the snapshot itself, then each
+/// event and alert it carries.
+pub fn messages(next_seq: &mut u64, snapshot: &Snapshot) -> Vec<StreamMessage> {
+    let mut out = Vec::with_capacity(1 + snapshot.events.len() + snapshot.alerts.len());
+    let mut push = |kind: &'static str, data: serde_json::Value| {
+        *next_seq += 1;
+        let seq = *next_seq;
+        out.push(StreamMessage {
+            seq,
+            kind,
+            json: json!({ "seq": seq, "type": kind, "data": data }).to_string(),
+        });
+    };
+    push("snapshot", json!(snapshot));
+    for event in &snapshot.events {
+        push("event", json!(event));
+    }
+    for alert in &snapshot.alerts {
+        push("alert", json!(alert));
+    }
+    out
+}
+
+#[derive(Clone)]
+pub struct StreamHub {
+    latest: watch::Receiver<Option<Arc<StreamMessage>>>,
+    messages: broadcast::Sender<Arc<StreamMessage>>,
+}
+
+/// What a new client gets: the latest snapshot to send right away, and a
+/// receiver for everything after it.
+pub struct Subscription {
+    pub initial: Option<Arc<StreamMessage>>,
+    pub rest: broadcast::Receiver<Arc<StreamMessage>>,
+}
+
+impl Subscription {
+    /// Messages at or below this `seq` were already covered by `initial`
+    /// but may still be queued on `rest`.
+    pub fn covered(&self) -> u64 {
+        self.initial.as_ref().map_or(0, |initial| initial.seq)
+    }
+}
+
+impl StreamHub {
+    /// The hub and the task that feeds it; the task runs until the feed ends.
+    pub fn new(feed: SnapshotFeed) -> (Self, impl std::future::Future<Output = ()>) {
+        let (latest_tx, latest) = watch::channel(None);
+        let (messages, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let publisher = messages.clone();
+        let pump = async move {
+            let mut snapshots = feed.stream();
+            let mut next_seq = 0;
+            while let Some(snapshot) = snapshots.next().await {
+                for message in messages_of(&mut next_seq, &snapshot) {
+                    if message.kind == "snapshot" {
+                        latest_tx.send_replace(Some(message.clone()));
+                    }
+                    let _ = publisher.send(message);
+                }
+            }
+        };
+        (Self { latest, messages }, pump)
+    }
+
+    pub fn subscribe(&self) -> Subscription {
+        // Subscribe first so nothing published after `initial` is lost.
+        let rest = self.messages.subscribe();
+        Subscription {
+            initial: self.latest.borrow().clone(),
+            rest,
+        }
+    }
+}
+
+fn messages_of(next_seq: &mut u64, snapshot: &Snapshot) -> Vec<Arc<StreamMessage>> {
+    messages(next_seq, snapshot)
+        .into_iter()
+        .map(Arc::new)
+        .collect()
+}
@@ -0,0 +1,134 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use chrono::Utc;
+use nobreak_core::{EventPhase, PowerEvent, PowerEventKind};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::TcpStream;
+use tokio_stream::StreamExt;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::fixtures::{gated_service, loopback, snapshot};
+use crate::http::run_http;
+use crate::stream::messages;
+
+/// An SSE client on `/api/v1/stream`.
+struct Sse {
+    lines: Lines<BufReader<TcpStream>>,
+}
+
+impl Sse {
+    /// Returns once the response headers are in, so the server has
+    /// already subscribed this client.
+    async fn connect(addr: SocketAddr) -> Self {
+        let mut stream = TcpStream::connect(addr).await.expect("connect");
+        stream
+            .write_all(b"GET /api/v1/stream HTTP/1.1\r\nHost: nobreak\r\n\r\n")
+            .await
+            .expect("write");
+        let mut lines = BufReader::new(stream).lines();
+        while !lines
+            .next_line()
+            .await
+            .expect("read")
+            .expect("headers")
+            .trim()
+            .is_empty()
+        {}
+        Self { lines }
+    }
+
+    /// The next envelope; chunk sizes and the `id`/`event` lines are skipped.
+    async fn next(&mut self) -> Value {
+        loop {
+            let line = self.lines.next_line().await.expect("read").expect("open");
+            if let Some(data) = line.strip_prefix("data: ") {
+                return serde_json::from_str(data).expect("json");
+            }
+        }
+    }
+
+    async fn next_snapshot(&mut self) -> Value {
+        loop {
+            let message = self.next().await;
+            if message["type"] == "snapshot" {
+                return message;
+            }
+        }
+    }
+}
+
+#[test]
+fn every_snapshot_and_event_gets_the_next_sequence_number() {
+    // Arrange
+    let quiet = snapshot();
+    let mut with_event = snapshot();
+    with_event.events.push(PowerEvent {
+        kind: PowerEventKind::MainsLost,
+        phase: EventPhase::Start,
+        started_at: Utc::now(),
+        ended_at: None,
+        duration_ms: None,
+        value: Some(0.0),
+    });
+    let mut next_seq = 0;
+
+    // Act
+    let first = messages(&mut next_seq, &quiet);
+    let second = messages(&mut next_seq, &with_event);
+
+    // Assert
+    let seqs: Vec<_> = first
+        .iter()
+        .chain(&second)
+        .map(|m| (m.seq, m.kind))
+        .collect();
+    assert_eq!(seqs, [(1, "snapshot"), (2, "snapshot"), (3, "event")]);
+    let envelope: serde_json::Value = serde_json::from_str(&second[1].json).expect("json");
+    assert_eq!(envelope["seq"], 3);
+    assert_eq!(envelope["type"], "event");
+    assert_eq!(envelope["data"]["kind"], "MAINS_LOST");
+    let snapshot: serde_json::Value = serde_json::from_str(&first[0].json).expect("json");
+    assert_eq!(snapshot["data"]["vars"]["vInput"], 127.4);
+}
+
+#[tokio::test]
+async fn late_clients_start_from_the_latest_snapshot_once() {
+    // Arrange
+    let (service, permit) = gated_service();
+    let (listener, addr) = loopback().await;
+    let server = tokio::spawn(run_http(service.feed(), listener));
+    let exchange = async {
+        let mut early = Sse::connect(addr).await;
+        permit.send(()).expect("monitor running");
+        let latest = early.next_snapshot().await;
+
+        // Act
+        let mut sse = Sse::connect(addr).await;
+        let url = format!("ws://{addr}/api/v1/stream");
+        let (mut ws, _) = tokio_tungstenite::connect_async(url)
+            .await
+            .expect("upgrade");
+        permit.send(()).expect("monitor running");
+        let sse_frames = [sse.next().await, sse.next().await];
+        let mut ws_frames = Vec::new();
+        while ws_frames.len() < 2 {
+            if let Message::Text(text) = ws.next().await.expect("open").expect("frame") {
+                ws_frames.push(serde_json::from_str::<Value>(&text).expect("json"));
+            }
+        }
+        (latest, early.next_snapshot().await, sse_frames, ws_frames)
+    };
+    let (latest, next, sse_frames, ws_frames) =
+        tokio::time::timeout(Duration::from_secs(5), exchange)
+            .await
+            .expect("server streams");
+    server.abort();
+    service.shutdown().await;
+
+    // Assert
+    assert_eq!(latest["seq"], 1);
+    assert_eq!(sse_frames, [latest.clone(), next.clone()]);
+    assert_eq!(ws_frames, [latest, next]);
+}
@@ -0,0 +1,365 @@
+//! The User-based Security Model for SNMPv3 (RFC 3414): localized keys,
+//! HMAC-SHA authentication, AES-128 privacy (RFC 3826) and the engine's
+//! boot counter.
+//!
+//! The agent is the authoritative engine for everything it receives and
+//! sends, so its own engine ID, boots and time go in every message.
+
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use aes::Aes128;
+use anyhow::{Context, Result};
+use cfb_mode::cipher::{AsyncStreamCipher, KeyIvInit};
+use hmac::{Hmac, Mac};
+use nobreak_core::persist::{load_json, save_json};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use tracing::warn;
+
+use crate::ber::{MessageV3, Oid, ScopedData, ScopedPdu, Value, FLAG_AUTH, FLAG_PRIV};
+use crate::settings::{SnmpAuth, SnmpSink, SnmpUser};
+
+/// `usmStats`; a [`Failure`] is the arc under it.
+const USM_STATS: &[u32] = &[1, 3, 6, 1, 6, 3, 15, 1, 1];
+/// Requests more than this many seconds off `snmpEngineTime` are replays.
+const TIME_WINDOW: i64 = 150;
+/// RFC 3414: boots and time stop here.
+const MAX_COUNTER: i64 = i32::MAX as i64;
+/// RFC 3411 engine ID: enterprise 0 with the high bit set, text format.
+const ENGINE_ID_PREFIX: &[u8] = &[0x80, 0, 0, 0, 4];
+
+/// Why a request was refused, as the `usmStats` counter that reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    UnsupportedSecLevel = 1,
+    NotInTimeWindow = 2,
+    UnknownUserName = 3,
+    UnknownEngineId = 4,
+    WrongDigest = 5,
+    DecryptionError = 6,
+}
+
+/// This agent's `snmpEngineID`, `snmpEngineBoots` and `snmpEngineTime`.
+pub struct Engine {
+    pub id: Vec<u8>,
+    pub boots: i64,
+    started: Instant,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EngineState {
+    engine_id: String,
+    boots: i64,
+}
+
+impl Engine {
+    pub fn new(id: Vec<u8>, boots: i64) -> Self {
+        Self {
+            id,
+            boots,
+            started: Instant::now(),
+        }
+    }
+
+    /// Counts this start in `engine_path`, so boots grow across restarts
+    /// and old requests cannot be replayed. Without a path every start is
+    /// boot 1. The ID is `engine_id`, or one made from `hostname`.
+    pub fn start(config: &SnmpSink, hostname: &str) -> Result<Self> {
+        let id = match &config.engine_id {
+            Some(hex) => parse_hex(hex).context("sinks.snmp.engine_id is not hex")?,
+            None => {
+                let name = if hostname.is_empty() {
+                    "nobreakd"
+                } else {
+                    hostname
+                };
+                let text = &name.as_bytes()[..name.len().min(27)];
+                [ENGINE_ID_PREFIX, text].concat()
+            }
+        };
+        let Some(path) = config.engine_path.as_deref() else {
+            return Ok(Self::new(id, 1));
+        };
+        let boots = previous_boots(path, &id).map_or(1, |boots| (boots + 1).min(MAX_COUNTER));
+        let state = EngineState {
+            engine_id: to_hex(&id),
+            boots,
+        };
+        save_json(path, &state).with_context(|| format!("writing {}", path.display()))?;
+        Ok(Self::new(id, boots))
+    }
+
+    /// Seconds since the engine started.
+    pub fn time(&self) -> i64 {
+        (self.started.elapsed().as_secs() as i64).min(MAX_COUNTER)
+    }
+}
+
+/// Boots kept for this engine ID; another ID starts over.
+fn previous_boots(path: &Path, id: &[u8]) -> Option<i64> {
+    if !path.exists() {
+        return None;
+    }
+    match load_json::<EngineState>(path) {
+        Ok(state) => (state.engine_id == to_hex(id)).then_some(state.boots),
+        Err(err) => {
+            warn!(path = %path.display(), %err, "ignoring unreadable snmp engine state");
+            None
+        }
+    }
+}
+
+pub fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    let text = text.strip_prefix("0x").unwrap_or(text);
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// RFC 3414 A.2: the password stretched to 1 MB and hashed, then tied to
+/// one engine as H(Ku || engineID || Ku).
+pub fn localized_key(auth: SnmpAuth, password: &[u8], engine_id: &[u8]) -> Vec<u8> {
+    fn localize<D: Digest>(password: &[u8], engine_id: &[u8]) -> Vec<u8> {
+        let stretched: Vec<u8> = password.iter().copied().cycle().take(1 << 20).collect();
+        let ku = D::digest(&stretched);
+        D::new()
+            .chain_update(&ku)
+            .chain_update(engine_id)
+            .chain_update(&ku)
+            .finalize()
+            .to_vec()
+    }
+    match auth {
+        SnmpAuth::Sha => localize::<Sha1>(password, engine_id),
+        SnmpAuth::Sha256 => localize::<Sha256>(password, engine_id),
+    }
+}
+
+/// `msgAuthenticationParameters` for `message`: HMAC-SHA-96 or
+/// HMAC-192-SHA-256.
+pub fn digest(auth: SnmpAuth, key: &[u8], message: &[u8]) -> Vec<u8> {
+    let (full, len) = match auth {
+        SnmpAuth::Sha => (
+            Hmac::<Sha1>::new_from_slice(key)
+                .expect("hmac takes keys of any length")
+                .chain_update(message)
+                .finalize()
+                .into_bytes()
+                .to_vec(),
+            12,
+        ),
+        SnmpAuth::Sha256 => (
+            Hmac::<Sha256>::new_from_slice(key)
+                .expect("hmac takes keys of any length")
+                .chain_update(message)
+                .finalize()
+                .into_bytes()
+                .to_vec(),
+            24,
+        ),
+    };
+    full[..len].to_vec()
+}
+
+fn digest_len(auth: SnmpAuth) -> usize {
+    match auth {
+        SnmpAuth::Sha => 12,
+        SnmpAuth::Sha256 => 24,
+    }
+}
+
+/// Compares without returning early, so timing does not leak the digest.
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// RFC 3826: AES-128-CFB keyed by the first 16 bytes of the privacy key,
+/// with boots, time and the salt as the IV.
+fn aes_cfb(encrypt: bool, key: &[u8], boots: i64, time: i64, salt: &[u8], data: &mut [u8]) {
+    let mut iv = [0; 16];
+    iv[..4].copy_from_slice(&(boots as u32).to_be_bytes());
+    iv[4..8].copy_from_slice(&(time as u32).to_be_bytes());
+    iv[8..].copy_from_slice(salt);
+    let key = &key[..16];
+    if encrypt {
+        cfb_mode::Encryptor::<Aes128>::new_from_slices(key, &iv)
+            .expect("a 16 byte key and iv")
+            .encrypt(data);
+    } else {
+        cfb_mode::Decryptor::<Aes128>::new_from_slices(key, &iv)
+            .expect("a 16 byte key and iv")
+            .decrypt(data);
+    }
+}
+
+struct User {
+    name: Vec<u8>,
+    auth: SnmpAuth,
+    auth_key: Vec<u8>,
+    priv_key: Option<Vec<u8>>,
+}
+
+impl User {
+    /// The least security a request from this user needs.
+    fn level(&self) -> u8 {
+        FLAG_AUTH | self.priv_key.as_ref().map_or(0, |_| FLAG_PRIV)
+    }
+}
+
+pub struct Usm {
+    pub engine: Engine,
+    users: Vec<User>,
+    /// `usmStats` counters, by [`Failure`] arc.
+    stats: [u32; 6],
+    salt: u64,
+}
+
+impl Usm {
+    /// Localizes every user's keys to `engine`, which takes a moment per
+    /// password.
+    pub fn new(engine: Engine, users: &[SnmpUser]) -> Self {
+        let users = users
+            .iter()
+            .map(|user| User {
+                name: user.name.as_bytes().to_vec(),
+                auth: user.auth,
+                auth_key: localized_key(user.auth, user.auth_password.as_bytes(), &engine.id),
+                priv_key: user
+                    .priv_password
+                    .as_ref()
+                    .map(|password| localized_key(user.auth, password.as_bytes(), &engine.id)),
+            })
+            .collect();
+        let salt = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos() as u64);
+        Self {
+            engine,
+            users,
+            stats: [0; 6],
+            salt,
+        }
+    }
+
+    /// The security level `user` is configured for, as msgFlags bits.
+    pub fn level(&self, user: &[u8]) -> Option<u8> {
+        self.user(user).map(User::level)
+    }
+
+    fn user(&self, name: &[u8]) -> Option<&User> {
+        self.users.iter().find(|user| user.name == name)
+    }
+
+    /// The counter varbind a Report for `failure` carries.
+    pub fn stat(&self, failure: Failure) -> (Oid, Value) {
+        let arc = failure as u32;
+        (
+            [USM_STATS, &[arc, 0]].concat(),
+            Value::Counter32(self.stats[arc as usize - 1]),
+        )
+    }
+
+    /// RFC 3414 3.2: checks that `message`, received as `raw`, is for this
+    /// engine, from a known user, authentic and timely, and decrypts it.
+    pub fn incoming(&mut self, raw: &[u8], message: &MessageV3) -> Result<ScopedPdu, Failure> {
+        let checked = self.check(raw, message);
+        if let Err(failure) = checked {
+            let count = &mut self.stats[failure as usize - 1];
+            *count = count.wrapping_add(1);
+        }
+        checked
+    }
+
+    fn check(&self, raw: &[u8], message: &MessageV3) -> Result<ScopedPdu, Failure> {
+        let params = &message.usm;
+        if params.engine_id != self.engine.id {
+            return Err(Failure::UnknownEngineId);
+        }
+        let user = self.user(&params.user).ok_or(Failure::UnknownUserName)?;
+        let level = message.flags & (FLAG_AUTH | FLAG_PRIV);
+        if level & !user.level() != 0 {
+            return Err(Failure::UnsupportedSecLevel);
+        }
+        if level & FLAG_AUTH != 0 {
+            let range = MessageV3::auth_range(raw).map_err(|_| Failure::WrongDigest)?;
+            let mut zeroed = raw.to_vec();
+            zeroed[range].fill(0);
+            if !same(&digest(user.auth, &user.auth_key, &zeroed), &params.auth) {
+                return Err(Failure::WrongDigest);
+            }
+            if params.boots != self.engine.boots
+                || self.engine.boots >= MAX_COUNTER
+                || (params.time - self.engine.time()).abs() > TIME_WINDOW
+            {
+                return Err(Failure::NotInTimeWindow);
+            }
+        }
+        match &message.data {
+            ScopedData::Plain(scoped) => Ok(scoped.clone()),
+            ScopedData::Encrypted(bytes) => {
+                let key = user.priv_key.as_ref().ok_or(Failure::DecryptionError)?;
+                if params.privacy.len() != 8 {
+                    return Err(Failure::DecryptionError);
+                }
+                let mut plain = bytes.clone();
+                aes_cfb(
+                    false,
+                    key,
+                    params.boots,
+                    params.time,
+                    &params.privacy,
+                    &mut plain,
+                );
+                ScopedPdu::decode(&plain).map_err(|_| Failure::DecryptionError)
+            }
+        }
+    }
+
+    /// RFC 3414 3.1: fills in this engine's parameters, then encrypts and
+    /// signs `message` as its flags ask, for the user it names. `None`
+    /// when that user has no keys for the level.
+    pub fn outgoing(&mut self, mut message: MessageV3) -> Option<Vec<u8>> {
+        let params = &mut message.usm;
+        params.engine_id = self.engine.id.clone();
+        params.boots = self.engine.boots;
+        params.time = self.engine.time();
+        if message.flags & FLAG_AUTH == 0 {
+            return Some(message.encode());
+        }
+        self.salt = self.salt.wrapping_add(1);
+        let salt = self.salt.to_be_bytes();
+        let user = self.user(&params.user)?;
+        if message.flags & FLAG_PRIV != 0 {
+            let key = user.priv_key.as_ref()?;
+            let ScopedData::Plain(scoped) = &message.data else {
+                return None;
+            };
+            let mut bytes = scoped.encode();
+            aes_cfb(true, key, params.boots, params.time, &salt, &mut bytes);
+            params.privacy = salt.to_vec();
+            message.data = ScopedData::Encrypted(bytes);
+        }
+        message.usm.auth = vec![0; digest_len(user.auth)];
+        let mut bytes = message.encode();
+        let range = MessageV3::auth_range(&bytes).ok()?;
+        let signed = digest(user.auth, &user.auth_key, &bytes);
+        bytes[range].copy_from_slice(&signed);
+        Some(bytes)
+    }
+}
+
+/// Room a v3 reply needs around its PDU: header, USM parameters with the
+/// longest digest, the salt and the engine ID again as the context.
+pub fn envelope_len(engine_id: &[u8], user: &[u8]) -> usize {
+    64 + 2 * engine_id.len() + user.len()
+}
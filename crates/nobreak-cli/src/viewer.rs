@@ -5,8 +5,10 @@ use std::time::{Duration, Instant};
 use anyhow::Result;
 use crossterm::event::{self, Event, KeyCode};
 use crossterm::execute;
-use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
-use nobreak_core::{Monitor, Snapshot, UpsDriver};
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use nobreak_core::{Snapshot, SnapshotFeed};
 use ratatui::backend::CrosstermBackend;
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
 use ratatui::style::{Color, Modifier, Style};
@@ -93,6 +95,9 @@ impl ViewerState {
     fn update(&mut self, snapshot: Snapshot, window_sec: f64) {
         let t = self.start.elapsed().as_secs_f64();
         for (idx, (key, _, _)) in METRIC_KEYS.iter().enumerate() {
+            if snapshot.vars_meta.contains_key(*key) {
+                continue;
+            }
             if let Some(value) = snapshot.vars.get(*key).and_then(|v| v.as_f64()) {
                 self.series[idx].push(t, value, window_sec);
             }
@@ -101,7 +106,7 @@ impl ViewerState {
     }
 }
 
-pub async fn run_viewer<D: UpsDriver>(monitor: &mut Monitor<D>, window_sec: f64) -> Result<()> {
+pub async fn run_viewer(feed: SnapshotFeed, window_sec: f64) -> Result<()> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen)?;
@@ -109,7 +114,7 @@ pub async fn run_viewer<D: UpsDriver>(monitor: &mut Monitor<D>, window_sec: f64)
     let mut terminal = Terminal::new(backend)?;
 
     let mut state = ViewerState::new();
-    let mut next_tick = Instant::now();
+    let mut latest = feed.watch();
     let mut command_buffer = String::new();
 
     let run_result = async {
@@ -136,11 +141,10 @@ pub async fn run_viewer<D: UpsDriver>(monitor: &mut Monitor<D>, window_sec: f64)
                 }
             }
 
-            if Instant::now() >= next_tick {
-                let snapshot = monitor.tick().await;
-                let interval = monitor.effective_interval();
-                state.update(snapshot, window_sec);
-                next_tick = Instant::now() + interval;
+            if latest.has_changed()? {
+                if let Some(snapshot) = latest.borrow_and_update().clone() {
+                    state.update(snapshot, window_sec);
+                }
             }
 
             terminal.draw(|frame| draw_ui(frame.size(), frame, &state, window_sec))?;
@@ -160,10 +164,7 @@ pub async fn run_viewer<D: UpsDriver>(monitor: &mut Monitor<D>, window_sec: f64)
 fn draw_ui(area: Rect, frame: &mut ratatui::Frame<'_>, state: &ViewerState, window_sec: f64) {
     let rows = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Length(3),
-            Constraint::Min(0),
-        ])
+        .constraints([Constraint::Length(3), Constraint::Min(0)])
         .split(area);
 
     let header = render_header(state, window_sec);
@@ -188,7 +189,13 @@ fn draw_ui(area: Rect, frame: &mut ratatui::Frame<'_>, state: &ViewerState, wind
 
         for col in cols.iter().copied() {
             if idx < state.series.len() {
-                render_metric_chart(frame, col, &state.series[idx], state.start.elapsed().as_secs_f64(), window_sec);
+                render_metric_chart(
+                    frame,
+                    col,
+                    &state.series[idx],
+                    state.start.elapsed().as_secs_f64(),
+                    window_sec,
+                );
             } else {
                 let empty = Paragraph::new(Line::from(" "));
                 frame.render_widget(empty, col);
@@ -223,7 +230,12 @@ fn render_header(state: &ViewerState, window_sec: f64) -> Paragraph<'static> {
             window_sec as u64
         );
         lines.push(Line::from(vec![
-            Span::styled("Nobreak Graph Viewer  ", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+            Span::styled(
+                "Nobreak Graph Viewer  ",
+                Style::default()
+                    .fg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD),
+            ),
             Span::raw(status),
         ]));
         lines.push(Line::from(device));
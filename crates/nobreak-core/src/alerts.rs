@@ -0,0 +1,249 @@
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::snapshot::Snapshot;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+/// What a rule watches. `metric` is any name accepted by [`Snapshot::metric`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AlertCondition {
+    Above {
+        metric: String,
+        value: f64,
+    },
+    Below {
+        metric: String,
+        value: f64,
+    },
+    Stale,
+    Increase {
+        metric: String,
+        by: f64,
+        within_sec: u64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub name: String,
+    pub severity: Severity,
+    pub condition: AlertCondition,
+    /// How long the condition must hold before the alert fires.
+    #[serde(default)]
+    pub for_sec: u64,
+    /// Margin an `above`/`below` value has to clear before the alert resolves.
+    #[serde(default)]
+    pub hysteresis: f64,
+}
+
+/// Top-level shape of an alert rules file: a list of `[[alerts]]` tables.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AlertRuleSet {
+    #[serde(default)]
+    pub alerts: Vec<AlertRule>,
+}
+
+impl AlertRuleSet {
+    pub fn validate(&self) -> Result<(), String> {
+        let mut names = std::collections::BTreeSet::new();
+        for rule in &self.alerts {
+            if rule.name.trim().is_empty() {
+                return Err("alert rule with empty name".to_string());
+            }
+            if !names.insert(rule.name.as_str()) {
+                return Err(format!("duplicate alert rule name: {}", rule.name));
+            }
+            if rule.hysteresis < 0.0 {
+                return Err(format!(
+                    "alert {}: hysteresis must not be negative",
+                    rule.name
+                ));
+            }
+            if let AlertCondition::Increase { within_sec: 0, .. } = rule.condition {
+                return Err(format!("alert {}: within_sec must be positive", rule.name));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+/// One alert edge. `since` is when the condition started to hold, before
+/// `for_sec` ran out; `firing_since` is when the alert fired. Both records of
+/// one alert carry the same pair.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlertRecord {
+    pub rule: String,
+    pub severity: Severity,
+    pub state: AlertState,
+    pub since: DateTime<Utc>,
+    pub firing_since: DateTime<Utc>,
+    pub at: DateTime<Utc>,
+    pub value: Option<f64>,
+    pub condition: AlertCondition,
+}
+
+#[derive(Debug, Clone)]
+struct RuleState {
+    rule: AlertRule,
+    pending_since: Option<DateTime<Utc>>,
+    firing_since: Option<DateTime<Utc>>,
+    history: VecDeque<(DateTime<Utc>, f64)>,
+}
+
+/// Evaluates alert rules against each snapshot and reports edges only:
+/// one `firing` record when a rule trips and one `resolved` when it clears.
+#[derive(Debug, Clone, Default)]
+pub struct AlertEngine {
+    rules: Vec<RuleState>,
+}
+
+impl AlertEngine {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        Self {
+            rules: rules
+                .into_iter()
+                .map(|rule| RuleState {
+                    rule,
+                    pending_since: None,
+                    firing_since: None,
+                    history: VecDeque::new(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Replaces the rule set, keeping the state of rules whose definition is
+    /// unchanged so a reload does not re-fire them.
+    pub fn set_rules(&mut self, rules: Vec<AlertRule>) {
+        let mut previous = std::mem::take(&mut self.rules);
+        self.rules = rules
+            .into_iter()
+            .map(|rule| match previous.iter().position(|s| s.rule == rule) {
+                Some(idx) => previous.swap_remove(idx),
+                None => RuleState {
+                    rule,
+                    pending_since: None,
+                    firing_since: None,
+                    history: VecDeque::new(),
+                },
+            })
+            .collect();
+    }
+
+    /// Rules currently firing, as `(name, severity)`.
+    pub fn firing(&self) -> Vec<(String, Severity)> {
+        self.rules
+            .iter()
+            .filter(|s| s.firing_since.is_some())
+            .map(|s| (s.rule.name.clone(), s.rule.severity))
+            .collect()
+    }
+
+    pub fn evaluate(&mut self, snapshot: &Snapshot) -> Vec<AlertRecord> {
+        let now = snapshot.ts;
+        let mut out = Vec::new();
+
+        for state in &mut self.rules {
+            let Some((met, value)) = state.check(snapshot) else {
+                continue;
+            };
+
+            if !met {
+                let pending = state.pending_since.take();
+                if let Some(firing) = state.firing_since.take() {
+                    let since = pending.unwrap_or(firing);
+                    out.push(state.record(AlertState::Resolved, since, firing, now, value));
+                }
+                continue;
+            }
+
+            if state.firing_since.is_some() {
+                continue;
+            }
+            let pending = *state.pending_since.get_or_insert(now);
+            if now - pending >= Duration::seconds(state.rule.for_sec as i64) {
+                state.firing_since = Some(now);
+                out.push(state.record(AlertState::Firing, pending, now, now, value));
+            }
+        }
+
+        out
+    }
+}
+
+impl RuleState {
+    /// Whether the condition holds on this snapshot. `None` means the metric
+    /// is unavailable and the rule keeps its current state.
+    fn check(&mut self, snapshot: &Snapshot) -> Option<(bool, Option<f64>)> {
+        let firing = self.firing_since.is_some();
+        let hysteresis = if firing { self.rule.hysteresis } else { 0.0 };
+
+        match &self.rule.condition {
+            AlertCondition::Above { metric, value } => {
+                let v = snapshot.metric(metric)?;
+                Some((v > value - hysteresis, Some(v)))
+            }
+            AlertCondition::Below { metric, value } => {
+                let v = snapshot.metric(metric)?;
+                Some((v < value + hysteresis, Some(v)))
+            }
+            AlertCondition::Stale => Some((snapshot.freshness.stale, None)),
+            AlertCondition::Increase {
+                metric,
+                by,
+                within_sec,
+            } => {
+                let v = snapshot.metric(metric)?;
+                let horizon = snapshot.ts - Duration::seconds(*within_sec as i64);
+                while self.history.front().is_some_and(|(ts, _)| *ts < horizon) {
+                    self.history.pop_front();
+                }
+                self.history.push_back((snapshot.ts, v));
+                let floor = self
+                    .history
+                    .iter()
+                    .map(|(_, v)| *v)
+                    .fold(f64::INFINITY, f64::min);
+                let increase = v - floor;
+                Some((increase >= *by, Some(increase)))
+            }
+        }
+    }
+
+    fn record(
+        &self,
+        state: AlertState,
+        since: DateTime<Utc>,
+        firing_since: DateTime<Utc>,
+        at: DateTime<Utc>,
+        value: Option<f64>,
+    ) -> AlertRecord {
+        AlertRecord {
+            rule: self.rule.name.clone(),
+            severity: self.rule.severity,
+            state,
+            since,
+            firing_since,
+            at,
+            value,
+            condition: self.rule.condition.clone(),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use crate::alerts::{AlertEngine, AlertRuleSet, AlertState, Severity};
+use crate::test_driver::snapshot_at;
+
+const RULES: &str = r#"
+[[alerts]]
+name = "inverter-hot"
+severity = "warning"
+condition = { kind = "above", metric = "temperature", value = 45.0 }
+for_sec = 60
+hysteresis = 2.0
+
+[[alerts]]
+name = "reconnect-storm"
+severity = "critical"
+condition = { kind = "increase", metric = "reconnects", by = 3.0, within_sec = 300 }
+"#;
+
+fn engine() -> AlertEngine {
+    let set: AlertRuleSet = toml::from_str(RULES).expect("parse rules");
+    set.validate().expect("valid rules");
+    AlertEngine::new(set.alerts)
+}
+
+#[test]
+fn threshold_fires_after_hold_time_and_resolves_past_hysteresis() {
+    // Arrange
+    let mut engine = engine();
+
+    // Act
+    let early = engine.evaluate(&snapshot_at(0, true, &[("temperature", 47.0)]));
+    let held = engine.evaluate(&snapshot_at(59, true, &[("temperature", 46.0)]));
+    let fired = engine.evaluate(&snapshot_at(60, true, &[("temperature", 46.0)]));
+    let in_band = engine.evaluate(&snapshot_at(70, true, &[("temperature", 44.0)]));
+    let resolved = engine.evaluate(&snapshot_at(80, true, &[("temperature", 42.5)]));
+
+    // Assert
+    assert!(early.is_empty() && held.is_empty());
+    assert_eq!(fired.len(), 1);
+    assert_eq!(fired[0].state, AlertState::Firing);
+    assert_eq!(fired[0].severity, Severity::Warning);
+    assert_eq!(fired[0].since, snapshot_at(0, true, &[]).ts);
+    assert_eq!(fired[0].firing_since, fired[0].at);
+    assert!(in_band.is_empty(), "44 C is inside the hysteresis band");
+    assert_eq!(resolved[0].state, AlertState::Resolved);
+    assert_eq!(
+        (resolved[0].since, resolved[0].firing_since),
+        (fired[0].since, fired[0].firing_since),
+        "both edges carry the same times"
+    );
+    assert!(engine.firing().is_empty());
+}
+
+#[test]
+fn increase_rule_tracks_counter_growth_within_window() {
+    // Arrange
+    let mut engine = engine();
+    let tick = |secs: i64, reconnects: u64| {
+        let mut snapshot = snapshot_at(secs, true, &[]);
+        snapshot.quality.reconnects = reconnects;
+        snapshot
+    };
+
+    // Act
+    engine.evaluate(&tick(0, 1));
+    let slow = engine.evaluate(&tick(400, 3));
+    let storm = engine.evaluate(&tick(500, 6));
+
+    // Assert
+    assert!(slow.is_empty(), "first sample has aged out of the window");
+    assert_eq!(storm[0].rule, "reconnect-storm");
+    assert_eq!(storm[0].value, Some(3.0));
+}
+
+#[test]
+fn duplicate_rule_names_are_rejected() {
+    // Arrange
+    let doubled = format!("{RULES}{}", RULES.replace("reconnect-storm", "other"));
+    let set: AlertRuleSet = toml::from_str(&doubled).expect("parse rules");
+
+    // Act
+    let result = set.validate();
+
+    // Assert
+    assert!(result.unwrap_err().contains("inverter-hot"));
+}
@@ -0,0 +1,310 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::persist::{load_json, save_json};
+use crate::profile::BatteryModel;
+use crate::snapshot::Snapshot;
+
+/// Episodes kept in the state file; older ones stop influencing the trend.
+const MAX_EPISODES: usize = 100;
+/// Episodes averaged into the current state-of-health figure.
+const SOH_EPISODES: usize = 5;
+const SECS_PER_MONTH: f64 = 30.0 * 86_400.0;
+/// How often an open episode is saved while on battery.
+const SAVE_EVERY_SEC: i64 = 60;
+/// An open episode whose last sample is older than this, e.g. after a
+/// restart or a long device loss, is closed at that sample instead of being
+/// stretched over time nobody observed.
+const RESUME_WITHIN_SEC: i64 = 300;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BatteryHealthConfig {
+    /// JSON file the episode history is persisted to; `None` keeps it in memory.
+    pub state_path: Option<PathBuf>,
+    /// Warn once estimated capacity falls below this fraction of nominal.
+    pub warn_below_fraction: f64,
+    /// Shorter or shallower episodes are recorded but not used for health.
+    pub min_episode_sec: f64,
+    pub min_depth_pct: f64,
+}
+
+impl Default for BatteryHealthConfig {
+    fn default() -> Self {
+        Self {
+            state_path: None,
+            warn_below_fraction: 0.8,
+            min_episode_sec: 60.0,
+            min_depth_pct: 5.0,
+        }
+    }
+}
+
+/// One on-battery period, summarised when mains returns.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DischargeEpisode {
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub duration_sec: f64,
+    pub start_voltage: Option<f64>,
+    pub end_voltage: Option<f64>,
+    pub avg_load_pct: f64,
+    pub voltage_drop_v_per_min: Option<f64>,
+    pub depth_of_discharge_pct: f64,
+    /// Observed capacity relative to the battery model, when the episode was
+    /// long and deep enough to judge.
+    pub capacity_fraction: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct HealthState {
+    episodes: Vec<DischargeEpisode>,
+    /// The episode in progress, saved so a restart on battery does not lose it.
+    #[serde(default)]
+    open: Option<OpenEpisode>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OpenEpisode {
+    started_at: DateTime<Utc>,
+    start_voltage: Option<f64>,
+    start_charge: Option<f64>,
+    last_at: DateTime<Utc>,
+    last_voltage: Option<f64>,
+    last_charge: Option<f64>,
+    load_sum: f64,
+    load_samples: u32,
+}
+
+/// Records discharge episodes and derives a state-of-health estimate and its
+/// trend from how fast the battery drains compared with the model.
+#[derive(Debug, Clone)]
+pub struct BatteryHealthTracker {
+    config: BatteryHealthConfig,
+    model: BatteryModel,
+    state: HealthState,
+    last_saved: Option<DateTime<Utc>>,
+}
+
+impl BatteryHealthTracker {
+    /// Loads persisted history if configured; an unreadable state file is
+    /// logged and replaced rather than stopping the monitor.
+    pub fn new(config: BatteryHealthConfig, model: BatteryModel) -> Self {
+        let state = config
+            .state_path
+            .as_deref()
+            .filter(|path| path.exists())
+            .and_then(|path| match load_json::<HealthState>(path) {
+                Ok(state) => Some(state),
+                Err(err) => {
+                    warn!(path=%path.display(), %err, "ignoring unreadable battery health state");
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        Self {
+            config,
+            model,
+            state,
+            last_saved: None,
+        }
+    }
+
+    pub fn set_model(&mut self, model: BatteryModel) {
+        self.model = model;
+    }
+
+    pub fn episodes(&self) -> &[DischargeEpisode] {
+        &self.state.episodes
+    }
+
+    /// Weighted mean capacity fraction of the most recent usable episodes.
+    pub fn state_of_health(&self) -> Option<f64> {
+        let recent = self
+            .state
+            .episodes
+            .iter()
+            .rev()
+            .filter_map(|e| e.capacity_fraction.map(|c| (c, e.depth_of_discharge_pct)))
+            .take(SOH_EPISODES)
+            .collect::<Vec<_>>();
+        let weight: f64 = recent.iter().map(|(_, depth)| depth).sum();
+        if weight <= 0.0 {
+            return None;
+        }
+        Some(recent.iter().map(|(c, depth)| c * depth).sum::<f64>() / weight)
+    }
+
+    /// Least-squares slope of capacity fraction per 30 days.
+    pub fn trend_per_month(&self) -> Option<f64> {
+        let points = self
+            .state
+            .episodes
+            .iter()
+            .filter_map(|e| e.capacity_fraction.map(|c| (e.ended_at, c)))
+            .collect::<Vec<_>>();
+        if points.len() < 2 {
+            return None;
+        }
+        let t0 = points[0].0;
+        let n = points.len() as f64;
+        let xs = points
+            .iter()
+            .map(|(ts, _)| (*ts - t0).num_seconds() as f64 / SECS_PER_MONTH);
+        let (sx, sy, sxx, sxy) = xs
+            .zip(points.iter().map(|(_, c)| *c))
+            .fold((0.0, 0.0, 0.0, 0.0), |(sx, sy, sxx, sxy), (x, y)| {
+                (sx + x, sy + y, sxx + x * x, sxy + x * y)
+            });
+        let denom = n * sxx - sx * sx;
+        (denom > f64::EPSILON).then(|| (n * sxy - sx * sy) / denom)
+    }
+
+    pub fn update(&mut self, snapshot: &mut Snapshot, on_battery: bool) {
+        let gap = |open: &OpenEpisode| (snapshot.ts - open.last_at).num_seconds();
+        if let Some(open) = self
+            .state
+            .open
+            .take_if(|open| !on_battery || gap(open) > RESUME_WITHIN_SEC)
+        {
+            self.finish(open);
+        }
+        if on_battery {
+            self.track(snapshot);
+            self.maybe_save(snapshot.ts);
+        }
+
+        let vars = &mut snapshot.vars;
+        vars.insert(
+            "batteryDischargeEpisodes".to_string(),
+            serde_json::Value::from(self.state.episodes.len() as u64),
+        );
+        if let Some(soh) = self.state_of_health() {
+            vars.insert(
+                "batteryHealthPct".to_string(),
+                serde_json::Value::from((soh * 1000.0).round() / 10.0),
+            );
+            vars.insert(
+                "batteryHealthWarning".to_string(),
+                serde_json::Value::Bool(soh < self.config.warn_below_fraction),
+            );
+        }
+        if let Some(trend) = self.trend_per_month() {
+            vars.insert(
+                "batteryHealthTrendPctPerMonth".to_string(),
+                serde_json::Value::from((trend * 1000.0).round() / 10.0),
+            );
+        }
+    }
+
+    fn track(&mut self, snapshot: &Snapshot) {
+        let voltage = snapshot.metric("vBattery");
+        let charge = snapshot.metric("cBattery");
+        let load = snapshot.metric("pOutput");
+
+        let open = self.state.open.get_or_insert(OpenEpisode {
+            started_at: snapshot.ts,
+            start_voltage: voltage,
+            start_charge: charge,
+            last_at: snapshot.ts,
+            last_voltage: voltage,
+            last_charge: charge,
+            load_sum: 0.0,
+            load_samples: 0,
+        });
+
+        open.last_at = snapshot.ts;
+        open.start_voltage = open.start_voltage.or(voltage);
+        open.start_charge = open.start_charge.or(charge);
+        open.last_voltage = voltage.or(open.last_voltage);
+        open.last_charge = charge.or(open.last_charge);
+        if let Some(load) = load {
+            open.load_sum += load;
+            open.load_samples += 1;
+        }
+    }
+
+    fn finish(&mut self, open: OpenEpisode) {
+        let duration_sec = (open.last_at - open.started_at).num_milliseconds() as f64 / 1000.0;
+        let avg_load_pct = if open.load_samples > 0 {
+            open.load_sum / open.load_samples as f64
+        } else {
+            0.0
+        };
+        let depth = match (open.start_charge, open.last_charge) {
+            (Some(start), Some(end)) => (start - end).max(0.0),
+            _ => 0.0,
+        };
+        let voltage_drop = match (open.start_voltage, open.last_voltage) {
+            (Some(start), Some(end)) if duration_sec > 0.0 => {
+                Some((start - end) / (duration_sec / 60.0))
+            }
+            _ => None,
+        };
+
+        let usable =
+            duration_sec >= self.config.min_episode_sec && depth >= self.config.min_depth_pct;
+        let capacity_fraction = usable
+            .then(|| self.model.full_runtime_sec(avg_load_pct))
+            .flatten()
+            .map(|full| {
+                // Runtime the model expects for this depth versus what it took.
+                let expected_sec = full * depth / 100.0;
+                (duration_sec / expected_sec).clamp(0.0, 1.5)
+            });
+
+        let episode = DischargeEpisode {
+            started_at: open.started_at,
+            ended_at: open.last_at,
+            duration_sec,
+            start_voltage: open.start_voltage,
+            end_voltage: open.last_voltage,
+            avg_load_pct,
+            voltage_drop_v_per_min: voltage_drop,
+            depth_of_discharge_pct: depth,
+            capacity_fraction,
+        };
+
+        self.state.episodes.push(episode);
+        if self.state.episodes.len() > MAX_EPISODES {
+            let excess = self.state.episodes.len() - MAX_EPISODES;
+            self.state.episodes.drain(..excess);
+        }
+
+        if let Some(soh) = self.state_of_health() {
+            if soh < self.config.warn_below_fraction {
+                warn!(
+                    soh_pct = soh * 100.0,
+                    threshold_pct = self.config.warn_below_fraction * 100.0,
+                    "battery capacity below configured fraction of nominal"
+                );
+            }
+        }
+
+        self.flush();
+    }
+
+    /// Saves the history and the open episode, if a state path is set.
+    pub fn flush(&mut self) {
+        if let Some(path) = &self.config.state_path {
+            if let Err(err) = save_json(path, &self.state) {
+                warn!(path=%path.display(), %err, "failed to persist battery health state");
+            }
+        }
+    }
+
+    fn maybe_save(&mut self, now: DateTime<Utc>) {
+        if self
+            .last_saved
+            .is_some_and(|saved| (now - saved).num_seconds() < SAVE_EVERY_SEC)
+        {
+            return;
+        }
+        self.last_saved = Some(now);
+        self.flush();
+    }
+}
@@ -0,0 +1,159 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::battery_health::{BatteryHealthConfig, BatteryHealthTracker};
+use crate::profile::ModelProfile;
+use crate::snapshot::Snapshot;
+use crate::test_driver::snapshot_at;
+
+fn temp_state(name: &str) -> PathBuf {
+    let uniq = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("unix epoch")
+        .as_nanos();
+    env::temp_dir().join(format!("nobreak-tests-{name}-{uniq}/battery.json"))
+}
+
+/// Runs one discharge at 50% load from 100% charge, losing `pct_per_min`.
+fn discharge(tracker: &mut BatteryHealthTracker, start: i64, minutes: i64, pct_per_min: f64) {
+    for minute in 0..=minutes {
+        let charge = 100.0 - pct_per_min * minute as f64;
+        let volts = 52.0 - 0.5 * minute as f64;
+        let mut snapshot = snapshot_at(
+            start + minute * 60,
+            true,
+            &[("pOutput", 50.0), ("cBattery", charge), ("vBattery", volts)],
+        );
+        tracker.update(&mut snapshot, true);
+    }
+    let mut restored = snapshot_at(start + minutes * 60 + 1, true, &[("cBattery", 90.0)]);
+    tracker.update(&mut restored, false);
+}
+
+#[test]
+fn episode_records_depth_rate_and_capacity() {
+    // Arrange
+    let model = ModelProfile::ragtech_3200va().battery;
+    let mut tracker = BatteryHealthTracker::new(BatteryHealthConfig::default(), model);
+
+    // Act: the model expects 12 min to empty at 50% load; this bank drains
+    // 40% in 4 min, i.e. at 83% of expected capacity.
+    discharge(&mut tracker, 0, 4, 10.0);
+
+    // Assert
+    let episode = &tracker.episodes()[0];
+    assert_eq!(episode.depth_of_discharge_pct, 40.0);
+    assert_eq!(episode.voltage_drop_v_per_min, Some(0.5));
+    assert_eq!(episode.avg_load_pct, 50.0);
+    let soh = tracker.state_of_health().expect("soh");
+    assert!((soh - 240.0 / 288.0).abs() < 1e-9);
+}
+
+#[test]
+fn history_survives_restart_and_trend_follows_decline() {
+    // Arrange
+    let path = temp_state("health-persist");
+    let config = BatteryHealthConfig {
+        state_path: Some(path.clone()),
+        ..BatteryHealthConfig::default()
+    };
+    let model = ModelProfile::ragtech_3200va().battery;
+    let mut first = BatteryHealthTracker::new(config.clone(), model.clone());
+    discharge(&mut first, 0, 4, 8.0);
+
+    // Act
+    let mut second = BatteryHealthTracker::new(config, model);
+    discharge(&mut second, 30 * 86_400, 4, 14.0);
+    let mut snapshot = snapshot_at(30 * 86_400 + 600, true, &[]);
+    second.update(&mut snapshot, false);
+
+    // Assert
+    assert_eq!(second.episodes().len(), 2);
+    let trend = second.trend_per_month().expect("trend");
+    assert!(trend < 0.0, "faster drain means declining health");
+    assert_eq!(snapshot.vars["batteryDischargeEpisodes"], 2);
+    assert_eq!(snapshot.vars["batteryHealthWarning"], true);
+
+    let _ = fs::remove_dir_all(path.parent().expect("temp dir"));
+}
+
+#[test]
+fn short_blips_do_not_count_toward_health() {
+    // Arrange
+    let model = ModelProfile::ragtech_3200va().battery;
+    let mut tracker = BatteryHealthTracker::new(BatteryHealthConfig::default(), model);
+
+    // Act
+    discharge(&mut tracker, 0, 0, 0.0);
+
+    // Assert
+    assert_eq!(tracker.episodes().len(), 1);
+    assert!(tracker.episodes()[0].capacity_fraction.is_none());
+    assert!(tracker.state_of_health().is_none());
+}
+
+fn on_battery(secs: i64, charge: f64) -> Snapshot {
+    snapshot_at(
+        secs,
+        true,
+        &[("pOutput", 50.0), ("cBattery", charge), ("vBattery", 50.0)],
+    )
+}
+
+#[test]
+fn open_episode_survives_a_restart_on_battery() {
+    // Arrange
+    let path = temp_state("health-open");
+    let config = BatteryHealthConfig {
+        state_path: Some(path.clone()),
+        ..BatteryHealthConfig::default()
+    };
+    let model = ModelProfile::ragtech_3200va().battery;
+    let mut first = BatteryHealthTracker::new(config.clone(), model.clone());
+    first.update(&mut on_battery(0, 100.0), true);
+    first.update(&mut on_battery(60, 90.0), true);
+    first.flush();
+
+    // Act
+    let mut resumed = BatteryHealthTracker::new(config, model);
+    resumed.update(&mut on_battery(120, 80.0), true);
+    resumed.update(&mut snapshot_at(121, true, &[]), false);
+
+    // Assert
+    assert_eq!(resumed.episodes().len(), 1);
+    let episode = &resumed.episodes()[0];
+    assert_eq!(episode.started_at, on_battery(0, 100.0).ts);
+    assert_eq!(episode.duration_sec, 120.0);
+    assert_eq!(episode.depth_of_discharge_pct, 20.0);
+
+    let _ = fs::remove_dir_all(path.parent().expect("temp dir"));
+}
+
+#[test]
+fn open_episode_ends_at_its_last_sample_after_a_long_gap() {
+    // Arrange
+    let path = temp_state("health-gap");
+    let config = BatteryHealthConfig {
+        state_path: Some(path.clone()),
+        ..BatteryHealthConfig::default()
+    };
+    let model = ModelProfile::ragtech_3200va().battery;
+    let mut first = BatteryHealthTracker::new(config.clone(), model.clone());
+    first.update(&mut on_battery(0, 100.0), true);
+    first.update(&mut on_battery(60, 90.0), true);
+    first.flush();
+
+    // Act
+    let mut after_gap = BatteryHealthTracker::new(config, model);
+    after_gap.update(&mut on_battery(3_600, 70.0), true);
+
+    // Assert
+    assert_eq!(after_gap.episodes().len(), 1, "the new outage stays open");
+    let closed = &after_gap.episodes()[0];
+    assert_eq!(closed.ended_at, on_battery(60, 90.0).ts);
+    assert_eq!(closed.depth_of_discharge_pct, 10.0);
+
+    let _ = fs::remove_dir_all(path.parent().expect("temp dir"));
+}
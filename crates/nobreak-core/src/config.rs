@@ -1,5 +1,15 @@
 use std::time::Duration;
 
+use crate::alerts::AlertRule;
+use crate::battery_health::BatteryHealthConfig;
+use crate::energy::EnergyConfig;
+use crate::events::EventThresholds;
+use crate::filters::FilterConfig;
+use crate::plausibility::PlausibilityConfig;
+use crate::pq_stats::PqStatsConfig;
+use crate::prodist::ProdistConfig;
+use crate::profile::ModelProfile;
+
 #[derive(Debug, Clone)]
 pub struct MonitorConfig {
     pub sample_interval: Duration,
@@ -10,6 +20,21 @@ pub struct MonitorConfig {
     pub poll_timeout: Duration,
     pub error_threshold: u32,
     pub auto_tune: bool,
+    /// Pin the interval to `sample_interval_min` on battery or during power
+    /// events, and relax it to `sample_interval_max` once stable on mains.
+    pub power_aware_sampling: bool,
+    pub stable_after: Duration,
+    pub hold_last_good: bool,
+    pub quality_windows: Vec<Duration>,
+    pub plausibility: PlausibilityConfig,
+    pub filters: FilterConfig,
+    pub events: EventThresholds,
+    pub alerts: Vec<AlertRule>,
+    pub profile: ModelProfile,
+    pub battery_health: BatteryHealthConfig,
+    pub energy: EnergyConfig,
+    pub prodist: ProdistConfig,
+    pub pq_stats: PqStatsConfig,
 }
 
 impl Default for MonitorConfig {
@@ -23,6 +48,23 @@ impl Default for MonitorConfig {
             poll_timeout: Duration::from_millis(700),
             error_threshold: 3,
             auto_tune: true,
+            power_aware_sampling: true,
+            stable_after: Duration::from_secs(3600),
+            hold_last_good: false,
+            quality_windows: vec![
+                Duration::from_secs(60),
+                Duration::from_secs(300),
+                Duration::from_secs(3600),
+            ],
+            plausibility: PlausibilityConfig::default(),
+            filters: FilterConfig::default(),
+            events: EventThresholds::default(),
+            alerts: Vec::new(),
+            profile: ModelProfile::default(),
+            battery_health: BatteryHealthConfig::default(),
+            energy: EnergyConfig::default(),
+            prodist: ProdistConfig::default(),
+            pq_stats: PqStatsConfig::default(),
         }
     }
 }
@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use chrono::{DateTime, FixedOffset, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::persist::{load_json, save_json};
+use crate::snapshot::Snapshot;
+
+/// Hourly buckets kept in the state file.
+const HOURLY_RETENTION: usize = 24 * 7;
+/// Daily buckets kept in the state file.
+const DAILY_RETENTION: usize = 400;
+/// Minimum spacing between state file writes.
+const SAVE_EVERY_SEC: i64 = 60;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnergyConfig {
+    pub state_path: Option<PathBuf>,
+    /// Intervals longer than this (device away, monitor stopped) are not
+    /// integrated, since the load during them is unknown.
+    pub max_gap_sec: f64,
+    /// Offset used for hour/day/month buckets and tariff bands.
+    pub utc_offset_minutes: i32,
+    pub tariff: Option<Tariff>,
+}
+
+impl Default for EnergyConfig {
+    fn default() -> Self {
+        Self {
+            state_path: None,
+            max_gap_sec: 10.0,
+            utc_offset_minutes: 0,
+            tariff: None,
+        }
+    }
+}
+
+/// Price per kWh by local hour. Hours not covered by a band use
+/// `default_price_per_kwh`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tariff {
+    pub currency: String,
+    pub default_price_per_kwh: f64,
+    #[serde(default)]
+    pub bands: Vec<TariffBand>,
+}
+
+/// `[start_hour, end_hour)` in local time; wraps past midnight when
+/// `end_hour <= start_hour`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TariffBand {
+    pub name: String,
+    pub start_hour: u32,
+    pub end_hour: u32,
+    pub price_per_kwh: f64,
+}
+
+impl Tariff {
+    pub fn price_at(&self, hour: u32) -> f64 {
+        self.bands
+            .iter()
+            .find(|band| {
+                if band.start_hour < band.end_hour {
+                    (band.start_hour..band.end_hour).contains(&hour)
+                } else {
+                    hour >= band.start_hour || hour < band.end_hour
+                }
+            })
+            .map(|band| band.price_per_kwh)
+            .unwrap_or(self.default_price_per_kwh)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct EnergyBucket {
+    pub wh: f64,
+    pub cost: f64,
+}
+
+impl EnergyBucket {
+    fn add(&mut self, wh: f64, cost: f64) {
+        self.wh += wh;
+        self.cost += cost;
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EnergyLedger {
+    pub total: EnergyBucket,
+    pub hourly: BTreeMap<String, EnergyBucket>,
+    pub daily: BTreeMap<String, EnergyBucket>,
+    pub monthly: BTreeMap<String, EnergyBucket>,
+}
+
+/// Integrates delivered energy from `pOutput` x the profile's rated watts over
+/// the real time between samples (trapezoidal rule).
+#[derive(Debug, Clone)]
+pub struct EnergyMeter {
+    config: EnergyConfig,
+    rated_w: f64,
+    ledger: EnergyLedger,
+    last: Option<(DateTime<Utc>, f64)>,
+    last_saved: Option<DateTime<Utc>>,
+}
+
+impl EnergyMeter {
+    pub fn new(config: EnergyConfig, rated_w: f64) -> Self {
+        let ledger = config
+            .state_path
+            .as_deref()
+            .filter(|path| path.exists())
+            .and_then(|path| match load_json::<EnergyLedger>(path) {
+                Ok(ledger) => Some(ledger),
+                Err(err) => {
+                    warn!(path=%path.display(), %err, "ignoring unreadable energy state");
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        Self {
+            config,
+            rated_w,
+            ledger,
+            last: None,
+            last_saved: None,
+        }
+    }
+
+    pub fn set_rating(&mut self, rated_w: f64) {
+        self.rated_w = rated_w;
+    }
+
+    pub fn set_tariff(&mut self, tariff: Option<Tariff>) {
+        self.config.tariff = tariff;
+    }
+
+    pub fn ledger(&self) -> &EnergyLedger {
+        &self.ledger
+    }
+
+    pub fn update(&mut self, snapshot: &mut Snapshot) {
+        let Some(load_pct) = snapshot.metric("pOutput") else {
+            return;
+        };
+        let watts = load_pct.max(0.0) / 100.0 * self.rated_w;
+        let now = snapshot.ts;
+
+        if let Some((prev_ts, prev_watts)) = self.last {
+            let secs = (now - prev_ts).num_milliseconds() as f64 / 1000.0;
+            if secs > 0.0 && secs <= self.config.max_gap_sec {
+                let wh = (prev_watts + watts) / 2.0 * secs / 3600.0;
+                self.accumulate(now, wh);
+            }
+        }
+        self.last = Some((now, watts));
+
+        self.publish(snapshot, watts);
+        self.maybe_save(now);
+    }
+
+    /// Writes the ledger now, regardless of the save interval.
+    pub fn flush(&mut self) {
+        if let Some(path) = &self.config.state_path {
+            if let Err(err) = save_json(path, &self.ledger) {
+                warn!(path=%path.display(), %err, "failed to persist energy state");
+            }
+        }
+    }
+
+    fn local(&self, ts: DateTime<Utc>) -> DateTime<FixedOffset> {
+        let offset = FixedOffset::east_opt(self.config.utc_offset_minutes * 60)
+            .unwrap_or_else(|| FixedOffset::east_opt(0).expect("zero offset"));
+        ts.with_timezone(&offset)
+    }
+
+    fn accumulate(&mut self, ts: DateTime<Utc>, wh: f64) {
+        let local = self.local(ts);
+        let cost = self
+            .config
+            .tariff
+            .as_ref()
+            .map(|t| t.price_at(local.hour()) * wh / 1000.0)
+            .unwrap_or(0.0);
+
+        self.ledger.total.add(wh, cost);
+        let keys = bucket_keys(local);
+        self.ledger.hourly.entry(keys.0).or_default().add(wh, cost);
+        self.ledger.daily.entry(keys.1).or_default().add(wh, cost);
+        self.ledger.monthly.entry(keys.2).or_default().add(wh, cost);
+
+        trim_oldest(&mut self.ledger.hourly, HOURLY_RETENTION);
+        trim_oldest(&mut self.ledger.daily, DAILY_RETENTION);
+    }
+
+    fn publish(&self, snapshot: &mut Snapshot, watts: f64) {
+        let (hour, day, month) = bucket_keys(self.local(snapshot.ts));
+        let get = |map: &BTreeMap<String, EnergyBucket>, key: &str| {
+            map.get(key).copied().unwrap_or_default()
+        };
+        let buckets = [
+            ("Total", self.ledger.total),
+            ("Hour", get(&self.ledger.hourly, &hour)),
+            ("Day", get(&self.ledger.daily, &day)),
+            ("Month", get(&self.ledger.monthly, &month)),
+        ];
+
+        let round = |v: f64| serde_json::Value::from((v * 1000.0).round() / 1000.0);
+        let vars = &mut snapshot.vars;
+        vars.insert("pOutputWatts".to_string(), round(watts));
+        for (label, bucket) in buckets {
+            vars.insert(format!("energy{label}Wh"), round(bucket.wh));
+            if self.config.tariff.is_some() {
+                vars.insert(format!("energy{label}Cost"), round(bucket.cost));
+            }
+        }
+        if let Some(tariff) = &self.config.tariff {
+            vars.insert(
+                "energyCurrency".to_string(),
+                serde_json::Value::String(tariff.currency.clone()),
+            );
+        }
+    }
+
+    fn maybe_save(&mut self, now: DateTime<Utc>) {
+        if self
+            .last_saved
+            .is_some_and(|saved| (now - saved).num_seconds() < SAVE_EVERY_SEC)
+        {
+            return;
+        }
+        self.last_saved = Some(now);
+        self.flush();
+    }
+}
+
+fn bucket_keys(local: DateTime<FixedOffset>) -> (String, String, String) {
+    (
+        local.format("%Y-%m-%dT%H").to_string(),
+        local.format("%Y-%m-%d").to_string(),
+        local.format("%Y-%m").to_string(),
+    )
+}
+
+fn trim_oldest(map: &mut BTreeMap<String, EnergyBucket>, keep: usize) {
+    while map.len() > keep {
+        map.pop_first();
+    }
+}
@@ -0,0 +1,91 @@
+use std::env;
+use std::fs;
+use std::time::SystemTime;
+
+use crate::energy::{EnergyConfig, EnergyMeter, Tariff, TariffBand};
+use crate::test_driver::snapshot_at;
+
+fn run(meter: &mut EnergyMeter, from: i64, to: i64, load: f64) -> crate::snapshot::Snapshot {
+    let mut last = snapshot_at(from, true, &[]);
+    for secs in from..=to {
+        last = snapshot_at(secs, true, &[("pOutput", load)]);
+        meter.update(&mut last);
+    }
+    last
+}
+
+#[test]
+fn integrates_load_times_rating_over_real_intervals() {
+    // Arrange
+    let mut meter = EnergyMeter::new(EnergyConfig::default(), 2000.0);
+
+    // Act: one hour at 50% of 2000 W, then a 60 s gap that must be skipped.
+    run(&mut meter, 0, 3600, 50.0);
+    let last = run(&mut meter, 3660, 3660, 50.0);
+
+    // Assert
+    assert_eq!(last.vars["energyTotalWh"], 1000.0);
+    assert_eq!(last.vars["pOutputWatts"], 1000.0);
+    assert!(!last.vars.contains_key("energyTotalCost"));
+}
+
+#[test]
+fn tariff_bands_price_energy_by_local_hour() {
+    // Arrange
+    let tariff = Tariff {
+        currency: "BRL".to_string(),
+        default_price_per_kwh: 0.5,
+        bands: vec![TariffBand {
+            name: "peak".to_string(),
+            start_hour: 18,
+            end_hour: 21,
+            price_per_kwh: 2.0,
+        }],
+    };
+    let config = EnergyConfig {
+        utc_offset_minutes: -180,
+        tariff: Some(tariff.clone()),
+        ..EnergyConfig::default()
+    };
+    let mut meter = EnergyMeter::new(config, 1000.0);
+
+    // Act
+    run(&mut meter, 0, 3600, 100.0);
+
+    // Assert
+    // snapshot_at(0) is 00:00 UTC, i.e. 21:00 local at UTC-3: off-peak.
+    assert!((meter.ledger().total.cost - 0.5).abs() < 1e-9);
+    assert_eq!(tariff.price_at(20), 2.0);
+    assert_eq!(tariff.price_at(23), 0.5);
+    assert_eq!(
+        meter.ledger().daily.keys().collect::<Vec<_>>(),
+        ["2026-02-15"]
+    );
+}
+
+#[test]
+fn ledger_survives_restart() {
+    // Arrange
+    let uniq = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("unix epoch")
+        .as_nanos();
+    let dir = env::temp_dir().join(format!("nobreak-tests-energy-{uniq}"));
+    let config = EnergyConfig {
+        state_path: Some(dir.join("energy.json")),
+        ..EnergyConfig::default()
+    };
+    let mut first = EnergyMeter::new(config.clone(), 3600.0);
+    run(&mut first, 0, 10, 100.0);
+    first.flush();
+
+    // Act
+    let mut second = EnergyMeter::new(config, 3600.0);
+    let last = run(&mut second, 100, 110, 100.0);
+
+    // Assert
+    assert_eq!(last.vars["energyTotalWh"], 20.0);
+    assert_eq!(last.vars["energyMonthWh"], 20.0);
+
+    let _ = fs::remove_dir_all(dir);
+}
@@ -0,0 +1,296 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::prodist::{detect_nominal, INTERRUPTION_BELOW_V};
+use crate::snapshot::Snapshot;
+
+/// A `vInput` reading within this fraction of a nominal voltage identifies
+/// the grid; sags and swells are too far off to tell 127 V from 220 V.
+const NOMINAL_BAND: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PowerEventKind {
+    MainsLost,
+    MainsRestored,
+    Brownout,
+    Overvoltage,
+    BatteryLow,
+    Overload,
+    DeviceLost,
+    DeviceFound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventPhase {
+    Start,
+    End,
+}
+
+/// One edge of a power condition. `End` edges repeat the `started_at` of the
+/// matching `Start` so each record is self-contained.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PowerEvent {
+    pub kind: PowerEventKind,
+    pub phase: EventPhase,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub duration_ms: Option<u64>,
+    /// Triggering value on `Start`, worst value seen on `End`.
+    pub value: Option<f64>,
+}
+
+/// Thresholds for the event detector. Brownout and overvoltage default to a
+/// percentage of the grid's nominal voltage, which comes from `nominal_v` or
+/// is detected from `vInput`; until it is known only mains loss is reported.
+/// `hysteresis` is applied on the way back to normal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EventThresholds {
+    /// Filled from `[prodist] nominal_v` rather than read from `[events]`.
+    #[serde(skip)]
+    pub nominal_v: Option<f64>,
+    pub mains_lost_below_v: f64,
+    pub brownout_below_pct: f64,
+    pub overvoltage_above_pct: f64,
+    /// Absolute limits, used instead of the percentages when set.
+    pub brownout_below_v: Option<f64>,
+    pub overvoltage_above_v: Option<f64>,
+    pub voltage_hysteresis_v: f64,
+    pub battery_low_below_pct: f64,
+    pub overload_above_pct: f64,
+    pub percent_hysteresis: f64,
+}
+
+impl Default for EventThresholds {
+    fn default() -> Self {
+        Self {
+            nominal_v: None,
+            mains_lost_below_v: INTERRUPTION_BELOW_V,
+            brownout_below_pct: 87.0,
+            overvoltage_above_pct: 110.0,
+            brownout_below_v: None,
+            overvoltage_above_v: None,
+            voltage_hysteresis_v: 2.0,
+            battery_low_below_pct: 30.0,
+            overload_above_pct: 100.0,
+            percent_hysteresis: 2.0,
+        }
+    }
+}
+
+impl EventThresholds {
+    /// Brownout and overvoltage limits for a grid of `nominal_v`, or `None`
+    /// for a limit that needs the nominal voltage while it is unknown.
+    pub fn voltage_limits(&self, nominal_v: Option<f64>) -> (Option<f64>, Option<f64>) {
+        let of_nominal = |pct: f64| nominal_v.map(|nominal| nominal * pct / 100.0);
+        (
+            self.brownout_below_v
+                .or_else(|| of_nominal(self.brownout_below_pct)),
+            self.overvoltage_above_v
+                .or_else(|| of_nominal(self.overvoltage_above_pct)),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Direction {
+    Below,
+    Above,
+}
+
+#[derive(Debug, Clone)]
+struct Condition {
+    start_kind: PowerEventKind,
+    end_kind: PowerEventKind,
+    direction: Direction,
+    active_since: Option<DateTime<Utc>>,
+    worst: Option<f64>,
+}
+
+impl Condition {
+    fn new(start_kind: PowerEventKind, end_kind: PowerEventKind, direction: Direction) -> Self {
+        Self {
+            start_kind,
+            end_kind,
+            direction,
+            active_since: None,
+            worst: None,
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.active_since.is_some()
+    }
+
+    fn update(
+        &mut self,
+        active: bool,
+        value: Option<f64>,
+        ts: DateTime<Utc>,
+        out: &mut Vec<PowerEvent>,
+    ) {
+        match (self.active_since, active) {
+            (None, true) => {
+                self.active_since = Some(ts);
+                self.worst = value;
+                out.push(PowerEvent {
+                    kind: self.start_kind,
+                    phase: EventPhase::Start,
+                    started_at: ts,
+                    ended_at: None,
+                    duration_ms: None,
+                    value,
+                });
+            }
+            (Some(_), true) => {
+                self.worst = match (self.worst, value) {
+                    (Some(w), Some(v)) if self.direction == Direction::Below => Some(w.min(v)),
+                    (Some(w), Some(v)) => Some(w.max(v)),
+                    (w, v) => w.or(v),
+                };
+            }
+            (Some(since), false) => {
+                out.push(PowerEvent {
+                    kind: self.end_kind,
+                    phase: EventPhase::End,
+                    started_at: since,
+                    ended_at: Some(ts),
+                    duration_ms: Some((ts - since).num_milliseconds().max(0) as u64),
+                    value: self.worst,
+                });
+                self.active_since = None;
+                self.worst = None;
+            }
+            (None, false) => {}
+        }
+    }
+
+    /// Threshold test with hysteresis: once active, the value has to clear
+    /// the threshold by `hysteresis` before the condition ends.
+    fn evaluate(&self, value: f64, threshold: f64, hysteresis: f64) -> bool {
+        match (self.direction, self.is_active()) {
+            (Direction::Below, false) => value < threshold,
+            (Direction::Below, true) => value < threshold + hysteresis,
+            (Direction::Above, false) => value > threshold,
+            (Direction::Above, true) => value > threshold - hysteresis,
+        }
+    }
+}
+
+/// Turns the snapshot stream into start/end edges of power conditions.
+///
+/// Electrical conditions are only evaluated on connected snapshots with fresh
+/// vars; while the device is away they stay in whatever state they were in.
+#[derive(Debug, Clone)]
+pub struct EventDetector {
+    thresholds: EventThresholds,
+    detected_nominal_v: Option<f64>,
+    device: Condition,
+    mains: Condition,
+    brownout: Condition,
+    overvoltage: Condition,
+    battery_low: Condition,
+    overload: Condition,
+    seen_first: bool,
+}
+
+impl EventDetector {
+    pub fn new(thresholds: EventThresholds) -> Self {
+        use PowerEventKind::*;
+        Self {
+            thresholds,
+            detected_nominal_v: None,
+            device: Condition::new(DeviceLost, DeviceFound, Direction::Below),
+            mains: Condition::new(MainsLost, MainsRestored, Direction::Below),
+            brownout: Condition::new(Brownout, Brownout, Direction::Below),
+            overvoltage: Condition::new(Overvoltage, Overvoltage, Direction::Above),
+            battery_low: Condition::new(BatteryLow, BatteryLow, Direction::Below),
+            overload: Condition::new(Overload, Overload, Direction::Above),
+            seen_first: false,
+        }
+    }
+
+    pub fn set_thresholds(&mut self, thresholds: EventThresholds) {
+        self.thresholds = thresholds;
+    }
+
+    pub fn mains_lost(&self) -> bool {
+        self.mains.is_active()
+    }
+
+    pub fn active(&self) -> Vec<PowerEventKind> {
+        [
+            &self.device,
+            &self.mains,
+            &self.brownout,
+            &self.overvoltage,
+            &self.battery_low,
+            &self.overload,
+        ]
+        .into_iter()
+        .filter(|c| c.is_active())
+        .map(|c| c.start_kind)
+        .collect()
+    }
+
+    pub fn observe(&mut self, snapshot: &Snapshot) -> Vec<PowerEvent> {
+        let ts = snapshot.ts;
+        let mut out = Vec::new();
+        let t = self.thresholds.clone();
+
+        // A monitor that starts without a device has not "lost" it.
+        let connected = snapshot.device.connected;
+        if self.seen_first || connected {
+            self.device.update(!connected, None, ts, &mut out);
+        }
+        self.seen_first = true;
+
+        if !connected || snapshot.status.code == "DEGRADED" {
+            return out;
+        }
+
+        let fresh = |key: &str| snapshot.raw_metric(key);
+
+        if let Some(v) = fresh("vInput") {
+            let lost = self
+                .mains
+                .evaluate(v, t.mains_lost_below_v, t.voltage_hysteresis_v);
+            self.mains.update(lost, Some(v), ts, &mut out);
+
+            if self.detected_nominal_v.is_none() {
+                self.detected_nominal_v =
+                    detect_nominal(v).filter(|n| (v - n).abs() <= n * NOMINAL_BAND);
+            }
+            let (brownout_below, overvoltage_above) =
+                t.voltage_limits(t.nominal_v.or(self.detected_nominal_v));
+
+            if let Some(below) = brownout_below {
+                let brownout = !lost && self.brownout.evaluate(v, below, t.voltage_hysteresis_v);
+                self.brownout.update(brownout, Some(v), ts, &mut out);
+            }
+
+            if let Some(above) = overvoltage_above {
+                let over = self.overvoltage.evaluate(v, above, t.voltage_hysteresis_v);
+                self.overvoltage.update(over, Some(v), ts, &mut out);
+            }
+        }
+
+        if let Some(c) = fresh("cBattery") {
+            let low = self
+                .battery_low
+                .evaluate(c, t.battery_low_below_pct, t.percent_hysteresis);
+            self.battery_low.update(low, Some(c), ts, &mut out);
+        }
+
+        if let Some(p) = fresh("pOutput") {
+            let over = self
+                .overload
+                .evaluate(p, t.overload_above_pct, t.percent_hysteresis);
+            self.overload.update(over, Some(p), ts, &mut out);
+        }
+
+        out
+    }
+}
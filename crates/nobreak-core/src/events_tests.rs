@@ -0,0 +1,147 @@
+use crate::events::{EventDetector, EventPhase, EventThresholds, PowerEventKind};
+use crate::test_driver::snapshot_at;
+
+fn kinds(events: &[crate::events::PowerEvent]) -> Vec<(PowerEventKind, EventPhase)> {
+    events.iter().map(|e| (e.kind, e.phase)).collect()
+}
+
+#[test]
+fn mains_loss_and_restore_carry_outage_duration() {
+    // Arrange
+    let mut detector = EventDetector::new(EventThresholds::default());
+    detector.observe(&snapshot_at(0, true, &[("vInput", 127.0)]));
+
+    // Act
+    let lost = detector.observe(&snapshot_at(10, true, &[("vInput", 0.0)]));
+    let during = detector.observe(&snapshot_at(20, true, &[("vInput", 0.0)]));
+    let restored = detector.observe(&snapshot_at(95, true, &[("vInput", 126.0)]));
+
+    // Assert
+    assert_eq!(
+        kinds(&lost),
+        [(PowerEventKind::MainsLost, EventPhase::Start)]
+    );
+    assert!(during.is_empty());
+    assert_eq!(
+        kinds(&restored),
+        [(PowerEventKind::MainsRestored, EventPhase::End)]
+    );
+    assert_eq!(restored[0].duration_ms, Some(85_000));
+    assert_eq!(restored[0].started_at, lost[0].started_at);
+}
+
+#[test]
+fn smoothing_filters_do_not_delay_mains_loss() {
+    // Arrange
+    let mut detector = EventDetector::new(EventThresholds::default());
+    detector.observe(&snapshot_at(0, true, &[("vInput", 127.0)]));
+
+    // Act
+    let lost = detector.observe(&snapshot_at(
+        1,
+        true,
+        &[("vInput", 127.0), ("vInputRaw", 0.0)],
+    ));
+
+    // Assert
+    assert_eq!(
+        kinds(&lost),
+        [(PowerEventKind::MainsLost, EventPhase::Start)]
+    );
+}
+
+#[test]
+fn brownout_needs_hysteresis_to_clear() {
+    // Arrange
+    let mut detector = EventDetector::new(EventThresholds::default());
+    detector.observe(&snapshot_at(0, true, &[("vInput", 127.0)]));
+
+    // Act
+    let start = detector.observe(&snapshot_at(1, true, &[("vInput", 105.0)]));
+    let wobble = detector.observe(&snapshot_at(2, true, &[("vInput", 111.0)]));
+    let end = detector.observe(&snapshot_at(3, true, &[("vInput", 113.0)]));
+
+    // Assert
+    assert_eq!(
+        kinds(&start),
+        [(PowerEventKind::Brownout, EventPhase::Start)]
+    );
+    assert!(wobble.is_empty(), "111 V is inside the 2 V hysteresis band");
+    assert_eq!(kinds(&end), [(PowerEventKind::Brownout, EventPhase::End)]);
+    assert_eq!(end[0].value, Some(105.0));
+}
+
+#[test]
+fn device_loss_pairs_with_device_found() {
+    // Arrange
+    let mut detector = EventDetector::new(EventThresholds::default());
+    let startup = detector.observe(&snapshot_at(0, false, &[]));
+    detector.observe(&snapshot_at(1, true, &[("cBattery", 100.0)]));
+
+    // Act
+    let lost = detector.observe(&snapshot_at(2, false, &[]));
+    let found = detector.observe(&snapshot_at(
+        7,
+        true,
+        &[("cBattery", 25.0), ("pOutput", 120.0)],
+    ));
+
+    // Assert
+    assert!(
+        startup.is_empty(),
+        "starting without a device is not a loss"
+    );
+    assert_eq!(
+        kinds(&lost),
+        [(PowerEventKind::DeviceLost, EventPhase::Start)]
+    );
+    assert_eq!(
+        kinds(&found),
+        [
+            (PowerEventKind::DeviceFound, EventPhase::End),
+            (PowerEventKind::BatteryLow, EventPhase::Start),
+            (PowerEventKind::Overload, EventPhase::Start),
+        ]
+    );
+    assert_eq!(found[0].duration_ms, Some(5_000));
+}
+
+#[test]
+fn voltage_limits_follow_a_220_v_grid() {
+    // Arrange
+    let mut detector = EventDetector::new(EventThresholds::default());
+
+    // Act
+    let unknown = detector.observe(&snapshot_at(0, true, &[("vInput", 150.0)]));
+    let normal = detector.observe(&snapshot_at(1, true, &[("vInput", 221.0)]));
+    let sag = detector.observe(&snapshot_at(2, true, &[("vInput", 185.0)]));
+    let swell = detector.observe(&snapshot_at(3, true, &[("vInput", 245.0)]));
+
+    // Assert
+    assert!(unknown.is_empty(), "150 V does not identify the grid");
+    assert!(normal.is_empty(), "221 V is normal on a 220 V grid");
+    assert_eq!(kinds(&sag), [(PowerEventKind::Brownout, EventPhase::Start)]);
+    assert_eq!(
+        kinds(&swell),
+        [
+            (PowerEventKind::Brownout, EventPhase::End),
+            (PowerEventKind::Overvoltage, EventPhase::Start),
+        ]
+    );
+}
+
+#[test]
+fn configured_nominal_sets_the_limits_from_the_first_reading() {
+    // Arrange
+    let thresholds = EventThresholds {
+        nominal_v: Some(220.0),
+        ..EventThresholds::default()
+    };
+    let mut detector = EventDetector::new(thresholds);
+
+    // Act
+    let sag = detector.observe(&snapshot_at(0, true, &[("vInput", 150.0)]));
+
+    // Assert
+    assert_eq!(kinds(&sag), [(PowerEventKind::Brownout, EventPhase::Start)]);
+}
@@ -0,0 +1,137 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+/// Smoothing or outlier-rejection filter for one var.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FilterSpec {
+    /// Median of the last `window` samples.
+    Median { window: usize },
+    /// Exponential moving average; `alpha` is the weight of the new sample.
+    Ema { alpha: f64 },
+    /// Replaces a sample with the window median when it lies more than
+    /// `threshold` scaled MADs away from it; passes it through otherwise.
+    Hampel { window: usize, threshold: f64 },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FilterConfig {
+    pub vars: BTreeMap<String, FilterSpec>,
+}
+
+impl FilterConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, spec) in &self.vars {
+            match spec {
+                FilterSpec::Median { window: 0 } => {
+                    return Err(format!("{name}: median window must be positive"));
+                }
+                FilterSpec::Ema { alpha } if !(*alpha > 0.0 && *alpha <= 1.0) => {
+                    return Err(format!("{name}: ema alpha must be in (0, 1]"));
+                }
+                FilterSpec::Hampel { window, .. } if *window < 3 => {
+                    return Err(format!("{name}: hampel window must be at least 3"));
+                }
+                FilterSpec::Hampel { threshold, .. } if *threshold <= 0.0 => {
+                    return Err(format!("{name}: hampel threshold must be positive"));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Scales the median absolute deviation to a standard deviation for
+/// normally distributed data.
+const MAD_SCALE: f64 = 1.4826;
+
+#[derive(Debug, Clone, Default)]
+struct FilterState {
+    window: VecDeque<f64>,
+    ema: Option<f64>,
+}
+
+/// Applies the configured filters to a read's vars. The filtered value
+/// replaces the var, and the unfiltered one is kept as `<var>Raw`.
+#[derive(Debug, Clone)]
+pub struct VarFilters {
+    config: FilterConfig,
+    state: BTreeMap<String, FilterState>,
+}
+
+impl VarFilters {
+    pub fn new(config: FilterConfig) -> Self {
+        Self {
+            config,
+            state: BTreeMap::new(),
+        }
+    }
+
+    /// Swaps the configuration, dropping history only for vars whose
+    /// filter changed.
+    pub fn set_config(&mut self, config: FilterConfig) {
+        self.state
+            .retain(|name, _| config.vars.get(name) == self.config.vars.get(name));
+        self.config = config;
+    }
+
+    pub fn apply(&mut self, vars: &mut BTreeMap<String, serde_json::Value>) {
+        for (name, spec) in &self.config.vars {
+            let Some(raw) = vars.get(name).and_then(|v| v.as_f64()) else {
+                continue;
+            };
+            let state = self.state.entry(name.clone()).or_default();
+            let filtered = filter(spec, state, raw);
+            vars.insert(format!("{name}Raw"), serde_json::Value::from(raw));
+            vars.insert(name.clone(), serde_json::Value::from(filtered));
+        }
+    }
+}
+
+fn filter(spec: &FilterSpec, state: &mut FilterState, raw: f64) -> f64 {
+    match spec {
+        FilterSpec::Median { window } => {
+            push_window(&mut state.window, *window, raw);
+            median(state.window.iter().copied())
+        }
+        FilterSpec::Ema { alpha } => {
+            let next = state.ema.map_or(raw, |prev| prev + alpha * (raw - prev));
+            state.ema = Some(next);
+            next
+        }
+        FilterSpec::Hampel { window, threshold } => {
+            push_window(&mut state.window, *window, raw);
+            let center = median(state.window.iter().copied());
+            let mad = median(state.window.iter().map(|v| (v - center).abs())) * MAD_SCALE;
+            if (raw - center).abs() > threshold * mad {
+                center
+            } else {
+                raw
+            }
+        }
+    }
+}
+
+fn push_window(window: &mut VecDeque<f64>, size: usize, value: f64) {
+    window.push_back(value);
+    while window.len() > size.max(1) {
+        window.pop_front();
+    }
+}
+
+fn median(values: impl Iterator<Item = f64>) -> f64 {
+    let mut sorted = values.collect::<Vec<_>>();
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    sorted.sort_by(f64::total_cmp);
+    let mid = sorted.len() / 2;
+    if sorted.len() % 2 == 0 {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+
+use crate::config::MonitorConfig;
+use crate::filters::{FilterConfig, FilterSpec, VarFilters};
+use crate::monitor::Monitor;
+use crate::test_driver::{ok_read, ScriptedDriver};
+
+fn run(spec: FilterSpec, samples: &[f64]) -> Vec<f64> {
+    let mut filters = VarFilters::new(FilterConfig {
+        vars: BTreeMap::from([("vInput".to_string(), spec)]),
+    });
+    samples
+        .iter()
+        .map(|v| {
+            let mut vars = BTreeMap::from([("vInput".to_string(), serde_json::Value::from(*v))]);
+            filters.apply(&mut vars);
+            vars["vInput"].as_f64().expect("filtered value")
+        })
+        .collect()
+}
+
+#[test]
+fn median_suppresses_single_sample_spikes() {
+    // Arrange
+    let samples = [127.0, 128.0, 40.0, 127.0, 126.0];
+
+    // Act
+    let out = run(FilterSpec::Median { window: 3 }, &samples);
+
+    // Assert
+    assert_eq!(out, [127.0, 127.5, 127.0, 127.0, 126.0]);
+}
+
+#[test]
+fn ema_weights_new_samples_by_alpha() {
+    // Arrange
+    let samples = [100.0, 200.0, 200.0];
+
+    // Act
+    let out = run(FilterSpec::Ema { alpha: 0.5 }, &samples);
+
+    // Assert
+    assert_eq!(out, [100.0, 150.0, 175.0]);
+}
+
+#[test]
+fn hampel_replaces_outliers_and_passes_normal_noise() {
+    // Arrange
+    let samples = [127.0, 128.0, 126.0, 127.0, 200.0, 128.0];
+
+    // Act
+    let out = run(
+        FilterSpec::Hampel {
+            window: 5,
+            threshold: 3.0,
+        },
+        &samples,
+    );
+
+    // Assert
+    assert_eq!(out, [127.0, 128.0, 126.0, 127.0, 127.0, 128.0]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn monitor_keeps_raw_value_next_to_filtered_one() {
+    // Arrange
+    let config = MonitorConfig {
+        filters: FilterConfig {
+            vars: BTreeMap::from([("vInput".to_string(), FilterSpec::Ema { alpha: 0.5 })]),
+        },
+        ..MonitorConfig::default()
+    };
+    let reads = vec![ok_read(&[("vInput", 120.0)]), ok_read(&[("vInput", 130.0)])];
+    let mut monitor = Monitor::new(ScriptedDriver::new(reads), config, None);
+
+    // Act
+    monitor.tick().await;
+    let snapshot = monitor.tick().await;
+
+    // Assert
+    assert_eq!(snapshot.vars["vInput"], 125.0);
+    assert_eq!(snapshot.vars["vInputRaw"], 130.0);
+}
@@ -1,9 +1,73 @@
+pub mod alerts;
+pub mod battery_health;
 pub mod config;
 pub mod driver;
+pub mod energy;
+pub mod events;
+pub mod filters;
 pub mod monitor;
+pub mod persist;
+pub mod plausibility;
+pub mod pq_stats;
+pub mod prodist;
+pub mod profile;
+pub mod runtime;
+pub mod scheduler;
+pub mod service;
 pub mod snapshot;
+pub mod stats;
 
+#[cfg(test)]
+mod alerts_tests;
+#[cfg(test)]
+mod battery_health_tests;
+#[cfg(test)]
+mod energy_tests;
+#[cfg(test)]
+mod events_tests;
+#[cfg(test)]
+mod filters_tests;
+#[cfg(test)]
+mod monitor_tests;
+#[cfg(test)]
+mod plausibility_tests;
+#[cfg(test)]
+mod pq_stats_tests;
+#[cfg(test)]
+mod prodist_tests;
+#[cfg(test)]
+mod runtime_tests;
+#[cfg(test)]
+mod scheduler_tests;
+#[cfg(test)]
+mod service_tests;
+#[cfg(test)]
+mod stats_tests;
+#[cfg(test)]
+mod test_driver;
+
+pub use alerts::{
+    AlertCondition, AlertEngine, AlertRecord, AlertRule, AlertRuleSet, AlertState, Severity,
+};
+pub use battery_health::{BatteryHealthConfig, BatteryHealthTracker, DischargeEpisode};
 pub use config::MonitorConfig;
 pub use driver::{DeviceInfo, DriverError, ReadResult, UpsDriver, VendorShimDriver};
-pub use monitor::Monitor;
-pub use snapshot::{Freshness, MonitorStatus, Snapshot, SnapshotDevice};
+pub use energy::{EnergyBucket, EnergyConfig, EnergyLedger, EnergyMeter, Tariff, TariffBand};
+pub use events::{EventDetector, EventPhase, EventThresholds, PowerEvent, PowerEventKind};
+pub use filters::{FilterConfig, FilterSpec, VarFilters};
+pub use monitor::{ConnectionState, Monitor};
+pub use plausibility::{MetricLimits, PlausibilityChecker, PlausibilityConfig, Validity};
+pub use pq_stats::{
+    itic_region, BucketStats, Disturbance, DisturbanceKind, HourExcursion, IticCounts, IticRegion,
+    PowerQualityDay, PqStats, PqStatsConfig, PqStatsState,
+};
+pub use prodist::{
+    FrequencyStats, IntervalReading, ProdistConfig, ProdistMonitor, ProdistReport, ProdistState,
+    VoltageClass,
+};
+pub use profile::{BatteryModel, ModelProfile};
+pub use runtime::RuntimeEstimator;
+pub use scheduler::{IntervalReason, Tick, TickScheduler};
+pub use service::{MonitorService, SnapshotFeed};
+pub use snapshot::{Freshness, MonitorStatus, Snapshot, SnapshotDevice, VarMeta};
+pub use stats::{QualityWindow, RollingStats};
@@ -1,15 +1,31 @@
 use std::collections::BTreeMap;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use chrono::Utc;
-use tokio::time::timeout;
+use serde::{Deserialize, Serialize};
+use tokio::time::{timeout, Instant};
+use tracing::info;
 
+use crate::alerts::{AlertEngine, Severity};
+use crate::battery_health::BatteryHealthTracker;
 use crate::config::MonitorConfig;
 use crate::driver::{DeviceInfo, DriverError, UpsDriver};
-use crate::snapshot::{Freshness, MonitorStatus, Snapshot, SnapshotDevice, SnapshotQuality, Transport};
+use crate::energy::EnergyMeter;
+use crate::events::{EventDetector, PowerEventKind};
+use crate::filters::VarFilters;
+use crate::plausibility::PlausibilityChecker;
+use crate::pq_stats::PqStats;
+use crate::prodist::ProdistMonitor;
+use crate::runtime::RuntimeEstimator;
+use crate::scheduler::{IntervalReason, TickScheduler};
+use crate::stats::RollingStats;
+use crate::snapshot::{
+    Freshness, MonitorStatus, Snapshot, SnapshotDevice, SnapshotQuality, Transport, VarMeta,
+};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-enum ConnectionState {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ConnectionState {
     Disconnected,
     Connecting,
     Streaming,
@@ -17,6 +33,18 @@ enum ConnectionState {
     Reconnecting,
 }
 
+impl ConnectionState {
+    pub fn as_code(&self) -> &'static str {
+        match self {
+            Self::Disconnected => "DISCONNECTED",
+            Self::Connecting => "CONNECTING",
+            Self::Streaming => "STREAMING",
+            Self::Degraded => "DEGRADED",
+            Self::Reconnecting => "RECONNECTING",
+        }
+    }
+}
+
 pub struct Monitor<D: UpsDriver> {
     driver: D,
     config: MonitorConfig,
@@ -28,9 +56,26 @@ pub struct Monitor<D: UpsDriver> {
     reads_err: u64,
     reconnects: u64,
     effective_interval: Duration,
+    interval_reason: IntervalReason,
+    interval_changes: u64,
+    stable_since: Option<Instant>,
+    interval_pinned: bool,
     process_start: Instant,
     last_ok_instant: Option<Instant>,
     last_ok_ts: Option<chrono::DateTime<Utc>>,
+    connected_at: Option<Instant>,
+    last_good: BTreeMap<String, (serde_json::Value, Instant)>,
+    scheduler: TickScheduler,
+    stats: RollingStats,
+    plausibility: PlausibilityChecker,
+    filters: VarFilters,
+    events: EventDetector,
+    alerts: AlertEngine,
+    runtime: RuntimeEstimator,
+    health: BatteryHealthTracker,
+    energy: EnergyMeter,
+    prodist: ProdistMonitor,
+    pq_stats: PqStats,
 }
 
 impl<D: UpsDriver> Monitor<D> {
@@ -46,9 +91,29 @@ impl<D: UpsDriver> Monitor<D> {
             reads_err: 0,
             reconnects: 0,
             effective_interval: config.sample_interval,
+            interval_reason: IntervalReason::Configured,
+            interval_changes: 0,
+            stable_since: None,
+            interval_pinned: false,
             process_start: Instant::now(),
             last_ok_instant: None,
             last_ok_ts: None,
+            connected_at: None,
+            last_good: BTreeMap::new(),
+            scheduler: TickScheduler::new(config.sample_interval),
+            stats: RollingStats::new(&config.quality_windows),
+            plausibility: PlausibilityChecker::new(config.plausibility.clone()),
+            filters: VarFilters::new(config.filters.clone()),
+            events: EventDetector::new(config.events.clone()),
+            alerts: AlertEngine::new(config.alerts.clone()),
+            runtime: RuntimeEstimator::new(config.profile.battery.clone()),
+            health: BatteryHealthTracker::new(
+                config.battery_health.clone(),
+                config.profile.battery.clone(),
+            ),
+            energy: EnergyMeter::new(config.energy.clone(), config.profile.rated_w),
+            prodist: ProdistMonitor::new(config.prodist.clone()),
+            pq_stats: PqStats::new(config.pq_stats.clone()),
         }
     }
 
@@ -56,6 +121,70 @@ impl<D: UpsDriver> Monitor<D> {
         self.effective_interval
     }
 
+    pub fn connection_state(&self) -> ConnectionState {
+        self.state
+    }
+
+    /// Power conditions currently in progress.
+    pub fn active_events(&self) -> Vec<PowerEventKind> {
+        self.events.active()
+    }
+
+    /// Alert rules currently firing.
+    pub fn firing_alerts(&self) -> Vec<(String, Severity)> {
+        self.alerts.firing()
+    }
+
+    /// Applies a reloaded configuration between ticks, keeping the device
+    /// connection, counters and analyser history. Of `battery_health`,
+    /// `energy`, `prodist` and `pq_stats` only the tariff is applied; callers
+    /// keep the rest at their running values until a restart.
+    pub fn reconfigure(&mut self, config: MonitorConfig) {
+        if config.quality_windows != self.config.quality_windows {
+            self.stats = RollingStats::new(&config.quality_windows);
+        }
+        self.plausibility.set_config(config.plausibility.clone());
+        self.filters.set_config(config.filters.clone());
+        self.events.set_thresholds(config.events.clone());
+        self.alerts.set_rules(config.alerts.clone());
+        self.runtime.set_model(config.profile.battery.clone());
+        self.health.set_model(config.profile.battery.clone());
+        self.energy.set_rating(config.profile.rated_w);
+        self.energy.set_tariff(config.energy.tariff.clone());
+
+        let interval_changed = config.sample_interval != self.config.sample_interval;
+        self.config = config;
+        if interval_changed {
+            self.stable_since = None;
+            self.interval_pinned = false;
+            self.set_interval(self.config.sample_interval, IntervalReason::Configured);
+        }
+    }
+
+    /// Waits for the next slot on the sampling grid, then reads.
+    pub async fn next_snapshot(&mut self) -> Snapshot {
+        self.wait_for_slot().await;
+        self.tick().await
+    }
+
+    /// Waits for the next slot on the sampling grid without reading.
+    pub async fn wait_for_slot(&mut self) {
+        self.scheduler.set_period(self.effective_interval);
+        self.scheduler.wait().await;
+    }
+
+    /// Releases the device; the next tick reconnects if called again.
+    pub async fn close(&mut self) {
+        self.health.flush();
+        self.energy.flush();
+        self.prodist.flush();
+        self.pq_stats.flush();
+        let _ = self.driver.disconnect().await;
+        self.current = None;
+        self.connected_at = None;
+        self.state = ConnectionState::Disconnected;
+    }
+
     pub async fn discover(&mut self) -> Result<Vec<DeviceInfo>, DriverError> {
         self.driver.discover().await
     }
@@ -64,19 +193,41 @@ impl<D: UpsDriver> Monitor<D> {
         self.state = ConnectionState::Connecting;
         let device = self.driver.connect(self.target_id.as_deref()).await?;
         self.current = Some(device.clone());
+        self.connected_at = Some(Instant::now());
         self.state = ConnectionState::Streaming;
         Ok(device)
     }
 
     pub async fn tick(&mut self) -> Snapshot {
+        let mut snapshot = self.sample().await;
+        snapshot.events = self.events.observe(&snapshot);
+        self.apply_sampling_policy();
+        snapshot.quality.effective_interval_ms = self.effective_interval.as_millis();
+        snapshot.quality.interval_reason = self.interval_reason;
+        snapshot.quality.interval_changes = self.interval_changes;
+        // Derived vars are only computed from a read that completed this tick.
+        if self.state == ConnectionState::Streaming {
+            let on_battery = self.events.mains_lost();
+            self.runtime.update(&mut snapshot, on_battery);
+            self.health.update(&mut snapshot, on_battery);
+            self.energy.update(&mut snapshot);
+            self.prodist.update(&mut snapshot, on_battery);
+            self.pq_stats.update(&mut snapshot);
+        }
+        snapshot.alerts = self.alerts.evaluate(&snapshot);
+        snapshot
+    }
+
+    async fn sample(&mut self) -> Snapshot {
         if !self.driver.is_connected() {
             match self.ensure_connected().await {
                 Ok(_) => {}
                 Err(err) => {
                     self.reads_err += 1;
                     self.errors_in_row += 1;
+                    self.stats.record(Instant::now(), false, Duration::ZERO);
                     self.state = ConnectionState::Disconnected;
-                    return self.disconnected_snapshot(err.to_string(), 0, BTreeMap::new());
+                    return self.disconnected_snapshot(err.to_string(), 0);
                 }
             }
         }
@@ -85,87 +236,186 @@ impl<D: UpsDriver> Monitor<D> {
         let timed = timeout(self.config.poll_timeout, self.driver.read()).await;
 
         match timed {
-            Ok(Ok(read_result)) => {
+            Ok(Ok(mut read_result)) => {
                 self.reads_ok += 1;
                 self.errors_in_row = 0;
                 let rtt = started.elapsed();
                 self.last_ok_instant = Some(Instant::now());
                 self.last_ok_ts = Some(Utc::now());
                 self.state = ConnectionState::Streaming;
+                self.stats.record(Instant::now(), true, rtt);
 
-                if self.config.auto_tune {
+                if self.config.auto_tune && !self.interval_pinned {
                     self.tune_interval(rtt, true);
                 }
 
-                self.connected_snapshot(
+                let validity = self.plausibility.check(
+                    Instant::now(),
+                    &mut read_result.vars,
+                    &mut read_result.failures,
+                );
+                self.filters.apply(&mut read_result.vars);
+
+                if self.config.hold_last_good {
+                    let now = Instant::now();
+                    for (key, value) in &read_result.vars {
+                        self.last_good.insert(key.clone(), (value.clone(), now));
+                    }
+                }
+
+                let mut snapshot = self.connected_snapshot(
                     read_result.status_code,
                     read_result.failures,
                     read_result.vars,
                     rtt,
-                )
+                );
+                snapshot.validity = validity;
+                snapshot
+            }
+            Ok(Err(err)) => self.record_failure(err.to_string(), started.elapsed().as_millis()).await,
+            Err(_) => {
+                self.record_failure("timeout".to_string(), self.config.poll_timeout.as_millis())
+                    .await
             }
-            Ok(Err(err)) => {
-                self.reads_err += 1;
-                self.errors_in_row += 1;
+        }
+    }
 
-                if self.config.auto_tune {
-                    self.tune_interval(self.config.poll_timeout, false);
-                }
+    /// Books a failed read and decides the next connection state.
+    ///
+    /// The `disconnected_after` deadline is checked first so that a device
+    /// which keeps failing below `error_threshold` is still re-initialised.
+    async fn record_failure(&mut self, reason: String, rtt_ms: u128) -> Snapshot {
+        self.reads_err += 1;
+        self.errors_in_row += 1;
+        self.stats.record(Instant::now(), false, Duration::ZERO);
 
-                let should_reconnect = self.errors_in_row >= self.config.error_threshold;
-                if should_reconnect {
-                    self.state = ConnectionState::Reconnecting;
-                    let _ = self.driver.disconnect().await;
-                    self.reconnects += 1;
-                    self.current = None;
-                } else {
-                    self.state = ConnectionState::Degraded;
-                }
+        if self.config.auto_tune && !self.interval_pinned {
+            self.tune_interval(self.config.poll_timeout, false);
+        }
 
-                self.disconnected_snapshot(err.to_string(), started.elapsed().as_millis(), BTreeMap::new())
-            }
-            Err(_) => {
-                self.reads_err += 1;
-                self.errors_in_row += 1;
+        if self.deadline_expired() {
+            self.state = ConnectionState::Disconnected;
+            self.reset_driver().await;
+        } else if self.errors_in_row >= self.config.error_threshold {
+            self.state = ConnectionState::Reconnecting;
+            self.reset_driver().await;
+        } else if !self.driver.is_connected() {
+            self.state = ConnectionState::Disconnected;
+            self.current = None;
+            self.connected_at = None;
+        } else {
+            self.state = ConnectionState::Degraded;
+        }
 
-                if self.config.auto_tune {
-                    self.tune_interval(self.config.poll_timeout, false);
-                }
+        self.disconnected_snapshot(reason, rtt_ms)
+    }
 
-                if self.errors_in_row >= self.config.error_threshold {
-                    self.state = ConnectionState::Reconnecting;
-                    let _ = self.driver.disconnect().await;
-                    self.reconnects += 1;
-                    self.current = None;
-                } else {
-                    self.state = ConnectionState::Degraded;
-                }
+    async fn reset_driver(&mut self) {
+        let _ = self.driver.disconnect().await;
+        self.reconnects += 1;
+        self.current = None;
+        self.connected_at = None;
+    }
 
-                self.disconnected_snapshot("timeout".to_string(), self.config.poll_timeout.as_millis(), BTreeMap::new())
-            }
-        }
+    /// True once no read has succeeded for `disconnected_after`, measured
+    /// from the later of the last good read and the current session start.
+    fn deadline_expired(&self) -> bool {
+        let anchor = match (self.last_ok_instant, self.connected_at) {
+            (Some(ok), Some(conn)) => ok.max(conn),
+            (Some(ok), None) => ok,
+            (None, Some(conn)) => conn,
+            (None, None) => self.process_start,
+        };
+        anchor.elapsed() >= self.config.disconnected_after
     }
 
     fn tune_interval(&mut self, rtt: Duration, ok: bool) {
         if !ok {
-            self.effective_interval = (self.effective_interval + Duration::from_millis(250))
+            let next = (self.effective_interval + Duration::from_millis(250))
                 .min(self.config.sample_interval_max);
+            self.set_interval(next, IntervalReason::ReadError);
             return;
         }
 
         let threshold = self.effective_interval.mul_f64(0.6);
         if rtt > threshold {
-            self.effective_interval = (self.effective_interval + Duration::from_millis(200))
+            let next = (self.effective_interval + Duration::from_millis(200))
                 .min(self.config.sample_interval_max);
+            self.set_interval(next, IntervalReason::SlowRead);
             return;
         }
 
-        if self.reads_ok % 30 == 0 {
-            self.effective_interval = self
+        if self.reads_ok.is_multiple_of(30) {
+            let next = self
                 .effective_interval
                 .saturating_sub(Duration::from_millis(100))
                 .max(self.config.sample_interval_min);
+            self.set_interval(next, IntervalReason::FastReads);
+        }
+    }
+
+    /// Overrides the RTT-driven interval from the power state: full
+    /// resolution while on battery or during a power event, and the relaxed
+    /// maximum once the mains has been stable for `stable_after`.
+    fn apply_sampling_policy(&mut self) {
+        if !self.config.power_aware_sampling {
+            return;
+        }
+
+        let now = Instant::now();
+        let power_event = self
+            .events
+            .active()
+            .into_iter()
+            .any(|kind| kind != PowerEventKind::DeviceLost);
+        let urgent = if self.events.mains_lost() {
+            Some(IntervalReason::OnBattery)
+        } else if power_event {
+            Some(IntervalReason::ActiveEvent)
+        } else {
+            None
+        };
+
+        if let Some(reason) = urgent {
+            self.stable_since = None;
+            self.interval_pinned = true;
+            self.set_interval(self.config.sample_interval_min, reason);
+            return;
         }
+
+        match self.state {
+            // A failed read says nothing about the mains, so it does not
+            // restart the stability clock.
+            ConnectionState::Streaming | ConnectionState::Degraded => {
+                let since = *self.stable_since.get_or_insert(now);
+                self.interval_pinned = now.duration_since(since) >= self.config.stable_after;
+                if self.interval_pinned {
+                    self.set_interval(
+                        self.config.sample_interval_max,
+                        IntervalReason::StableOnMains,
+                    );
+                }
+            }
+            _ => {
+                self.stable_since = None;
+                self.interval_pinned = false;
+            }
+        }
+    }
+
+    fn set_interval(&mut self, interval: Duration, reason: IntervalReason) {
+        if interval == self.effective_interval {
+            return;
+        }
+        info!(
+            from_ms = self.effective_interval.as_millis() as u64,
+            to_ms = interval.as_millis() as u64,
+            ?reason,
+            "sampling interval changed"
+        );
+        self.effective_interval = interval;
+        self.interval_reason = reason;
+        self.interval_changes += 1;
     }
 
     fn connected_snapshot(
@@ -193,6 +443,11 @@ impl<D: UpsDriver> Monitor<D> {
                 failures,
             },
             vars,
+            vars_meta: BTreeMap::new(),
+            validity: BTreeMap::new(),
+            events: Vec::new(),
+            daily_report: None,
+            alerts: Vec::new(),
             quality: SnapshotQuality {
                 poll_ms: rtt.as_millis(),
                 stale_seconds: 0.0,
@@ -200,28 +455,52 @@ impl<D: UpsDriver> Monitor<D> {
                 reads_err: self.reads_err,
                 reconnects: self.reconnects,
                 effective_interval_ms: self.effective_interval.as_millis(),
+                interval_reason: self.interval_reason,
+                interval_changes: self.interval_changes,
+                ticks_skipped: self.scheduler.skipped(),
+                ticks_late: self.scheduler.late(),
+                windows: self.stats.summarize(Instant::now()),
             },
         }
     }
 
-    fn disconnected_snapshot(
-        &self,
-        reason: String,
-        rtt_ms: u128,
-        vars: BTreeMap<String, serde_json::Value>,
-    ) -> Snapshot {
+    /// Last good value of every var, tagged with how long ago it was read.
+    fn held_vars(&self) -> (BTreeMap<String, serde_json::Value>, BTreeMap<String, VarMeta>) {
+        if !self.config.hold_last_good {
+            return (BTreeMap::new(), BTreeMap::new());
+        }
+
+        let mut vars = BTreeMap::new();
+        let mut meta = BTreeMap::new();
+        for (key, (value, read_at)) in &self.last_good {
+            vars.insert(key.clone(), value.clone());
+            meta.insert(
+                key.clone(),
+                VarMeta {
+                    age_ms: read_at.elapsed().as_millis(),
+                    held: true,
+                },
+            );
+        }
+        (vars, meta)
+    }
+
+    fn disconnected_snapshot(&self, reason: String, rtt_ms: u128) -> Snapshot {
         let now = Utc::now();
         let age_ms = self
             .last_ok_instant
-            .map(|t| t.elapsed().as_millis())
-            .unwrap_or(self.config.disconnected_after.as_millis());
+            .unwrap_or(self.process_start)
+            .elapsed()
+            .as_millis();
 
-        let stale = age_ms > self.config.stale_after.as_millis();
+        let stale = self.last_ok_instant.is_none() || age_ms > self.config.stale_after.as_millis();
+        let connected = self.driver.is_connected();
+        let (vars, vars_meta) = self.held_vars();
 
         Snapshot {
             ts: now,
             mono_ms: self.process_start.elapsed().as_millis(),
-            device: self.snapshot_device(false),
+            device: self.snapshot_device(connected),
             freshness: Freshness {
                 rtt_ms,
                 age_ms,
@@ -229,10 +508,15 @@ impl<D: UpsDriver> Monitor<D> {
                 last_ok_ts: self.last_ok_ts,
             },
             status: MonitorStatus {
-                code: "DISCONNECTED".to_string(),
+                code: self.state.as_code().to_string(),
                 failures: vec![reason],
             },
             vars,
+            vars_meta,
+            validity: BTreeMap::new(),
+            events: Vec::new(),
+            daily_report: None,
+            alerts: Vec::new(),
             quality: SnapshotQuality {
                 poll_ms: rtt_ms,
                 stale_seconds: age_ms as f64 / 1000.0,
@@ -240,6 +524,11 @@ impl<D: UpsDriver> Monitor<D> {
                 reads_err: self.reads_err,
                 reconnects: self.reconnects,
                 effective_interval_ms: self.effective_interval.as_millis(),
+                interval_reason: self.interval_reason,
+                interval_changes: self.interval_changes,
+                ticks_skipped: self.scheduler.skipped(),
+                ticks_late: self.scheduler.late(),
+                windows: self.stats.summarize(Instant::now()),
             },
         }
     }
@@ -250,7 +539,7 @@ impl<D: UpsDriver> Monitor<D> {
                 .target_id
                 .clone()
                 .unwrap_or_else(|| "unknown".to_string()),
-            model: "RagTech 3200VA".to_string(),
+            model: self.config.profile.name.clone(),
             transport: "unknown".to_string(),
             path: "".to_string(),
             vid: "".to_string(),
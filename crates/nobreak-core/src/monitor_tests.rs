@@ -0,0 +1,170 @@
+use std::time::Duration;
+
+use crate::config::MonitorConfig;
+use crate::monitor::{ConnectionState, Monitor};
+use crate::scheduler::IntervalReason;
+use crate::test_driver::{failed_read, ok_read, ScriptedDriver};
+
+fn deadline_config() -> MonitorConfig {
+    MonitorConfig {
+        disconnected_after: Duration::from_secs(5),
+        error_threshold: 100,
+        auto_tune: false,
+        ..MonitorConfig::default()
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn disconnected_after_forces_reinit_below_error_threshold() {
+    // Arrange
+    let mut reads = vec![ok_read(&[("vInput", 127.0)])];
+    reads.extend((0..5).map(|_| failed_read()));
+    reads.push(ok_read(&[("vInput", 126.0)]));
+    let mut monitor = Monitor::new(ScriptedDriver::new(reads), deadline_config(), None);
+
+    // Act
+    let first = monitor.tick().await;
+    let mut codes = Vec::new();
+    for _ in 0..5 {
+        tokio::time::advance(Duration::from_secs(1)).await;
+        codes.push(monitor.tick().await.status.code);
+    }
+    let disconnected_state = monitor.connection_state();
+    tokio::time::advance(Duration::from_secs(1)).await;
+    let recovered = monitor.tick().await;
+
+    // Assert
+    assert_eq!(first.status.code, "ONLINE_RAW");
+    assert_eq!(codes, ["DEGRADED", "DEGRADED", "DEGRADED", "DEGRADED", "DISCONNECTED"]);
+    assert_eq!(disconnected_state, ConnectionState::Disconnected);
+    assert!(recovered.device.connected, "next tick should re-init the driver");
+    assert_eq!(recovered.status.code, "ONLINE_RAW");
+    assert_eq!(recovered.quality.reconnects, 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn deadline_restarts_from_new_session_after_reinit() {
+    // Arrange
+    let reads = (0..7).map(|_| failed_read()).collect();
+    let mut monitor = Monitor::new(ScriptedDriver::new(reads), deadline_config(), None);
+
+    // Act
+    let mut codes = Vec::new();
+    for _ in 0..7 {
+        codes.push(monitor.tick().await.status.code);
+        tokio::time::advance(Duration::from_secs(1)).await;
+    }
+
+    // Assert
+    assert_eq!(
+        codes,
+        ["DEGRADED", "DEGRADED", "DEGRADED", "DEGRADED", "DEGRADED", "DISCONNECTED", "DEGRADED"]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn never_successful_monitor_reports_stale() {
+    // Arrange
+    let mut monitor = Monitor::new(ScriptedDriver::new(vec![failed_read()]), deadline_config(), None);
+
+    // Act
+    let snapshot = monitor.tick().await;
+
+    // Assert
+    assert!(snapshot.freshness.stale);
+    assert!(snapshot.freshness.last_ok_ts.is_none());
+}
+
+#[tokio::test(start_paused = true)]
+async fn held_vars_carry_their_own_age_on_failed_reads() {
+    // Arrange
+    let config = MonitorConfig {
+        hold_last_good: true,
+        ..deadline_config()
+    };
+    let reads = vec![
+        ok_read(&[("vInput", 127.0), ("cBattery", 100.0)]),
+        ok_read(&[("vInput", 126.0)]),
+        failed_read(),
+    ];
+    let mut monitor = Monitor::new(ScriptedDriver::new(reads), config, None);
+
+    // Act
+    monitor.tick().await;
+    tokio::time::advance(Duration::from_secs(1)).await;
+    let fresh = monitor.tick().await;
+    tokio::time::advance(Duration::from_secs(1)).await;
+    let degraded = monitor.tick().await;
+
+    // Assert
+    assert!(fresh.vars_meta.is_empty(), "fresh snapshots carry no held vars");
+    assert_eq!(degraded.vars.get("vInput").and_then(|v| v.as_f64()), Some(126.0));
+    assert_eq!(degraded.vars_meta["vInput"].age_ms, 1000);
+    assert_eq!(degraded.vars_meta["cBattery"].age_ms, 2000);
+    assert!(degraded.vars_meta.values().all(|m| m.held));
+}
+
+#[tokio::test(start_paused = true)]
+async fn failed_reads_drop_vars_unless_holding() {
+    // Arrange
+    let reads = vec![ok_read(&[("vInput", 127.0)]), failed_read()];
+    let mut monitor = Monitor::new(ScriptedDriver::new(reads), deadline_config(), None);
+
+    // Act
+    monitor.tick().await;
+    let degraded = monitor.tick().await;
+
+    // Assert
+    assert!(degraded.vars.is_empty());
+    assert!(degraded.vars_meta.is_empty());
+}
+
+fn policy_config() -> MonitorConfig {
+    MonitorConfig {
+        sample_interval: Duration::from_secs(2),
+        stable_after: Duration::from_secs(10),
+        auto_tune: false,
+        ..MonitorConfig::default()
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn on_battery_pins_the_minimum_interval() {
+    // Arrange
+    let reads = vec![ok_read(&[("vInput", 0.0), ("cBattery", 90.0)])];
+    let mut monitor = Monitor::new(ScriptedDriver::new(reads), policy_config(), None);
+
+    // Act
+    let snapshot = monitor.tick().await;
+
+    // Assert
+    assert_eq!(monitor.effective_interval(), Duration::from_secs(1));
+    assert_eq!(snapshot.quality.effective_interval_ms, 1000);
+    assert_eq!(snapshot.quality.interval_reason, IntervalReason::OnBattery);
+    assert_eq!(snapshot.quality.interval_changes, 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn stable_mains_relaxes_until_a_power_event() {
+    // Arrange
+    let mut reads = (0..12)
+        .map(|_| ok_read(&[("vInput", 127.0)]))
+        .collect::<Vec<_>>();
+    reads.push(ok_read(&[("vInput", 100.0)]));
+    let mut monitor = Monitor::new(ScriptedDriver::new(reads), policy_config(), None);
+
+    // Act
+    let mut stable = None;
+    for _ in 0..12 {
+        stable = Some(monitor.tick().await);
+        tokio::time::advance(Duration::from_secs(1)).await;
+    }
+    let brownout = monitor.tick().await;
+
+    // Assert
+    let stable = stable.expect("ticked");
+    assert_eq!(stable.quality.effective_interval_ms, 3000);
+    assert_eq!(stable.quality.interval_reason, IntervalReason::StableOnMains);
+    assert_eq!(brownout.quality.effective_interval_ms, 1000);
+    assert_eq!(brownout.quality.interval_reason, IntervalReason::ActiveEvent);
+}
@@ -0,0 +1,23 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Reads a JSON state file written by [`save_json`].
+pub fn load_json<T: DeserializeOwned>(path: &Path) -> io::Result<T> {
+    let raw = fs::read(path)?;
+    serde_json::from_slice(&raw).map_err(io::Error::other)
+}
+
+/// Writes through a temp file and renames it into place, so a crash never
+/// leaves a truncated state file behind.
+pub fn save_json<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(value)?)?;
+    fs::rename(tmp, path)
+}
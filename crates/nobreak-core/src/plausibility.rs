@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+/// Accepted range and maximum rate of change for one decoded metric.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricLimits {
+    pub min: f64,
+    pub max: f64,
+    /// Largest plausible change per second; `None` disables spike checks,
+    /// e.g. for `vInput`, which legitimately drops to zero on a blackout.
+    #[serde(default)]
+    pub max_rate_per_sec: Option<f64>,
+}
+
+impl MetricLimits {
+    fn new(min: f64, max: f64, max_rate_per_sec: Option<f64>) -> Self {
+        Self {
+            min,
+            max,
+            max_rate_per_sec,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlausibilityConfig {
+    pub limits: BTreeMap<String, MetricLimits>,
+    /// Consecutive spike rejections after which the latest value is accepted
+    /// as a genuine step change.
+    pub spike_confirm_samples: u32,
+}
+
+impl Default for PlausibilityConfig {
+    fn default() -> Self {
+        let limits = [
+            ("vInput", MetricLimits::new(0.0, 300.0, None)),
+            ("vOutput", MetricLimits::new(0.0, 300.0, None)),
+            ("fOutput", MetricLimits::new(0.0, 70.0, Some(5.0))),
+            ("pOutput", MetricLimits::new(0.0, 150.0, None)),
+            ("vBattery", MetricLimits::new(0.0, 80.0, Some(5.0))),
+            ("cBattery", MetricLimits::new(0.0, 100.0, Some(10.0))),
+            ("temperature", MetricLimits::new(-10.0, 90.0, Some(2.0))),
+        ]
+        .into_iter()
+        .map(|(name, limits)| (name.to_string(), limits))
+        .collect();
+
+        Self {
+            limits,
+            spike_confirm_samples: 3,
+        }
+    }
+}
+
+impl PlausibilityConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, limits) in &self.limits {
+            if limits.min > limits.max {
+                return Err(format!("{name}: min must not exceed max"));
+            }
+            if limits.max_rate_per_sec.is_some_and(|rate| rate <= 0.0) {
+                return Err(format!("{name}: max_rate_per_sec must be positive"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Validity {
+    Ok,
+    OutOfRange,
+    Spike,
+}
+
+impl Validity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::OutOfRange => "out_of_range",
+            Self::Spike => "spike",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Accepted {
+    value: f64,
+    at: Instant,
+    spikes: u32,
+}
+
+/// Screens decoded metrics against configured ranges and rate-of-change
+/// limits before they reach the rest of the pipeline.
+#[derive(Debug, Clone)]
+pub struct PlausibilityChecker {
+    config: PlausibilityConfig,
+    accepted: BTreeMap<String, Accepted>,
+}
+
+impl PlausibilityChecker {
+    pub fn new(config: PlausibilityConfig) -> Self {
+        Self {
+            config,
+            accepted: BTreeMap::new(),
+        }
+    }
+
+    pub fn set_config(&mut self, config: PlausibilityConfig) {
+        self.config = config;
+    }
+
+    /// Removes implausible metrics from `vars`, describing each in
+    /// `failures`, and returns the validity of every checked metric.
+    pub fn check(
+        &mut self,
+        at: Instant,
+        vars: &mut BTreeMap<String, serde_json::Value>,
+        failures: &mut Vec<String>,
+    ) -> BTreeMap<String, Validity> {
+        let mut validity = BTreeMap::new();
+
+        let confirm = self.config.spike_confirm_samples;
+        for (name, limits) in &self.config.limits {
+            let Some(value) = vars.get(name).and_then(|v| v.as_f64()) else {
+                continue;
+            };
+            let status = if !value.is_finite() || value < limits.min || value > limits.max {
+                Validity::OutOfRange
+            } else {
+                rate_check(
+                    &mut self.accepted,
+                    confirm,
+                    name,
+                    value,
+                    at,
+                    limits.max_rate_per_sec,
+                )
+            };
+
+            if status != Validity::Ok {
+                vars.remove(name);
+                failures.push(format!("implausible:{name}={value} ({})", status.as_str()));
+            }
+            validity.insert(name.clone(), status);
+        }
+
+        validity
+    }
+}
+
+fn rate_check(
+    accepted: &mut BTreeMap<String, Accepted>,
+    confirm: u32,
+    name: &str,
+    value: f64,
+    at: Instant,
+    max_rate: Option<f64>,
+) -> Validity {
+    let Some(prev) = accepted.get_mut(name) else {
+        accepted.insert(
+            name.to_string(),
+            Accepted {
+                value,
+                at,
+                spikes: 0,
+            },
+        );
+        return Validity::Ok;
+    };
+
+    let Some(max_rate) = max_rate else {
+        *prev = Accepted {
+            value,
+            at,
+            spikes: 0,
+        };
+        return Validity::Ok;
+    };
+
+    // At least one second of slack so back-to-back reads are not
+    // held to an unreasonably tight step.
+    let dt = at.duration_since(prev.at).as_secs_f64().max(1.0);
+    if (value - prev.value).abs() / dt <= max_rate || prev.spikes + 1 >= confirm {
+        *prev = Accepted {
+            value,
+            at,
+            spikes: 0,
+        };
+        Validity::Ok
+    } else {
+        prev.spikes += 1;
+        Validity::Spike
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::config::MonitorConfig;
+use crate::monitor::Monitor;
+use crate::plausibility::{PlausibilityChecker, PlausibilityConfig, Validity};
+use crate::test_driver::{ok_read, ScriptedDriver};
+
+fn vars(pairs: &[(&str, f64)]) -> BTreeMap<String, serde_json::Value> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), serde_json::Value::from(*v)))
+        .collect()
+}
+
+#[tokio::test(start_paused = true)]
+async fn out_of_range_metrics_are_dropped_and_reported() {
+    // Arrange
+    let reads = vec![ok_read(&[("vInput", 900.0), ("cBattery", 80.0)])];
+    let mut monitor = Monitor::new(ScriptedDriver::new(reads), MonitorConfig::default(), None);
+
+    // Act
+    let snapshot = monitor.tick().await;
+
+    // Assert
+    assert!(!snapshot.vars.contains_key("vInput"));
+    assert_eq!(snapshot.vars["cBattery"], 80.0);
+    assert_eq!(snapshot.validity["vInput"], Validity::OutOfRange);
+    assert_eq!(snapshot.validity["cBattery"], Validity::Ok);
+    assert_eq!(
+        snapshot.status.failures,
+        ["implausible:vInput=900 (out_of_range)"]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn spikes_are_rejected_until_the_new_level_is_confirmed() {
+    // Arrange
+    let mut checker = PlausibilityChecker::new(PlausibilityConfig::default());
+    let levels = [80.0, 81.0, 20.0, 20.0, 20.0, 21.0];
+
+    // Act
+    let mut verdicts = Vec::new();
+    for level in levels {
+        let mut read = vars(&[("cBattery", level)]);
+        let mut failures = Vec::new();
+        let validity = checker.check(Instant::now(), &mut read, &mut failures);
+        verdicts.push(validity["cBattery"]);
+        tokio::time::advance(Duration::from_secs(1)).await;
+    }
+
+    // Assert
+    assert_eq!(
+        verdicts,
+        [
+            Validity::Ok,
+            Validity::Ok,
+            Validity::Spike,
+            Validity::Spike,
+            Validity::Ok,
+            Validity::Ok
+        ]
+    );
+}
+
+#[test]
+fn inverted_range_is_rejected() {
+    // Arrange
+    let mut config = PlausibilityConfig::default();
+    if let Some(limits) = config.limits.get_mut("vInput") {
+        limits.min = 400.0;
+    }
+
+    // Act
+    let result = config.validate();
+
+    // Assert
+    assert!(result.is_err());
+}
@@ -0,0 +1,420 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::path::PathBuf;
+
+use chrono::{DateTime, FixedOffset, NaiveDate, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::persist::{load_json, save_json};
+use crate::snapshot::Snapshot;
+
+/// IEEE 1159 bands, in per-unit of the nominal voltage.
+const SAG_BELOW_PU: f64 = 0.9;
+const SWELL_ABOVE_PU: f64 = 1.1;
+/// Deliberately not PRODIST's 50 V: the sag buckets and the ITIC curve are
+/// defined down to 0.1 pu, so a 40 V dip is still a deep sag here even though
+/// PRODIST and `MAINS_LOST` already count it as the mains being gone.
+const INTERRUPTION_BELOW_PU: f64 = 0.1;
+/// Episodes kept per day, to bound the state file.
+const MAX_EPISODES_PER_DAY: usize = 200;
+const SAVE_EVERY_SEC: i64 = 60;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PqStatsConfig {
+    pub state_path: Option<PathBuf>,
+    /// Offset from UTC for day and hour boundaries.
+    pub utc_offset_minutes: i32,
+    pub histogram_bin_v: f64,
+    pub retention_days: usize,
+}
+
+impl Default for PqStatsConfig {
+    fn default() -> Self {
+        Self {
+            state_path: None,
+            utc_offset_minutes: 0,
+            histogram_bin_v: 2.0,
+            retention_days: 31,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisturbanceKind {
+    Sag,
+    Swell,
+    Interruption,
+}
+
+/// Regions of the ITIC (CBEMA) tolerance curve.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IticRegion {
+    NoInterruption,
+    NoDamage,
+    Prohibited,
+}
+
+/// Places a disturbance of `magnitude_pu` lasting `duration_sec` on the
+/// ITIC curve.
+pub fn itic_region(magnitude_pu: f64, duration_sec: f64) -> IticRegion {
+    if magnitude_pu > 1.0 {
+        let limit = match duration_sec {
+            d if d < 0.001 => 2.0,
+            d if d < 0.003 => 1.4,
+            d if d < 0.5 => 1.2,
+            _ => 1.1,
+        };
+        if magnitude_pu > limit {
+            IticRegion::Prohibited
+        } else {
+            IticRegion::NoInterruption
+        }
+    } else {
+        let limit = match duration_sec {
+            d if d < 0.02 => 0.0,
+            d if d < 0.5 => 0.7,
+            d if d < 10.0 => 0.8,
+            _ => 0.9,
+        };
+        if magnitude_pu < limit {
+            IticRegion::NoDamage
+        } else {
+            IticRegion::NoInterruption
+        }
+    }
+}
+
+/// Depth bucket of a disturbance, named by its per-unit range.
+pub fn depth_bucket(kind: DisturbanceKind, magnitude_pu: f64) -> &'static str {
+    match kind {
+        DisturbanceKind::Interruption => "<10%",
+        DisturbanceKind::Sag => match magnitude_pu {
+            m if m >= 0.8 => "80-90%",
+            m if m >= 0.7 => "70-80%",
+            m if m >= 0.5 => "50-70%",
+            _ => "10-50%",
+        },
+        DisturbanceKind::Swell => match magnitude_pu {
+            m if m <= 1.2 => "110-120%",
+            m if m <= 1.4 => "120-140%",
+            _ => ">140%",
+        },
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Disturbance {
+    pub kind: DisturbanceKind,
+    pub started_at: DateTime<Utc>,
+    pub duration_sec: f64,
+    /// Residual voltage for sags, peak voltage for swells, in per-unit.
+    pub magnitude_pu: f64,
+    pub bucket: String,
+    pub itic: IticRegion,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BucketStats {
+    pub count: u64,
+    pub total_sec: f64,
+    pub max_sec: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HourExcursion {
+    pub min_v: f64,
+    pub max_v: f64,
+    pub samples: u64,
+    /// Largest deviation from nominal, in percent.
+    pub max_excursion_pct: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IticCounts {
+    pub no_interruption: u64,
+    pub no_damage: u64,
+    pub prohibited: u64,
+}
+
+/// Power-quality statistics for one local day.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PowerQualityDay {
+    pub date: NaiveDate,
+    pub nominal_v: Option<f64>,
+    /// `vInput` sample counts keyed by the lower edge of each bin, in volts.
+    pub histogram: BTreeMap<i64, u64>,
+    pub histogram_bin_v: f64,
+    /// Keyed by local hour (0-23).
+    pub hours: BTreeMap<u32, HourExcursion>,
+    pub sags: BTreeMap<String, BucketStats>,
+    pub swells: BTreeMap<String, BucketStats>,
+    pub interruptions: BucketStats,
+    pub itic: IticCounts,
+    pub disturbances: Vec<Disturbance>,
+}
+
+impl PowerQualityDay {
+    fn new(date: NaiveDate, bin_v: f64) -> Self {
+        Self {
+            date,
+            nominal_v: None,
+            histogram: BTreeMap::new(),
+            histogram_bin_v: bin_v,
+            hours: BTreeMap::new(),
+            sags: BTreeMap::new(),
+            swells: BTreeMap::new(),
+            interruptions: BucketStats::default(),
+            itic: IticCounts::default(),
+            disturbances: Vec::new(),
+        }
+    }
+
+    fn record(&mut self, disturbance: Disturbance) {
+        let bucket = match disturbance.kind {
+            DisturbanceKind::Sag => self.sags.entry(disturbance.bucket.clone()).or_default(),
+            DisturbanceKind::Swell => self.swells.entry(disturbance.bucket.clone()).or_default(),
+            DisturbanceKind::Interruption => &mut self.interruptions,
+        };
+        bucket.count += 1;
+        bucket.total_sec += disturbance.duration_sec;
+        bucket.max_sec = bucket.max_sec.max(disturbance.duration_sec);
+
+        match disturbance.itic {
+            IticRegion::NoInterruption => self.itic.no_interruption += 1,
+            IticRegion::NoDamage => self.itic.no_damage += 1,
+            IticRegion::Prohibited => self.itic.prohibited += 1,
+        }
+        if self.disturbances.len() < MAX_EPISODES_PER_DAY {
+            self.disturbances.push(disturbance);
+        }
+    }
+
+    fn count(&self, kind: DisturbanceKind) -> u64 {
+        match kind {
+            DisturbanceKind::Sag => self.sags.values().map(|b| b.count).sum(),
+            DisturbanceKind::Swell => self.swells.values().map(|b| b.count).sum(),
+            DisturbanceKind::Interruption => self.interruptions.count,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PqStatsState {
+    pub days: VecDeque<PowerQualityDay>,
+}
+
+#[derive(Debug, Clone)]
+struct OpenDisturbance {
+    kind: DisturbanceKind,
+    started_at: DateTime<Utc>,
+    extreme_pu: f64,
+}
+
+/// Aggregates sags, swells and interruptions by depth and ITIC region,
+/// plus a daily `vInput` histogram and hourly min/max excursions.
+///
+/// Disturbances are seen at the sampling rate; anything shorter than the
+/// sample interval is only caught if a sample happens to land in it.
+#[derive(Debug, Clone)]
+pub struct PqStats {
+    config: PqStatsConfig,
+    state: PqStatsState,
+    open: Option<OpenDisturbance>,
+    last_saved: Option<DateTime<Utc>>,
+}
+
+impl PqStats {
+    pub fn new(config: PqStatsConfig) -> Self {
+        let state = config
+            .state_path
+            .as_deref()
+            .filter(|path| path.exists())
+            .and_then(|path| match load_json::<PqStatsState>(path) {
+                Ok(state) => Some(state),
+                Err(err) => {
+                    warn!(path=%path.display(), %err, "ignoring unreadable power-quality state");
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        Self {
+            config,
+            state,
+            open: None,
+            last_saved: None,
+        }
+    }
+
+    pub fn days(&self) -> &VecDeque<PowerQualityDay> {
+        &self.state.days
+    }
+
+    /// Feeds one sample; sets `snapshot.daily_report` when a day closes.
+    pub fn update(&mut self, snapshot: &mut Snapshot) {
+        let Some(v) = snapshot.raw_metric("vInput") else {
+            return;
+        };
+        let ts = snapshot.ts;
+        let nominal = snapshot.metric("vInputNominal");
+        let local = self.local(ts);
+        let date = local.date_naive();
+
+        if self.state.days.back().is_none_or(|day| day.date != date) {
+            snapshot.daily_report = self.state.days.back().cloned();
+            self.state
+                .days
+                .push_back(PowerQualityDay::new(date, self.config.histogram_bin_v));
+            while self.state.days.len() > self.config.retention_days.max(1) {
+                self.state.days.pop_front();
+            }
+        }
+
+        let bin_v = self.config.histogram_bin_v.max(0.1);
+        let Some(day) = self.state.days.back_mut() else {
+            return;
+        };
+        day.nominal_v = nominal.or(day.nominal_v);
+        *day.histogram
+            .entry(((v / bin_v).floor() * bin_v) as i64)
+            .or_default() += 1;
+
+        let hour = day.hours.entry(local.hour()).or_insert(HourExcursion {
+            min_v: v,
+            max_v: v,
+            ..HourExcursion::default()
+        });
+        hour.min_v = hour.min_v.min(v);
+        hour.max_v = hour.max_v.max(v);
+        hour.samples += 1;
+        if let Some(nominal) = nominal {
+            let excursion = (v - nominal).abs() / nominal * 100.0;
+            hour.max_excursion_pct = Some(
+                hour.max_excursion_pct
+                    .map_or(excursion, |m| m.max(excursion)),
+            );
+        }
+
+        if let Some(nominal) = nominal {
+            self.track_disturbance(ts, v / nominal);
+        }
+        self.publish(snapshot, local.hour());
+        self.maybe_save(ts);
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(path) = &self.config.state_path {
+            if let Err(err) = save_json(path, &self.state) {
+                warn!(path=%path.display(), %err, "failed to persist power-quality state");
+            }
+        }
+    }
+
+    fn track_disturbance(&mut self, ts: DateTime<Utc>, pu: f64) {
+        let kind = if pu < INTERRUPTION_BELOW_PU {
+            Some(DisturbanceKind::Interruption)
+        } else if pu < SAG_BELOW_PU {
+            Some(DisturbanceKind::Sag)
+        } else if pu > SWELL_ABOVE_PU {
+            Some(DisturbanceKind::Swell)
+        } else {
+            None
+        };
+
+        match (&mut self.open, kind) {
+            (Some(open), Some(kind)) if same_direction(open.kind, kind) => {
+                // A sag that deepens into an interruption stays one episode.
+                if kind == DisturbanceKind::Interruption {
+                    open.kind = kind;
+                }
+                open.extreme_pu = if kind == DisturbanceKind::Swell {
+                    open.extreme_pu.max(pu)
+                } else {
+                    open.extreme_pu.min(pu)
+                };
+            }
+            (_, kind) => {
+                if let Some(open) = self.open.take() {
+                    self.close_disturbance(open, ts);
+                }
+                self.open = kind.map(|kind| OpenDisturbance {
+                    kind,
+                    started_at: ts,
+                    extreme_pu: pu,
+                });
+            }
+        }
+    }
+
+    fn close_disturbance(&mut self, open: OpenDisturbance, ended_at: DateTime<Utc>) {
+        let duration_sec = (ended_at - open.started_at).num_milliseconds().max(0) as f64 / 1000.0;
+        let disturbance = Disturbance {
+            kind: open.kind,
+            started_at: open.started_at,
+            duration_sec,
+            magnitude_pu: (open.extreme_pu * 1000.0).round() / 1000.0,
+            bucket: depth_bucket(open.kind, open.extreme_pu).to_string(),
+            itic: itic_region(open.extreme_pu, duration_sec),
+        };
+        if let Some(day) = self.state.days.back_mut() {
+            day.record(disturbance);
+        }
+    }
+
+    fn publish(&self, snapshot: &mut Snapshot, hour: u32) {
+        let Some(day) = self.state.days.back() else {
+            return;
+        };
+        let vars = &mut snapshot.vars;
+        vars.insert(
+            "pqSagsDay".to_string(),
+            serde_json::Value::from(day.count(DisturbanceKind::Sag)),
+        );
+        vars.insert(
+            "pqSwellsDay".to_string(),
+            serde_json::Value::from(day.count(DisturbanceKind::Swell)),
+        );
+        vars.insert(
+            "pqInterruptionsDay".to_string(),
+            serde_json::Value::from(day.count(DisturbanceKind::Interruption)),
+        );
+        vars.insert(
+            "pqIticProhibitedDay".to_string(),
+            serde_json::Value::from(day.itic.prohibited),
+        );
+        if let Some(excursion) = day.hours.get(&hour) {
+            vars.insert(
+                "vInputHourMin".to_string(),
+                serde_json::Value::from(excursion.min_v),
+            );
+            vars.insert(
+                "vInputHourMax".to_string(),
+                serde_json::Value::from(excursion.max_v),
+            );
+        }
+    }
+
+    fn local(&self, ts: DateTime<Utc>) -> DateTime<FixedOffset> {
+        let offset = FixedOffset::east_opt(self.config.utc_offset_minutes * 60)
+            .unwrap_or_else(|| FixedOffset::east_opt(0).expect("zero offset"));
+        ts.with_timezone(&offset)
+    }
+
+    fn maybe_save(&mut self, now: DateTime<Utc>) {
+        if self
+            .last_saved
+            .is_some_and(|saved| (now - saved).num_seconds() < SAVE_EVERY_SEC)
+        {
+            return;
+        }
+        self.last_saved = Some(now);
+        self.flush();
+    }
+}
+
+fn same_direction(a: DisturbanceKind, b: DisturbanceKind) -> bool {
+    (a == DisturbanceKind::Swell) == (b == DisturbanceKind::Swell)
+}
@@ -0,0 +1,111 @@
+use crate::pq_stats::{itic_region, IticRegion, PqStats, PqStatsConfig};
+use crate::test_driver::snapshot_at;
+
+#[test]
+fn itic_curve_regions() {
+    // Arrange
+    let cases = [
+        (0.85, 5.0, IticRegion::NoInterruption),
+        (0.75, 5.0, IticRegion::NoDamage),
+        (0.75, 0.2, IticRegion::NoInterruption),
+        (0.85, 30.0, IticRegion::NoDamage),
+        (1.15, 5.0, IticRegion::Prohibited),
+        (1.15, 0.2, IticRegion::NoInterruption),
+        (1.3, 0.002, IticRegion::NoInterruption),
+        (1.15, 30.0, IticRegion::Prohibited),
+        (1.3, 2.0, IticRegion::Prohibited),
+    ];
+
+    // Act
+    let regions = cases
+        .iter()
+        .map(|(pu, secs, _)| itic_region(*pu, *secs))
+        .collect::<Vec<_>>();
+
+    // Assert
+    let expected = cases.iter().map(|(_, _, r)| *r).collect::<Vec<_>>();
+    assert_eq!(regions, expected);
+}
+
+#[test]
+fn sags_and_swells_are_bucketed_with_durations() {
+    // Arrange
+    let mut stats = PqStats::new(PqStatsConfig::default());
+    let readings = [
+        220.0, 190.0, 170.0, 185.0, 220.0, 250.0, 255.0, 220.0, 221.0, 160.0, 220.0,
+    ];
+
+    // Act
+    let mut last = snapshot_at(0, true, &[]);
+    for (secs, v) in readings.iter().enumerate() {
+        last = snapshot_at(
+            secs as i64,
+            true,
+            &[("vInput", *v), ("vInputNominal", 220.0)],
+        );
+        stats.update(&mut last);
+    }
+
+    // Assert
+    let day = stats.days().back().expect("day");
+    let deep = &day.sags["70-80%"];
+    assert_eq!(deep.count, 2);
+    assert_eq!(deep.max_sec, 3.0);
+    assert_eq!(deep.total_sec, 4.0);
+    assert_eq!(day.swells["110-120%"].count, 1);
+    assert_eq!(day.itic.no_damage, 2);
+    // 1.16 pu for 2 s is past the 110% limit.
+    assert_eq!(day.itic.prohibited, 1);
+    assert_eq!(day.itic.no_interruption, 0);
+    assert_eq!(day.hours[&0].min_v, 160.0);
+    assert_eq!(day.hours[&0].max_v, 255.0);
+    assert_eq!(last.vars["pqSagsDay"], 2);
+}
+
+#[test]
+fn disturbances_are_counted_from_unfiltered_readings() {
+    // Arrange
+    let mut stats = PqStats::new(PqStatsConfig::default());
+    let raw = [220.0, 170.0, 220.0];
+
+    // Act
+    for (secs, v) in raw.iter().enumerate() {
+        let mut snapshot = snapshot_at(
+            secs as i64,
+            true,
+            &[
+                ("vInput", 220.0),
+                ("vInputRaw", *v),
+                ("vInputNominal", 220.0),
+            ],
+        );
+        stats.update(&mut snapshot);
+    }
+
+    // Assert
+    let day = stats.days().back().expect("day");
+    assert_eq!(day.sags["70-80%"].count, 1);
+    assert_eq!(day.hours[&0].min_v, 170.0);
+}
+
+#[test]
+fn daily_report_is_emitted_once_the_day_closes() {
+    // Arrange
+    let mut stats = PqStats::new(PqStatsConfig::default());
+    let mut first = snapshot_at(86_398, true, &[("vInput", 219.0)]);
+    let mut second = snapshot_at(86_399, true, &[("vInput", 221.0)]);
+    let mut next_day = snapshot_at(86_400, true, &[("vInput", 220.0)]);
+
+    // Act
+    stats.update(&mut first);
+    stats.update(&mut second);
+    stats.update(&mut next_day);
+
+    // Assert
+    let report = next_day.daily_report.expect("report for the closed day");
+    assert_eq!(report.date.to_string(), "2026-02-16");
+    assert_eq!(report.histogram[&218], 1);
+    assert_eq!(report.histogram[&220], 1);
+    assert!(second.daily_report.is_none());
+    assert_eq!(stats.days().len(), 2);
+}
@@ -0,0 +1,363 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::persist::{load_json, save_json};
+use crate::snapshot::Snapshot;
+
+/// PRODIST Module 8 integrates readings over 10-minute intervals.
+const WINDOW_SEC: i64 = 600;
+/// A full measurement campaign is 1008 intervals (7 days).
+const CAMPAIGN_WINDOWS: usize = 1008;
+/// Regulatory limits for the duration indices, in percent.
+const DRP_LIMIT_PCT: f64 = 3.0;
+const DRC_LIMIT_PCT: f64 = 0.5;
+/// Readings below this are interruptions, accounted by continuity indices
+/// rather than by DRP/DRC. The event detector's `MAINS_LOST` default uses the
+/// same limit, so both agree on when the mains are gone.
+pub(crate) const INTERRUPTION_BELOW_V: f64 = 50.0;
+/// Valid readings needed before the nominal voltage is locked in.
+const NOMINAL_LOCK_SAMPLES: u32 = 60;
+const SAVE_EVERY_SEC: i64 = 60;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProdistConfig {
+    pub state_path: Option<PathBuf>,
+    /// Fixed nominal voltage (127 or 220); auto-detected when `None`.
+    pub nominal_v: Option<f64>,
+    /// Normal-operation frequency band.
+    pub frequency_min_hz: f64,
+    pub frequency_max_hz: f64,
+}
+
+impl Default for ProdistConfig {
+    fn default() -> Self {
+        Self {
+            state_path: None,
+            nominal_v: None,
+            frequency_min_hz: 59.9,
+            frequency_max_hz: 60.1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VoltageClass {
+    Adequate,
+    Precarious,
+    Critical,
+    Interruption,
+}
+
+impl VoltageClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Adequate => "adequate",
+            Self::Precarious => "precarious",
+            Self::Critical => "critical",
+            Self::Interruption => "interruption",
+        }
+    }
+}
+
+/// Steady-state ranges for low-voltage connection points (Module 8, table
+/// for 220/127 V systems).
+pub fn classify(nominal_v: f64, reading_v: f64) -> VoltageClass {
+    if reading_v < INTERRUPTION_BELOW_V {
+        return VoltageClass::Interruption;
+    }
+    let (crit_low, prec_low, prec_high, crit_high) = if nominal_v >= 170.0 {
+        (191.0, 202.0, 231.0, 233.0)
+    } else {
+        (110.0, 117.0, 133.0, 135.0)
+    };
+    if (prec_low..=prec_high).contains(&reading_v) {
+        VoltageClass::Adequate
+    } else if (crit_low..=crit_high).contains(&reading_v) {
+        VoltageClass::Precarious
+    } else {
+        VoltageClass::Critical
+    }
+}
+
+/// Nominal voltage of the grid a reading most plausibly belongs to.
+pub fn detect_nominal(reading_v: f64) -> Option<f64> {
+    match reading_v {
+        v if v >= 170.0 => Some(220.0),
+        v if v >= 80.0 => Some(127.0),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IntervalReading {
+    pub start: DateTime<Utc>,
+    pub mean_v: f64,
+    pub samples: u32,
+    pub class: VoltageClass,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FrequencyStats {
+    pub samples: u64,
+    pub out_of_range_samples: u64,
+    pub out_of_range_sec: f64,
+    pub min_hz: Option<f64>,
+    pub max_hz: Option<f64>,
+    pub max_deviation_hz: f64,
+}
+
+/// Utility-facing summary over the retained campaign.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProdistReport {
+    pub nominal_v: Option<f64>,
+    pub intervals: usize,
+    pub campaign_complete: bool,
+    pub precarious_intervals: usize,
+    pub critical_intervals: usize,
+    pub drp_pct: f64,
+    pub drc_pct: f64,
+    pub drp_limit_pct: f64,
+    pub drc_limit_pct: f64,
+    pub drp_exceeded: bool,
+    pub drc_exceeded: bool,
+    pub first_interval: Option<DateTime<Utc>>,
+    pub last_interval: Option<DateTime<Utc>>,
+    pub frequency: FrequencyStats,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProdistState {
+    pub nominal_v: Option<f64>,
+    pub intervals: VecDeque<IntervalReading>,
+    pub frequency: FrequencyStats,
+}
+
+#[derive(Debug, Clone)]
+struct OpenInterval {
+    start: DateTime<Utc>,
+    sum_v: f64,
+    samples: u32,
+}
+
+/// Classifies `vInput` per PRODIST Module 8 and accumulates the DRP/DRC
+/// duration indices over 10-minute intervals, plus frequency deviations
+/// from `fOutput` (which tracks the input frequency while on mains).
+#[derive(Debug, Clone)]
+pub struct ProdistMonitor {
+    config: ProdistConfig,
+    state: ProdistState,
+    detect_votes: (u32, u32),
+    open: Option<OpenInterval>,
+    last_freq_ts: Option<DateTime<Utc>>,
+    last_saved: Option<DateTime<Utc>>,
+}
+
+impl ProdistMonitor {
+    pub fn new(config: ProdistConfig) -> Self {
+        let mut state = config
+            .state_path
+            .as_deref()
+            .filter(|path| path.exists())
+            .and_then(|path| match load_json::<ProdistState>(path) {
+                Ok(state) => Some(state),
+                Err(err) => {
+                    warn!(path=%path.display(), %err, "ignoring unreadable PRODIST state");
+                    None
+                }
+            })
+            .unwrap_or_default();
+        if config.nominal_v.is_some() {
+            state.nominal_v = config.nominal_v;
+        }
+
+        Self {
+            config,
+            state,
+            detect_votes: (0, 0),
+            open: None,
+            last_freq_ts: None,
+            last_saved: None,
+        }
+    }
+
+    pub fn report(&self) -> ProdistReport {
+        report_from(&self.state)
+    }
+
+    pub fn update(&mut self, snapshot: &mut Snapshot, on_battery: bool) {
+        let ts = snapshot.ts;
+
+        if let Some(v) = snapshot.raw_metric("vInput") {
+            self.observe_voltage(ts, v);
+            if let Some(nominal) = self.state.nominal_v {
+                let class = classify(nominal, v);
+                snapshot.vars.insert(
+                    "vInputNominal".to_string(),
+                    serde_json::Value::from(nominal),
+                );
+                snapshot.vars.insert(
+                    "vInputClass".to_string(),
+                    serde_json::Value::String(class.as_str().to_string()),
+                );
+            }
+        }
+
+        match snapshot.raw_metric("fOutput") {
+            Some(hz) if !on_battery => self.observe_frequency(ts, hz),
+            _ => self.last_freq_ts = None,
+        }
+
+        let report = self.report();
+        let vars = &mut snapshot.vars;
+        vars.insert("prodistDrpPct".to_string(), round3(report.drp_pct));
+        vars.insert("prodistDrcPct".to_string(), round3(report.drc_pct));
+        vars.insert(
+            "prodistIntervals".to_string(),
+            serde_json::Value::from(report.intervals as u64),
+        );
+        vars.insert(
+            "fOutOfRangeSec".to_string(),
+            round3(report.frequency.out_of_range_sec),
+        );
+
+        self.maybe_save(ts);
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(path) = &self.config.state_path {
+            if let Err(err) = save_json(path, &self.state) {
+                warn!(path=%path.display(), %err, "failed to persist PRODIST state");
+            }
+        }
+    }
+
+    fn observe_voltage(&mut self, ts: DateTime<Utc>, v: f64) {
+        if self.state.nominal_v.is_none() {
+            match detect_nominal(v) {
+                Some(n) if n > 200.0 => self.detect_votes.1 += 1,
+                Some(_) => self.detect_votes.0 += 1,
+                None => {}
+            }
+            let (low, high) = self.detect_votes;
+            if low + high >= NOMINAL_LOCK_SAMPLES {
+                self.state.nominal_v = Some(if high > low { 220.0 } else { 127.0 });
+            }
+        }
+
+        let start = interval_start(ts);
+        if self.open.as_ref().is_some_and(|open| open.start != start) {
+            if let Some(open) = self.open.take() {
+                self.close_interval(open);
+            }
+        }
+
+        if v < INTERRUPTION_BELOW_V {
+            return;
+        }
+        let open = self.open.get_or_insert(OpenInterval {
+            start,
+            sum_v: 0.0,
+            samples: 0,
+        });
+        open.sum_v += v;
+        open.samples += 1;
+    }
+
+    fn close_interval(&mut self, open: OpenInterval) {
+        let Some(nominal) = self.state.nominal_v else {
+            return;
+        };
+        if open.samples == 0 {
+            return;
+        }
+        let mean_v = open.sum_v / open.samples as f64;
+        self.state.intervals.push_back(IntervalReading {
+            start: open.start,
+            mean_v,
+            samples: open.samples,
+            class: classify(nominal, mean_v),
+        });
+        while self.state.intervals.len() > CAMPAIGN_WINDOWS {
+            self.state.intervals.pop_front();
+        }
+    }
+
+    fn observe_frequency(&mut self, ts: DateTime<Utc>, hz: f64) {
+        let freq = &mut self.state.frequency;
+        freq.samples += 1;
+        freq.min_hz = Some(freq.min_hz.map_or(hz, |m| m.min(hz)));
+        freq.max_hz = Some(freq.max_hz.map_or(hz, |m| m.max(hz)));
+        freq.max_deviation_hz = freq.max_deviation_hz.max((hz - 60.0).abs());
+
+        let out = hz < self.config.frequency_min_hz || hz > self.config.frequency_max_hz;
+        if out {
+            freq.out_of_range_samples += 1;
+            if let Some(prev) = self.last_freq_ts {
+                freq.out_of_range_sec += (ts - prev).num_milliseconds().max(0) as f64 / 1000.0;
+            }
+            self.last_freq_ts = Some(ts);
+        } else {
+            self.last_freq_ts = None;
+        }
+    }
+
+    fn maybe_save(&mut self, now: DateTime<Utc>) {
+        if self
+            .last_saved
+            .is_some_and(|saved| (now - saved).num_seconds() < SAVE_EVERY_SEC)
+        {
+            return;
+        }
+        self.last_saved = Some(now);
+        self.flush();
+    }
+}
+
+/// Builds the report from persisted state, e.g. for `nobreakd query`.
+pub fn report_from(state: &ProdistState) -> ProdistReport {
+    let count = |class| state.intervals.iter().filter(|i| i.class == class).count();
+    let precarious = count(VoltageClass::Precarious);
+    let critical = count(VoltageClass::Critical);
+    let total = state.intervals.len();
+    let pct = |n: usize| {
+        if total == 0 {
+            0.0
+        } else {
+            n as f64 / total as f64 * 100.0
+        }
+    };
+    let drp = pct(precarious);
+    let drc = pct(critical);
+
+    ProdistReport {
+        nominal_v: state.nominal_v,
+        intervals: total,
+        campaign_complete: total >= CAMPAIGN_WINDOWS,
+        precarious_intervals: precarious,
+        critical_intervals: critical,
+        drp_pct: drp,
+        drc_pct: drc,
+        drp_limit_pct: DRP_LIMIT_PCT,
+        drc_limit_pct: DRC_LIMIT_PCT,
+        drp_exceeded: drp > DRP_LIMIT_PCT,
+        drc_exceeded: drc > DRC_LIMIT_PCT,
+        first_interval: state.intervals.front().map(|i| i.start),
+        last_interval: state.intervals.back().map(|i| i.start),
+        frequency: state.frequency.clone(),
+    }
+}
+
+fn interval_start(ts: DateTime<Utc>) -> DateTime<Utc> {
+    let secs = ts.timestamp();
+    DateTime::from_timestamp(secs - secs.rem_euclid(WINDOW_SEC), 0).unwrap_or(ts)
+}
+
+fn round3(v: f64) -> serde_json::Value {
+    serde_json::Value::from((v * 1000.0).round() / 1000.0)
+}
@@ -0,0 +1,76 @@
+use crate::prodist::{classify, ProdistConfig, ProdistMonitor, VoltageClass};
+use crate::test_driver::snapshot_at;
+
+#[test]
+fn ranges_follow_module_8_tables() {
+    // Arrange
+    let cases = [
+        (127.0, 117.0, VoltageClass::Adequate),
+        (127.0, 116.9, VoltageClass::Precarious),
+        (127.0, 135.0, VoltageClass::Precarious),
+        (127.0, 135.5, VoltageClass::Critical),
+        (220.0, 231.0, VoltageClass::Adequate),
+        (220.0, 195.0, VoltageClass::Precarious),
+        (220.0, 190.0, VoltageClass::Critical),
+        (220.0, 0.0, VoltageClass::Interruption),
+    ];
+
+    // Act
+    let classes = cases
+        .iter()
+        .map(|(nominal, reading, _)| classify(*nominal, *reading))
+        .collect::<Vec<_>>();
+
+    // Assert
+    let expected = cases.iter().map(|(_, _, class)| *class).collect::<Vec<_>>();
+    assert_eq!(classes, expected);
+}
+
+#[test]
+fn nominal_is_detected_and_intervals_feed_drp_drc() {
+    // Arrange
+    let mut monitor = ProdistMonitor::new(ProdistConfig::default());
+
+    // Act: 30 min adequate, 10 min precarious, 10 min critical, at 1 sample/10 s.
+    let mut last = snapshot_at(0, true, &[]);
+    for step in 0..=300 {
+        let v = match step {
+            0..=179 => 220.0,
+            180..=239 => 198.0,
+            _ => 185.0,
+        };
+        last = snapshot_at(step * 10, true, &[("vInput", v), ("fOutput", 60.0)]);
+        monitor.update(&mut last, false);
+    }
+
+    // Assert
+    let report = monitor.report();
+    assert_eq!(report.nominal_v, Some(220.0));
+    assert_eq!(report.intervals, 5);
+    assert_eq!(report.precarious_intervals, 1);
+    assert_eq!(report.critical_intervals, 1);
+    assert_eq!(report.drp_pct, 20.0);
+    assert!(report.drc_exceeded);
+    assert_eq!(last.vars["vInputClass"], "critical");
+}
+
+#[test]
+fn frequency_out_of_band_time_is_accumulated() {
+    // Arrange
+    let mut monitor = ProdistMonitor::new(ProdistConfig {
+        nominal_v: Some(127.0),
+        ..ProdistConfig::default()
+    });
+
+    // Act
+    for (secs, hz) in [(0, 60.0), (1, 60.3), (2, 60.4), (3, 60.2), (4, 60.0)] {
+        let mut snapshot = snapshot_at(secs, true, &[("vInput", 127.0), ("fOutput", hz)]);
+        monitor.update(&mut snapshot, false);
+    }
+
+    // Assert
+    let freq = monitor.report().frequency;
+    assert_eq!(freq.out_of_range_samples, 3);
+    assert_eq!(freq.out_of_range_sec, 2.0);
+    assert!((freq.max_deviation_hz - 0.4).abs() < 1e-9);
+}
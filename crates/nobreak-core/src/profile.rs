@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+
+/// Static facts about the UPS model that analysers need but the device does
+/// not report. Defaults describe the RagTech 3200VA; every field can be
+/// overridden from configuration when the unit or its battery bank differs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelProfile {
+    pub name: String,
+    pub rated_va: f64,
+    pub rated_w: f64,
+    pub battery: BatteryModel,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BatteryModel {
+    pub nominal_v: f64,
+    /// Full-charge runtime by output load, as `(load_pct, minutes)` points
+    /// sorted by load. Values between points are linearly interpolated.
+    pub runtime_curve: Vec<(f64, f64)>,
+}
+
+impl Default for ModelProfile {
+    fn default() -> Self {
+        Self::ragtech_3200va()
+    }
+}
+
+impl Default for BatteryModel {
+    fn default() -> Self {
+        ModelProfile::ragtech_3200va().battery
+    }
+}
+
+impl ModelProfile {
+    /// Approximate figures for a stock 3200VA unit; the runtime curve is a
+    /// conservative starting point meant to be replaced with the installed
+    /// bank's measured behaviour.
+    pub fn ragtech_3200va() -> Self {
+        Self {
+            name: "RagTech 3200VA".to_string(),
+            rated_va: 3200.0,
+            rated_w: 2240.0,
+            battery: BatteryModel {
+                nominal_v: 48.0,
+                runtime_curve: vec![
+                    (10.0, 60.0),
+                    (25.0, 28.0),
+                    (50.0, 12.0),
+                    (75.0, 7.0),
+                    (100.0, 4.0),
+                ],
+            },
+        }
+    }
+}
+
+impl BatteryModel {
+    /// Full-charge runtime in seconds at `load_pct`, clamped to the curve ends.
+    pub fn full_runtime_sec(&self, load_pct: f64) -> Option<f64> {
+        let curve = &self.runtime_curve;
+        let (first, last) = (curve.first()?, curve.last()?);
+        if load_pct <= first.0 {
+            return Some(first.1 * 60.0);
+        }
+        if load_pct >= last.0 {
+            return Some(last.1 * 60.0);
+        }
+        curve.windows(2).find_map(|pair| {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            if load_pct < x0 || load_pct > x1 || x1 <= x0 {
+                return None;
+            }
+            let t = (load_pct - x0) / (x1 - x0);
+            Some((y0 + t * (y1 - y0)) * 60.0)
+        })
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+
+use crate::profile::BatteryModel;
+use crate::snapshot::Snapshot;
+
+/// Charge history shorter than this is too noisy to fit a discharge rate.
+const MIN_FIT_SPAN_SEC: f64 = 60.0;
+/// Span of live discharge data after which the live rate gets full weight.
+const FULL_WEIGHT_SPAN_SEC: f64 = 300.0;
+/// Relative half-width of the band for a model-only estimate.
+const MODEL_BAND: f64 = 0.4;
+/// Relative half-width of the band once live data has full weight.
+const LIVE_BAND: f64 = 0.15;
+
+/// Minutes-remaining estimate combining the battery model's runtime curve
+/// with the discharge rate observed during the current on-battery episode.
+///
+/// Publishes `runtimeRemainingSec` with `runtimeRemainingSecLow`/`High` as a
+/// confidence band and `runtimeSource` (`model` or `blended`).
+#[derive(Debug, Clone)]
+pub struct RuntimeEstimator {
+    model: BatteryModel,
+    discharge: Vec<(DateTime<Utc>, f64)>,
+}
+
+impl RuntimeEstimator {
+    pub fn new(model: BatteryModel) -> Self {
+        Self {
+            model,
+            discharge: Vec::new(),
+        }
+    }
+
+    pub fn set_model(&mut self, model: BatteryModel) {
+        self.model = model;
+    }
+
+    pub fn update(&mut self, snapshot: &mut Snapshot, on_battery: bool) {
+        if !on_battery {
+            self.discharge.clear();
+        }
+
+        let (Some(load), Some(charge)) = (snapshot.metric("pOutput"), snapshot.metric("cBattery"))
+        else {
+            return;
+        };
+        let charge = charge.clamp(0.0, 100.0);
+
+        if on_battery {
+            self.discharge.push((snapshot.ts, charge));
+        }
+
+        let Some(full) = self.model.full_runtime_sec(load) else {
+            return;
+        };
+        let model_sec = full * charge / 100.0;
+
+        let (estimate, band, source) = match self.live_estimate(charge) {
+            Some((live_sec, span)) => {
+                let weight = (span / FULL_WEIGHT_SPAN_SEC).min(1.0);
+                let estimate = weight * live_sec + (1.0 - weight) * model_sec;
+                let band = MODEL_BAND - weight * (MODEL_BAND - LIVE_BAND);
+                (estimate, band, "blended")
+            }
+            None => (model_sec, MODEL_BAND, "model"),
+        };
+
+        insert_runtime(&mut snapshot.vars, estimate, band, source);
+    }
+
+    /// Seconds to empty from a least-squares fit of charge over time, with
+    /// the span of data it was fitted on.
+    fn live_estimate(&self, charge: f64) -> Option<(f64, f64)> {
+        let (t0, _) = *self.discharge.first()?;
+        let (t_last, _) = *self.discharge.last()?;
+        let span = (t_last - t0).num_milliseconds() as f64 / 1000.0;
+        if span < MIN_FIT_SPAN_SEC {
+            return None;
+        }
+
+        let n = self.discharge.len() as f64;
+        let points = self
+            .discharge
+            .iter()
+            .map(|(ts, c)| ((*ts - t0).num_milliseconds() as f64 / 1000.0, *c));
+        let (sx, sy, sxx, sxy) = points.fold((0.0, 0.0, 0.0, 0.0), |(sx, sy, sxx, sxy), (x, y)| {
+            (sx + x, sy + y, sxx + x * x, sxy + x * y)
+        });
+        let denom = n * sxx - sx * sx;
+        if denom <= f64::EPSILON {
+            return None;
+        }
+        let slope = (n * sxy - sx * sy) / denom;
+        if slope >= 0.0 {
+            return None;
+        }
+
+        Some((charge / -slope, span))
+    }
+}
+
+fn insert_runtime(
+    vars: &mut BTreeMap<String, serde_json::Value>,
+    estimate: f64,
+    band: f64,
+    source: &str,
+) {
+    let round = |v: f64| serde_json::Value::from(v.max(0.0).round());
+    vars.insert("runtimeRemainingSec".to_string(), round(estimate));
+    vars.insert(
+        "runtimeRemainingSecLow".to_string(),
+        round(estimate * (1.0 - band)),
+    );
+    vars.insert(
+        "runtimeRemainingSecHigh".to_string(),
+        round(estimate * (1.0 + band)),
+    );
+    vars.insert(
+        "runtimeSource".to_string(),
+        serde_json::Value::String(source.to_string()),
+    );
+}
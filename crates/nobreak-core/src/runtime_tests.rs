@@ -0,0 +1,57 @@
+use crate::profile::ModelProfile;
+use crate::runtime::RuntimeEstimator;
+use crate::test_driver::snapshot_at;
+
+fn var(snapshot: &crate::snapshot::Snapshot, key: &str) -> f64 {
+    snapshot.vars[key].as_f64().expect("numeric var")
+}
+
+#[test]
+fn curve_interpolates_between_load_points() {
+    // Arrange
+    let model = ModelProfile::ragtech_3200va().battery;
+
+    // Act
+    let mid = model.full_runtime_sec(37.5);
+    let beyond = model.full_runtime_sec(150.0);
+
+    // Assert
+    assert_eq!(mid, Some(20.0 * 60.0));
+    assert_eq!(beyond, Some(4.0 * 60.0));
+}
+
+#[test]
+fn on_mains_publishes_model_estimate_with_wide_band() {
+    // Arrange
+    let mut estimator = RuntimeEstimator::new(ModelProfile::ragtech_3200va().battery);
+    let mut snapshot = snapshot_at(0, true, &[("pOutput", 50.0), ("cBattery", 50.0)]);
+
+    // Act
+    estimator.update(&mut snapshot, false);
+
+    // Assert
+    assert_eq!(var(&snapshot, "runtimeRemainingSec"), 360.0);
+    assert_eq!(var(&snapshot, "runtimeRemainingSecLow"), 216.0);
+    assert_eq!(var(&snapshot, "runtimeRemainingSecHigh"), 504.0);
+    assert_eq!(snapshot.vars["runtimeSource"], "model");
+}
+
+#[test]
+fn live_discharge_rate_takes_over_on_battery() {
+    // Arrange
+    let mut estimator = RuntimeEstimator::new(ModelProfile::ragtech_3200va().battery);
+    let mut last = snapshot_at(0, true, &[]);
+
+    // Act: 1% charge per 10 s at a load the model thinks lasts much longer.
+    for step in 0..=30 {
+        let charge = 100.0 - step as f64;
+        last = snapshot_at(step * 10, true, &[("pOutput", 10.0), ("cBattery", charge)]);
+        estimator.update(&mut last, true);
+    }
+
+    // Assert
+    assert_eq!(last.vars["runtimeSource"], "blended");
+    assert_eq!(var(&last, "runtimeRemainingSec"), 700.0);
+    let band = var(&last, "runtimeRemainingSecHigh") - var(&last, "runtimeRemainingSec");
+    assert_eq!(band, 105.0);
+}
@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::time::{sleep_until, Instant};
+
+/// Why the sampling interval last changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntervalReason {
+    /// Still at `sample_interval`.
+    #[default]
+    Configured,
+    /// A slow read backed the interval off.
+    SlowRead,
+    /// A failed read backed the interval off.
+    ReadError,
+    /// A run of fast reads tightened the interval.
+    FastReads,
+    /// On battery: pinned to `sample_interval_min`.
+    OnBattery,
+    /// A power event is in progress: pinned to `sample_interval_min`.
+    ActiveEvent,
+    /// Stable on mains for `stable_after`: relaxed to `sample_interval_max`.
+    StableOnMains,
+}
+
+/// Fixed-grid tick source on the monotonic clock.
+///
+/// Slots are `anchor + n * period`, so the time spent reading never pushes
+/// later ticks back. The caller awaits the read before asking for the next
+/// slot, which keeps at most one read in flight; slots that pass while a read
+/// is still running are skipped and counted rather than fired in a burst.
+#[derive(Debug, Clone)]
+pub struct TickScheduler {
+    period: Duration,
+    next: Option<Instant>,
+    skipped: u64,
+    late: u64,
+}
+
+/// Outcome of one scheduled slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tick {
+    pub scheduled: Instant,
+    pub lateness: Duration,
+    pub skipped: u64,
+}
+
+impl TickScheduler {
+    pub fn new(period: Duration) -> Self {
+        Self {
+            period: period.max(Duration::from_millis(1)),
+            next: None,
+            skipped: 0,
+            late: 0,
+        }
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Changes the grid spacing, keeping the slot already handed out as the
+    /// new anchor so a period change does not reset the phase.
+    pub fn set_period(&mut self, period: Duration) {
+        let period = period.max(Duration::from_millis(1));
+        if period == self.period {
+            return;
+        }
+        if let Some(next) = self.next {
+            self.next = Some(next - self.period + period);
+        }
+        self.period = period;
+    }
+
+    /// Slots lost because a previous read overran them.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Ticks that fired more than a tenth of a period after their slot.
+    pub fn late(&self) -> u64 {
+        self.late
+    }
+
+    pub async fn wait(&mut self) -> Tick {
+        if let Some(next) = self.next {
+            sleep_until(next).await;
+        }
+        self.advance(Instant::now())
+    }
+
+    fn advance(&mut self, now: Instant) -> Tick {
+        let scheduled = self.next.unwrap_or(now);
+        let lateness = now.saturating_duration_since(scheduled);
+        let missed = (lateness.as_nanos() / self.period.as_nanos()) as u64;
+
+        self.skipped += missed;
+        if lateness > self.period / 10 {
+            self.late += 1;
+        }
+
+        let slots = u32::try_from(missed + 1).unwrap_or(u32::MAX);
+        self.next = Some(scheduled + self.period * slots);
+
+        Tick {
+            scheduled,
+            lateness,
+            skipped: missed,
+        }
+    }
+}
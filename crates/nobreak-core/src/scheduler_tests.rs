@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use tokio::time::{advance, Instant};
+
+use crate::config::MonitorConfig;
+use crate::monitor::Monitor;
+use crate::scheduler::TickScheduler;
+use crate::test_driver::{ok_read, ScriptedDriver};
+
+#[tokio::test(start_paused = true)]
+async fn grid_does_not_drift_with_read_duration() {
+    // Arrange
+    let start = Instant::now();
+    let mut scheduler = TickScheduler::new(Duration::from_secs(1));
+
+    // Act
+    let mut offsets = Vec::new();
+    for _ in 0..4 {
+        let tick = scheduler.wait().await;
+        offsets.push(tick.scheduled.duration_since(start).as_millis());
+        advance(Duration::from_millis(300)).await;
+    }
+
+    // Assert
+    assert_eq!(offsets, [0, 1000, 2000, 3000]);
+    assert_eq!(scheduler.skipped(), 0);
+    assert_eq!(scheduler.late(), 0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn overrunning_read_skips_slots_instead_of_bursting() {
+    // Arrange
+    let start = Instant::now();
+    let mut scheduler = TickScheduler::new(Duration::from_secs(1));
+    scheduler.wait().await;
+
+    // Act
+    advance(Duration::from_millis(2500)).await;
+    let tick = scheduler.wait().await;
+    let following = scheduler.wait().await;
+
+    // Assert
+    assert_eq!(tick.skipped, 1);
+    assert_eq!(tick.scheduled.duration_since(start).as_millis(), 1000);
+    assert_eq!(following.scheduled.duration_since(start).as_millis(), 3000);
+    assert_eq!(scheduler.skipped(), 1);
+    assert_eq!(scheduler.late(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn period_change_keeps_phase_of_pending_slot() {
+    // Arrange
+    let start = Instant::now();
+    let mut scheduler = TickScheduler::new(Duration::from_secs(1));
+    scheduler.wait().await;
+
+    // Act
+    scheduler.set_period(Duration::from_secs(3));
+    let tick = scheduler.wait().await;
+
+    // Assert
+    assert_eq!(tick.scheduled.duration_since(start).as_millis(), 3000);
+}
+
+#[tokio::test(start_paused = true)]
+async fn monitor_reports_skipped_ticks_in_quality() {
+    // Arrange
+    let config = MonitorConfig {
+        auto_tune: false,
+        ..MonitorConfig::default()
+    };
+    let reads = (0..3).map(|_| ok_read(&[("vInput", 127.0)])).collect();
+    let mut monitor = Monitor::new(ScriptedDriver::new(reads), config, None);
+
+    // Act
+    monitor.next_snapshot().await;
+    advance(Duration::from_millis(3200)).await;
+    let snapshot = monitor.next_snapshot().await;
+
+    // Assert
+    assert_eq!(snapshot.quality.ticks_skipped, 2);
+    assert_eq!(snapshot.quality.ticks_late, 1);
+}
@@ -0,0 +1,122 @@
+use tokio::sync::{broadcast, watch};
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+use tracing::warn;
+
+use crate::config::MonitorConfig;
+use crate::driver::UpsDriver;
+use crate::monitor::Monitor;
+use crate::snapshot::Snapshot;
+
+const BROADCAST_CAPACITY: usize = 64;
+
+/// Owns the single [`Monitor`] in a background task and fans its snapshots
+/// out to any number of sinks.
+///
+/// Sinks never call `tick()` themselves, so one process can print, export
+/// and serve the same sample stream while holding the device only once.
+pub struct MonitorService {
+    feed: SnapshotFeed,
+    stop: watch::Sender<bool>,
+    config: watch::Sender<Option<MonitorConfig>>,
+    task: JoinHandle<()>,
+}
+
+/// Cheap, cloneable subscription point handed to each sink.
+#[derive(Clone)]
+pub struct SnapshotFeed {
+    latest: watch::Receiver<Option<Snapshot>>,
+    snapshots: broadcast::Sender<Snapshot>,
+}
+
+impl MonitorService {
+    pub fn spawn<D: UpsDriver + 'static>(mut monitor: Monitor<D>) -> Self {
+        let (latest_tx, latest_rx) = watch::channel(None);
+        let (snapshots, _) = broadcast::channel(BROADCAST_CAPACITY);
+        let (stop, mut stop_rx) = watch::channel(false);
+        let (config, mut config_rx) = watch::channel(None::<MonitorConfig>);
+
+        let publisher = snapshots.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                // Applied between ticks so a reload never interrupts a read.
+                if config_rx.has_changed().unwrap_or(false) {
+                    if let Some(config) = config_rx.borrow_and_update().clone() {
+                        monitor.reconfigure(config);
+                    }
+                }
+                // Stop is only honoured between ticks: a read that has
+                // started finishes (within `poll_timeout`) and is published.
+                tokio::select! {
+                    biased;
+                    _ = stop_rx.changed() => break,
+                    _ = monitor.wait_for_slot() => {}
+                }
+                let snapshot = monitor.tick().await;
+                latest_tx.send_replace(Some(snapshot.clone()));
+                let _ = publisher.send(snapshot);
+            }
+            monitor.close().await;
+        });
+
+        Self {
+            feed: SnapshotFeed {
+                latest: latest_rx,
+                snapshots,
+            },
+            stop,
+            config,
+            task,
+        }
+    }
+
+    pub fn feed(&self) -> SnapshotFeed {
+        self.feed.clone()
+    }
+
+    /// Hands a new configuration to the running monitor. A tick already
+    /// waiting on its slot completes with the old settings; the new ones
+    /// apply from the following tick, without reconnecting the device.
+    pub fn reconfigure(&self, config: MonitorConfig) {
+        self.config.send_replace(Some(config));
+    }
+
+    /// Stops the tick loop and waits for the driver to be released. A read
+    /// already in flight completes and is published first.
+    pub async fn shutdown(self) {
+        let _ = self.stop.send(true);
+        if let Err(err) = self.task.await {
+            warn!(%err, "monitor task ended abnormally");
+        }
+    }
+}
+
+impl SnapshotFeed {
+    pub fn latest(&self) -> Option<Snapshot> {
+        self.latest.borrow().clone()
+    }
+
+    /// Receiver that always holds the most recent snapshot; suited to sinks
+    /// that only care about current state.
+    pub fn watch(&self) -> watch::Receiver<Option<Snapshot>> {
+        self.latest.clone()
+    }
+
+    /// Receiver that sees every snapshot, in order, from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Snapshot> {
+        self.snapshots.subscribe()
+    }
+
+    /// Every snapshot from now on as a `Stream`. A subscriber that falls more
+    /// than the channel capacity behind skips the missed snapshots.
+    pub fn stream(&self) -> impl Stream<Item = Snapshot> + Send + Unpin + 'static {
+        BroadcastStream::new(self.subscribe()).filter_map(|item| match item {
+            Ok(snapshot) => Some(snapshot),
+            Err(err) => {
+                warn!(%err, "snapshot subscriber lagged");
+                None
+            }
+        })
+    }
+}
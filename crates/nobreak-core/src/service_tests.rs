@@ -0,0 +1,124 @@
+use std::time::Duration;
+
+use tokio_stream::StreamExt;
+
+use crate::config::MonitorConfig;
+use crate::events::PowerEventKind;
+use crate::monitor::Monitor;
+use crate::scheduler::IntervalReason;
+use crate::service::MonitorService;
+use crate::test_driver::{ok_read, ScriptedDriver};
+
+fn service(reads: usize) -> MonitorService {
+    let config = MonitorConfig {
+        auto_tune: false,
+        ..MonitorConfig::default()
+    };
+    let reads = (0..reads)
+        .map(|idx| ok_read(&[("vInput", 120.0 + idx as f64)]))
+        .collect();
+    MonitorService::spawn(Monitor::new(ScriptedDriver::new(reads), config, None))
+}
+
+#[tokio::test(start_paused = true)]
+async fn every_subscriber_sees_the_same_snapshots() {
+    // Arrange
+    let service = service(3);
+    let feed = service.feed();
+    let mut first = feed.stream();
+    let mut second = feed.stream();
+
+    // Act
+    let a = first.next().await.expect("first stream");
+    let b = second.next().await.expect("second stream");
+    let a_next = first.next().await.expect("first stream");
+
+    // Assert
+    assert_eq!(a.vars["vInput"], b.vars["vInput"]);
+    assert_eq!(a_next.vars["vInput"].as_f64(), Some(121.0));
+    assert_eq!(
+        a_next.mono_ms - a.mono_ms,
+        1000,
+        "service ticks on the sampling grid"
+    );
+
+    service.shutdown().await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn latest_is_available_to_late_subscribers() {
+    // Arrange
+    let service = service(1);
+    let feed = service.feed();
+    let mut watch = feed.watch();
+
+    // Act
+    watch.changed().await.expect("first snapshot");
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    // Assert
+    let latest = feed.latest().expect("latest snapshot");
+    assert_eq!(latest.vars["vInput"].as_f64(), Some(120.0));
+
+    service.shutdown().await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn reconfigure_applies_between_ticks_without_reconnecting() {
+    // Arrange
+    let service = service(4);
+    let mut snapshots = service.feed().stream();
+    let first = snapshots.next().await.expect("first snapshot");
+    let mut config = MonitorConfig {
+        auto_tune: false,
+        power_aware_sampling: false,
+        sample_interval: Duration::from_secs(2),
+        ..MonitorConfig::default()
+    };
+    config.events.brownout_below_v = Some(125.0);
+
+    // Act
+    service.reconfigure(config);
+    let _in_flight = snapshots.next().await.expect("second snapshot");
+    let third = snapshots.next().await.expect("third snapshot");
+    let fourth = snapshots.next().await.expect("fourth snapshot");
+
+    // Assert
+    assert!(third
+        .events
+        .iter()
+        .any(|e| e.kind == PowerEventKind::Brownout));
+    assert_eq!(fourth.mono_ms - third.mono_ms, 2000);
+    assert_eq!(third.quality.interval_reason, IntervalReason::Configured);
+    assert_eq!(fourth.quality.reconnects, 0);
+    assert!(fourth.device.connected);
+    assert_eq!(first.device.id, fourth.device.id);
+
+    service.shutdown().await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn shutdown_lets_the_read_in_flight_finish() {
+    // Arrange
+    let config = MonitorConfig {
+        auto_tune: false,
+        ..MonitorConfig::default()
+    };
+    let mut driver = ScriptedDriver::new(vec![
+        ok_read(&[("vInput", 120.0)]),
+        ok_read(&[("vInput", 121.0)]),
+    ]);
+    driver.read_delay = Duration::from_millis(300);
+    let service = MonitorService::spawn(Monitor::new(driver, config, None));
+    let mut snapshots = service.feed().stream();
+    snapshots.next().await.expect("first snapshot");
+
+    // Act
+    tokio::time::sleep(Duration::from_millis(900)).await;
+    service.shutdown().await;
+    let in_flight = snapshots.next().await.expect("read in flight at stop");
+
+    // Assert
+    assert_eq!(in_flight.vars["vInput"].as_f64(), Some(121.0));
+    assert!(snapshots.next().await.is_none(), "no tick after stop");
+}
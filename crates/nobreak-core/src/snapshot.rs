@@ -3,6 +3,13 @@ use std::collections::BTreeMap;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::alerts::AlertRecord;
+use crate::events::PowerEvent;
+use crate::plausibility::Validity;
+use crate::pq_stats::PowerQualityDay;
+use crate::scheduler::IntervalReason;
+use crate::stats::QualityWindow;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Snapshot {
     pub ts: DateTime<Utc>,
@@ -11,6 +18,19 @@ pub struct Snapshot {
     pub freshness: Freshness,
     pub status: MonitorStatus,
     pub vars: BTreeMap<String, serde_json::Value>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vars_meta: BTreeMap<String, VarMeta>,
+    /// Plausibility verdict for each checked metric of this read.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub validity: BTreeMap<String, Validity>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<PowerEvent>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alerts: Vec<AlertRecord>,
+    /// Power-quality statistics of the local day that just ended; only on
+    /// the first snapshot after midnight.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_report: Option<PowerQualityDay>,
     pub quality: SnapshotQuality,
 }
 
@@ -45,6 +65,13 @@ pub struct MonitorStatus {
     pub failures: Vec<String>,
 }
 
+/// Per-var provenance, present only for vars carried over from an earlier read.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VarMeta {
+    pub age_ms: u128,
+    pub held: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SnapshotQuality {
     pub poll_ms: u128,
@@ -53,4 +80,43 @@ pub struct SnapshotQuality {
     pub reads_err: u64,
     pub reconnects: u64,
     pub effective_interval_ms: u128,
+    #[serde(default)]
+    pub interval_reason: IntervalReason,
+    #[serde(default)]
+    pub interval_changes: u64,
+    #[serde(default)]
+    pub ticks_skipped: u64,
+    #[serde(default)]
+    pub ticks_late: u64,
+    #[serde(default)]
+    pub windows: Vec<QualityWindow>,
+}
+
+impl Snapshot {
+    /// Numeric view of a var or a monitor field, for rule evaluation and
+    /// metric export. Held vars are reported as missing.
+    pub fn metric(&self, name: &str) -> Option<f64> {
+        let flag = |b: bool| if b { 1.0 } else { 0.0 };
+        match name {
+            "connected" => Some(flag(self.device.connected)),
+            "stale" => Some(flag(self.freshness.stale)),
+            "age_ms" => Some(self.freshness.age_ms as f64),
+            "rtt_ms" => Some(self.freshness.rtt_ms as f64),
+            "reads_ok" => Some(self.quality.reads_ok as f64),
+            "reads_err" => Some(self.quality.reads_err as f64),
+            "reconnects" => Some(self.quality.reconnects as f64),
+            "ticks_skipped" => Some(self.quality.ticks_skipped as f64),
+            "ticks_late" => Some(self.quality.ticks_late as f64),
+            _ if self.vars_meta.contains_key(name) => None,
+            _ => self.vars.get(name).and_then(|v| v.as_f64()),
+        }
+    }
+
+    /// Like [`Snapshot::metric`], but reads `<name>Raw` when a smoothing
+    /// filter replaced the var, so power events and power-quality
+    /// statistics see the actual waveform.
+    pub fn raw_metric(&self, name: &str) -> Option<f64> {
+        self.metric(&format!("{name}Raw"))
+            .or_else(|| self.metric(name))
+    }
 }
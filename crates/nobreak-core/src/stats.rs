@@ -0,0 +1,132 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+/// Read outcomes over the longest configured window, summarised per window
+/// on demand.
+#[derive(Debug, Clone)]
+pub struct RollingStats {
+    windows: Vec<Duration>,
+    horizon: Duration,
+    samples: VecDeque<ReadSample>,
+    last_ok_before_horizon: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ReadSample {
+    at: Instant,
+    ok: bool,
+    rtt_ms: u128,
+}
+
+/// Summary of one rolling window as published in `SnapshotQuality`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QualityWindow {
+    pub window_sec: u64,
+    pub reads: u64,
+    pub success_rate: Option<f64>,
+    pub rtt_p50_ms: Option<u128>,
+    pub rtt_p95_ms: Option<u128>,
+    pub rtt_p99_ms: Option<u128>,
+    pub max_gap_ms: u128,
+}
+
+impl RollingStats {
+    pub fn new(windows: &[Duration]) -> Self {
+        let mut windows = windows.to_vec();
+        windows.sort();
+        windows.dedup();
+        let horizon = windows.last().copied().unwrap_or_default();
+
+        Self {
+            windows,
+            horizon,
+            samples: VecDeque::new(),
+            last_ok_before_horizon: None,
+        }
+    }
+
+    pub fn record(&mut self, at: Instant, ok: bool, rtt: Duration) {
+        self.samples.push_back(ReadSample {
+            at,
+            ok,
+            rtt_ms: rtt.as_millis(),
+        });
+
+        while let Some(front) = self.samples.front() {
+            if at.saturating_duration_since(front.at) <= self.horizon {
+                break;
+            }
+            if front.ok {
+                self.last_ok_before_horizon = Some(front.at);
+            }
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn summarize(&self, now: Instant) -> Vec<QualityWindow> {
+        self.windows
+            .iter()
+            .map(|window| self.summarize_window(now, *window))
+            .collect()
+    }
+
+    fn summarize_window(&self, now: Instant, window: Duration) -> QualityWindow {
+        let start = now.checked_sub(window).unwrap_or(now);
+        let in_window = self.samples.iter().filter(|s| s.at >= start);
+
+        let mut reads = 0_u64;
+        let mut rtts = Vec::new();
+        let mut max_gap = Duration::ZERO;
+        let mut previous_ok = self.last_ok_at_or_before(start);
+
+        for sample in in_window {
+            reads += 1;
+            if !sample.ok {
+                continue;
+            }
+            rtts.push(sample.rtt_ms);
+            if let Some(prev) = previous_ok {
+                max_gap = max_gap.max(sample.at.saturating_duration_since(prev.max(start)));
+            }
+            previous_ok = Some(sample.at);
+        }
+
+        // An outage still in progress counts as a gap up to now.
+        if let Some(prev) = previous_ok {
+            max_gap = max_gap.max(now.saturating_duration_since(prev.max(start)));
+        }
+
+        rtts.sort_unstable();
+
+        QualityWindow {
+            window_sec: window.as_secs(),
+            reads,
+            success_rate: (reads > 0).then(|| rtts.len() as f64 / reads as f64),
+            rtt_p50_ms: percentile(&rtts, 50.0),
+            rtt_p95_ms: percentile(&rtts, 95.0),
+            rtt_p99_ms: percentile(&rtts, 99.0),
+            max_gap_ms: max_gap.as_millis(),
+        }
+    }
+
+    fn last_ok_at_or_before(&self, at: Instant) -> Option<Instant> {
+        self.samples
+            .iter()
+            .rev()
+            .find(|s| s.ok && s.at < at)
+            .map(|s| s.at)
+            .or(self.last_ok_before_horizon)
+    }
+}
+
+/// Nearest-rank percentile over an already sorted slice.
+fn percentile(sorted: &[u128], pct: f64) -> Option<u128> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::stats::RollingStats;
+
+fn secs(n: u64) -> Duration {
+    Duration::from_secs(n)
+}
+
+#[test]
+fn windows_only_count_their_own_samples() {
+    // Arrange
+    let t0 = Instant::now();
+    let mut stats = RollingStats::new(&[secs(60), secs(300)]);
+    for i in 0..300 {
+        let ok = i >= 100;
+        stats.record(t0 + secs(i), ok, Duration::from_millis(100 + i % 10));
+    }
+
+    // Act
+    let summary = stats.summarize(t0 + secs(299));
+
+    // Assert
+    assert_eq!(summary[0].window_sec, 60);
+    assert_eq!(summary[0].reads, 61);
+    assert_eq!(summary[0].success_rate, Some(1.0));
+    assert_eq!(summary[1].reads, 300);
+    assert_eq!(summary[1].success_rate, Some(200.0 / 300.0));
+    assert_eq!(summary[1].rtt_p50_ms, Some(104));
+    assert_eq!(summary[1].rtt_p99_ms, Some(109));
+}
+
+#[test]
+fn max_gap_includes_outage_still_in_progress() {
+    // Arrange
+    let t0 = Instant::now();
+    let mut stats = RollingStats::new(&[secs(60)]);
+    stats.record(t0, true, Duration::from_millis(100));
+    stats.record(t0 + secs(1), true, Duration::from_millis(100));
+    for i in 2..12 {
+        stats.record(t0 + secs(i), false, Duration::ZERO);
+    }
+
+    // Act
+    let summary = stats.summarize(t0 + secs(12));
+
+    // Assert
+    assert_eq!(summary[0].max_gap_ms, 11_000);
+    assert_eq!(summary[0].rtt_p95_ms, Some(100));
+}
+
+#[test]
+fn samples_older_than_longest_window_are_dropped() {
+    // Arrange
+    let t0 = Instant::now();
+    let mut stats = RollingStats::new(&[secs(60)]);
+    stats.record(t0, false, Duration::ZERO);
+
+    // Act
+    stats.record(t0 + secs(120), true, Duration::from_millis(50));
+    let summary = stats.summarize(t0 + secs(120));
+
+    // Assert
+    assert_eq!(summary[0].reads, 1);
+    assert_eq!(summary[0].success_rate, Some(1.0));
+}
@@ -0,0 +1,156 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+
+use crate::driver::{DeviceInfo, DriverError, ReadResult, UpsDriver};
+use crate::scheduler::IntervalReason;
+use crate::snapshot::{
+    Freshness, MonitorStatus, Snapshot, SnapshotDevice, SnapshotQuality, Transport,
+};
+
+/// Driver double that replays a fixed script of read outcomes.
+///
+/// Once the script is exhausted every further read fails with `Timeout`.
+pub(crate) struct ScriptedDriver {
+    reads: VecDeque<Result<ReadResult, DriverError>>,
+    connected: Option<DeviceInfo>,
+    pub(crate) connects: u32,
+    pub(crate) disconnects: u32,
+    /// How long each read takes, on the tokio clock.
+    pub(crate) read_delay: Duration,
+}
+
+impl ScriptedDriver {
+    pub(crate) fn new(reads: Vec<Result<ReadResult, DriverError>>) -> Self {
+        Self {
+            reads: reads.into(),
+            connected: None,
+            connects: 0,
+            disconnects: 0,
+            read_delay: Duration::ZERO,
+        }
+    }
+
+    pub(crate) fn device() -> DeviceInfo {
+        DeviceInfo {
+            id: "cdc:/dev/ttyACM0".to_string(),
+            model: "RagTech 3200VA".to_string(),
+            transport: "cdc".to_string(),
+            path: "/dev/ttyACM0".to_string(),
+            vid: "04d8".to_string(),
+            pid: "000a".to_string(),
+        }
+    }
+}
+
+pub(crate) fn ok_read(vars: &[(&str, f64)]) -> Result<ReadResult, DriverError> {
+    Ok(ReadResult {
+        status_code: "ONLINE_RAW".to_string(),
+        failures: Vec::new(),
+        vars: vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), serde_json::Value::from(*v)))
+            .collect::<BTreeMap<_, _>>(),
+    })
+}
+
+pub(crate) fn failed_read() -> Result<ReadResult, DriverError> {
+    Err(DriverError::Io("serial read failed".to_string()))
+}
+
+#[async_trait]
+impl UpsDriver for ScriptedDriver {
+    async fn discover(&mut self) -> Result<Vec<DeviceInfo>, DriverError> {
+        Ok(vec![Self::device()])
+    }
+
+    async fn connect(&mut self, _preferred_id: Option<&str>) -> Result<DeviceInfo, DriverError> {
+        self.connects += 1;
+        self.connected = Some(Self::device());
+        Ok(Self::device())
+    }
+
+    async fn read(&mut self) -> Result<ReadResult, DriverError> {
+        if !self.read_delay.is_zero() {
+            tokio::time::sleep(self.read_delay).await;
+        }
+        self.reads.pop_front().unwrap_or(Err(DriverError::Timeout))
+    }
+
+    async fn disconnect(&mut self) -> Result<(), DriverError> {
+        self.disconnects += 1;
+        self.connected = None;
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected.is_some()
+    }
+
+    fn current_device(&self) -> Option<DeviceInfo> {
+        self.connected.clone()
+    }
+}
+
+/// Minimal snapshot for feeding the per-snapshot analysers directly.
+pub(crate) fn snapshot_at(secs: i64, connected: bool, vars: &[(&str, f64)]) -> Snapshot {
+    let ts = Utc
+        .timestamp_opt(1_771_200_000 + secs, 0)
+        .single()
+        .expect("valid ts");
+    let device = ScriptedDriver::device();
+    Snapshot {
+        ts,
+        mono_ms: (secs * 1000) as u128,
+        device: SnapshotDevice {
+            id: device.id,
+            model: device.model,
+            transport: Transport {
+                kind: device.transport,
+                path: device.path,
+                vid: device.vid,
+                pid: device.pid,
+            },
+            connected,
+        },
+        freshness: Freshness {
+            rtt_ms: 100,
+            age_ms: 0,
+            stale: !connected,
+            last_ok_ts: Some(ts),
+        },
+        status: MonitorStatus {
+            code: if connected {
+                "ONLINE_RAW"
+            } else {
+                "DISCONNECTED"
+            }
+            .to_string(),
+            failures: Vec::new(),
+        },
+        vars: vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), serde_json::Value::from(*v)))
+            .collect(),
+        vars_meta: BTreeMap::new(),
+        validity: BTreeMap::new(),
+        events: Vec::new(),
+        daily_report: None,
+        alerts: Vec::new(),
+        quality: SnapshotQuality {
+            poll_ms: 100,
+            stale_seconds: 0.0,
+            reads_ok: 0,
+            reads_err: 0,
+            reconnects: 0,
+            effective_interval_ms: 1000,
+            interval_reason: IntervalReason::Configured,
+            interval_changes: 0,
+            ticks_skipped: 0,
+            ticks_late: 0,
+            windows: Vec::new(),
+        },
+    }
+}